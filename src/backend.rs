@@ -0,0 +1,116 @@
+use crate::config;
+use crate::issueapi::IssueAPI;
+use crate::issuecache;
+
+/// get_api_for_host returns the API of a project on the host, using the provider and token the
+/// configuration sets for the host.
+pub fn get_api_for_host(
+    config: &config::Config,
+    host: &str,
+    owner: String,
+    repo: String,
+) -> Result<Box<dyn IssueAPI>, String> {
+    match config.provider_for_host(host) {
+        Some(config::ProviderConfig::GitHub(c)) => github(
+            config.resolve_token(host, &c.token)?,
+            host,
+            owner,
+            repo,
+            c.api_url,
+        ),
+        Some(config::ProviderConfig::GitLab(c)) => gitlab(
+            config.resolve_token(host, &c.token)?,
+            host,
+            owner,
+            repo,
+            c.api_url,
+        ),
+        None => Err(format!("No token specified for host {}", host)),
+    }
+}
+
+#[cfg(feature = "github")]
+fn github(
+    token: String,
+    host: &str,
+    owner: String,
+    repo: String,
+    api_url: Option<String>,
+) -> Result<Box<dyn IssueAPI>, String> {
+    let api = crate::github::GitHubAPI::new(token, host, owner, repo);
+    Ok(Box::new(match api_url {
+        Some(api_url) => api.with_api_url(&api_url),
+        None => api,
+    }))
+}
+
+#[cfg(not(feature = "github"))]
+fn github(
+    _token: String,
+    host: &str,
+    _owner: String,
+    _repo: String,
+    _api_url: Option<String>,
+) -> Result<Box<dyn IssueAPI>, String> {
+    Err(format!(
+        "{} is a GitHub host, but issuefer was built without the github feature",
+        host
+    ))
+}
+
+#[cfg(feature = "gitlab")]
+fn gitlab(
+    token: String,
+    host: &str,
+    owner: String,
+    repo: String,
+    api_url: Option<String>,
+) -> Result<Box<dyn IssueAPI>, String> {
+    let api = crate::gitlab::GitLabAPI::new(token, host.to_string(), owner, repo);
+    Ok(Box::new(match api_url {
+        Some(api_url) => api.with_api_url(&api_url),
+        None => api,
+    }))
+}
+
+#[cfg(not(feature = "gitlab"))]
+fn gitlab(
+    _token: String,
+    host: &str,
+    _owner: String,
+    _repo: String,
+    _api_url: Option<String>,
+) -> Result<Box<dyn IssueAPI>, String> {
+    Err(format!(
+        "{} is a GitLab host, but issuefer was built without the gitlab feature",
+        host
+    ))
+}
+
+/// get_repo_api returns the API of a project on the host. Issues are cached, with offline set
+/// only the cache is used and no token is needed.
+pub fn get_repo_api(
+    config: &config::Config,
+    host: &str,
+    owner: String,
+    repo: String,
+    offline: bool,
+) -> Result<Box<dyn IssueAPI>, String> {
+    if offline {
+        return Ok(Box::new(issuecache::CachedIssueAPI::new(
+            None, host, &owner, &repo,
+        )));
+    }
+    let api = get_api_for_host(config, host, owner.clone(), repo.clone()).map_err(|e| {
+        format!(
+            "{}. Run 'issuefer doctor' or check README.md how to set up issuefer.",
+            e
+        )
+    })?;
+    Ok(Box::new(issuecache::CachedIssueAPI::new(
+        Some(api),
+        host,
+        &owner,
+        &repo,
+    )))
+}
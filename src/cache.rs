@@ -0,0 +1,154 @@
+use crate::git;
+use crate::issueapi::IssueNumber;
+use crate::todo::Todo;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+
+const SCAN_CACHE_FILE: &str = "scan-cache.json";
+const SCAN_CACHE_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CachedTodo {
+    pub line_number: usize,
+    pub keyword: String,
+    pub title: String,
+    pub issue_number: IssueNumber,
+    #[serde(default)]
+    pub issue_repo: Option<String>,
+    pub line: String,
+    pub marker: Range<usize>,
+}
+
+impl CachedTodo {
+    pub fn from_todo(todo: &Todo) -> CachedTodo {
+        CachedTodo {
+            line_number: todo.line_number,
+            keyword: todo.keyword.clone(),
+            title: todo.title.clone(),
+            issue_number: todo.issue_number,
+            issue_repo: todo.issue_repo.clone(),
+            line: todo.line.clone(),
+            marker: todo.marker.clone(),
+        }
+    }
+
+    pub fn to_todo(&self, file_path: &str) -> Todo {
+        Todo {
+            file_path: file_path.to_string(),
+            line_number: self.line_number,
+            keyword: self.keyword.clone(),
+            title: self.title.clone(),
+            issue_number: self.issue_number,
+            issue_repo: self.issue_repo.clone(),
+            line: self.line.clone(),
+            marker: self.marker.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ScanCacheFile {
+    version: u32,
+    entries: HashMap<String, Vec<CachedTodo>>,
+}
+
+/// ScanCache maps the git blob OID of a file content, together with the keywords it was scanned
+/// for, to the TODOs found in it. It lives in `<git dir>/issuefer/scan-cache.json`.
+pub struct ScanCache {
+    path: PathBuf,
+    entries: HashMap<String, Vec<CachedTodo>>,
+    used: HashMap<String, Vec<CachedTodo>>,
+    prune: bool,
+    // Why the cache could not be used, reported by the scan
+    warning: Option<String>,
+}
+
+impl ScanCache {
+    /// load reads the cache of the current repository. A missing or unreadable cache results in
+    /// an empty cache, i.e., a full scan. None is returned outside of git repositories. Set prune
+    /// only if all files of the repository are scanned, see save.
+    pub fn load(prune: bool) -> Option<ScanCache> {
+        let path = git::git_dir().ok()?.join("issuefer").join(SCAN_CACHE_FILE);
+        let mut entries = HashMap::new();
+        let mut warning = None;
+        if path.exists() {
+            match fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| {
+                    serde_json::from_str::<ScanCacheFile>(&content).map_err(|e| e.to_string())
+                }) {
+                Ok(cache_file) if cache_file.version == SCAN_CACHE_VERSION => {
+                    entries = cache_file.entries
+                }
+                Ok(_) => {
+                    warning = Some("Scan cache has an old format, rescanning all files".to_string())
+                }
+                Err(e) => {
+                    warning = Some(format!(
+                        "Scan cache {} is corrupt ({}), rescanning all files",
+                        path.display(),
+                        e
+                    ))
+                }
+            }
+        }
+        Some(ScanCache {
+            path,
+            entries,
+            used: HashMap::new(),
+            prune,
+            warning,
+        })
+    }
+
+    pub fn key(oid: &str, keywords: &[String]) -> String {
+        // The comments of the syntax tree hold other TODOs than the lines of a file
+        let scanner = if cfg!(feature = "tree-sitter") {
+            ":syntax"
+        } else {
+            ""
+        };
+        format!("{}:{}{}", oid, keywords.join(";"), scanner)
+    }
+
+    /// take_warning returns why the cache could not be read, if it could not.
+    pub fn take_warning(&mut self) -> Option<String> {
+        self.warning.take()
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<Vec<CachedTodo>> {
+        let todos = self.entries.get(key)?.clone();
+        self.used.insert(key.to_string(), todos.clone());
+        Some(todos)
+    }
+
+    pub fn insert(&mut self, key: &str, todos: Vec<CachedTodo>) {
+        self.used.insert(key.to_string(), todos);
+    }
+
+    /// save writes the entries used in this run. Entries of old file versions are only dropped
+    /// with prune, otherwise the entries of the files left out of this run would be lost too.
+    pub fn save(mut self) -> Result<(), String> {
+        let entries = if self.prune {
+            self.used
+        } else {
+            self.entries.extend(self.used);
+            self.entries
+        };
+        let cache_file = ScanCacheFile {
+            version: SCAN_CACHE_VERSION,
+            entries,
+        };
+        let content = serde_json::to_string(&cache_file).map_err(|e| e.to_string())?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, content).map_err(|e| e.to_string())?;
+        fs::rename(&tmp_path, &self.path).map_err(|e| e.to_string())
+    }
+}
@@ -1,10 +1,20 @@
+use crate::credentials::TokenSource;
+use crate::git;
 use crate::iniparser;
 
+use globset::{Glob, GlobMatcher};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::fmt;
+use std::sync::Mutex;
 
 #[derive(Debug, Clone)]
 pub struct GitHubConfig {
-    pub token: String,
+    pub token: TokenSource,
+    pub source: String,
+    /// Base URL of the REST API if it is not the default one of the host, e.g., for a proxy
+    pub api_url: Option<String>,
 }
 
 fn get_github_token_from_env() -> Option<String> {
@@ -15,24 +25,30 @@ fn get_github_token_from_env() -> Option<String> {
 }
 
 impl GitHubConfig {
-    pub fn new(token: &str) -> GitHubConfig {
+    pub fn new(token: TokenSource, source: &str) -> GitHubConfig {
         GitHubConfig {
-            token: token.to_string(),
+            token,
+            source: source.to_string(),
+            api_url: None,
         }
     }
     pub fn from_env() -> Option<GitHubConfig> {
-        if let Some(github_token) = get_github_token_from_env() {
-            Some(GitHubConfig::new(&github_token))
-        } else {
-            None
-        }
+        get_github_token_from_env().map(|github_token| {
+            GitHubConfig::new(
+                TokenSource::Plain(github_token),
+                "environment variable GITHUB_TOKEN",
+            )
+        })
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct GitLabConfig {
     pub host: String,
-    pub token: String,
+    pub token: TokenSource,
+    pub source: String,
+    /// Base URL of the REST API if it is not `https://<host>/api/v4`
+    pub api_url: Option<String>,
 }
 
 fn get_gitlab_token_from_env() -> Option<String> {
@@ -43,108 +59,625 @@ fn get_gitlab_token_from_env() -> Option<String> {
 }
 
 impl GitLabConfig {
-    pub fn new(host: &str, token: &str) -> GitLabConfig {
+    pub fn new(host: &str, token: TokenSource, source: &str) -> GitLabConfig {
         GitLabConfig {
             host: host.to_string(),
-            token: token.to_string(),
+            token,
+            source: source.to_string(),
+            api_url: None,
         }
     }
-    pub fn from_env() -> Vec<GitLabConfig> {
+    /// from_env reads GITLAB_TOKEN, i.e., tokens separated by ';', each optionally prefixed by
+    /// its host and ':'.
+    pub fn from_env() -> Result<Vec<GitLabConfig>, String> {
         let mut gitlab_configs: Vec<GitLabConfig> = Vec::new();
         if let Some(gitlab_token_env) = get_gitlab_token_from_env() {
             let gitlab_tokens: Vec<&str> = gitlab_token_env.split(';').collect();
             for token in gitlab_tokens {
                 let token_host: Vec<&str> = token.split(':').collect();
+                let source = "environment variable GITLAB_TOKEN";
                 if token_host.len() == 1 {
-                    gitlab_configs.push(GitLabConfig::new("", token_host.get(0).unwrap()))
+                    gitlab_configs.push(GitLabConfig::new(
+                        "",
+                        TokenSource::Plain(token_host.first().unwrap().to_string()),
+                        source,
+                    ))
                 } else if token_host.len() == 2 {
                     gitlab_configs.push(GitLabConfig::new(
-                        token_host.get(0).unwrap(),
-                        token_host.get(1).unwrap(),
+                        token_host.first().unwrap(),
+                        TokenSource::Plain(token_host.get(1).unwrap().to_string()),
+                        source,
                     ))
                 } else {
-                    eprintln!("Error parsing GITLAB_TOKEN. Read README.md and check it");
-                    std::process::exit(1);
+                    return Err(
+                        "Error parsing GITLAB_TOKEN. Read README.md and check it".to_string()
+                    );
                 }
             }
         }
-        gitlab_configs
+        Ok(gitlab_configs)
     }
 }
 
 #[derive(Debug)]
 pub struct GeneralConfig {
     pub ignored_extensions: Vec<String>,
+    pub remote: String,
+    pub provider: Option<String>,
+    pub keywords: Vec<String>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub labels: Vec<String>,
+    pub body_template: String,
+    pub overrides: Vec<PathOverride>,
+    pub max_file_size: u64,
 }
 
 impl GeneralConfig {
-    fn new() -> GeneralConfig {
+    pub(crate) fn new() -> GeneralConfig {
         GeneralConfig {
             ignored_extensions: Vec::<String>::new(),
+            remote: "origin".to_string(),
+            provider: None,
+            keywords: vec!["TODO".to_string()],
+            include: Vec::<String>::new(),
+            exclude: Vec::<String>::new(),
+            labels: Vec::<String>::new(),
+            body_template: "".to_string(),
+            overrides: Vec::<PathOverride>::new(),
+            max_file_size: 1024 * 1024,
         }
     }
+
+    /// keywords_for returns the keywords for a repository-relative path. The last matching
+    /// override wins.
+    pub fn keywords_for(&self, path: &str) -> &[String] {
+        self.overrides
+            .iter()
+            .rev()
+            .filter(|o| o.matcher.is_match(path))
+            .find_map(|o| o.keywords.as_deref())
+            .unwrap_or(&self.keywords)
+    }
+
+    /// labels_for returns the labels for issues created from TODOs in a repository-relative path.
+    pub fn labels_for(&self, path: &str) -> &[String] {
+        self.overrides
+            .iter()
+            .rev()
+            .filter(|o| o.matcher.is_match(path))
+            .find_map(|o| o.labels.as_deref())
+            .unwrap_or(&self.labels)
+    }
 }
 
+/// PathOverride changes settings for all files matching a glob, e.g., other keywords in docs/.
+#[derive(Debug)]
+pub struct PathOverride {
+    pub path: String,
+    matcher: GlobMatcher,
+    pub keywords: Option<Vec<String>>,
+    pub labels: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RepoPathOverride {
+    path: String,
+    keywords: Option<Vec<String>>,
+    labels: Option<Vec<String>>,
+}
+
+/// glob_pattern turns a pattern ending with '/' into one matching everything below the directory.
+pub fn glob_pattern(pattern: &str) -> String {
+    if pattern.ends_with('/') {
+        format!("{}**", pattern)
+    } else {
+        pattern.to_string()
+    }
+}
+
+// RepoConfig is the repository-local .issuefer.toml. It is meant to be checked in, therefore it
+// cannot contain tokens.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct RepoConfig {
+    remote: Option<String>,
+    provider: Option<String>,
+    keywords: Option<Vec<String>>,
+    include: Option<Vec<String>>,
+    #[serde(alias = "ignore")]
+    exclude: Option<Vec<String>>,
+    ignored_extensions: Option<Vec<String>>,
+    labels: Option<Vec<String>>,
+    body_template: Option<String>,
+    max_file_size: Option<u64>,
+    #[serde(rename = "override")]
+    overrides: Option<Vec<RepoPathOverride>>,
+}
+
+pub const REPO_CONFIG_FILE: &str = ".issuefer.toml";
+
 #[derive(Debug)]
 pub struct Config {
     pub general: GeneralConfig,
     pub github: Option<GitHubConfig>,
     pub gitlab: Vec<GitLabConfig>,
+    // sources maps the name of every setting that is not a default to the layer it came from
+    sources: BTreeMap<String, String>,
+    tokens: ResolvedTokens,
+    /// Where the config was loaded from, e.g., "config from /home/alice/.config/issuefer"
+    pub layers: Vec<String>,
+    /// Problems that did not stop loading the config, e.g., unknown keys
+    pub warnings: Vec<String>,
 }
 
-impl Config {
-    pub fn from_file(file_name: &std::path::PathBuf) -> Result<Config, String> {
-        match iniparser::parse_ini_file(&file_name.to_str().unwrap()) {
-            Ok(ini) => {
-                let mut config = Config {
-                    general: GeneralConfig::new(),
-                    github: None,
-                    gitlab: Vec::<GitLabConfig>::new(),
-                };
-                for section in ini.sections() {
-                    let section_name = section.name();
-                    if !section_name.is_empty() {
-                        if section_name == "general" {
-                            if let Ok(ignored_extensions) = section.get("ignored_extensions") {
-                                let ignored_extensions: Vec<String> = ignored_extensions
-                                    .split(';')
-                                    .map(|x| x.to_string())
-                                    .collect();
-                                config.general = GeneralConfig { ignored_extensions };
-                            }
-                            continue;
+// ResolvedTokens maps hosts to their resolved tokens, so a token command, credential helper
+// or keyring is only asked once per run. Debug output never shows the tokens.
+#[derive(Default)]
+struct ResolvedTokens(Mutex<HashMap<String, String>>);
+
+impl fmt::Debug for ResolvedTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ResolvedTokens(<redacted>)")
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+const REGEX_META_CHARACTERS: &str = "\\.+*?()|[]{}^$";
+
+// check_keywords returns the keywords with the escapes removed. A keyword is matched literally,
+// characters that have a meaning in regular expressions have to be escaped with a backslash,
+// e.g., "C\+\+", so keywords written as patterns are not silently matched literally.
+fn check_keywords(keywords: Vec<String>, source: &str) -> Result<Vec<String>, String> {
+    if keywords.is_empty() {
+        return Err(format!("{}: keywords must not be empty", source));
+    }
+    keywords
+        .iter()
+        .map(|keyword| {
+            if keyword.trim().is_empty() {
+                return Err(format!("{}: keywords must not be blank", source));
+            }
+            let mut literal = String::new();
+            let mut chars = keyword.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => match chars.next() {
+                        Some(escaped) if REGEX_META_CHARACTERS.contains(escaped) => {
+                            literal.push(escaped)
                         }
-                        if let Ok(token) = section.get("token") {
-                            if section_name == "github.com" {
-                                config.github = Some(GitHubConfig::new(token));
-                            } else {
-                                config.gitlab.push(GitLabConfig::new(section_name, token));
-                            }
-                        } else {
-                            println!(
-                                "Warning: No token found in section {}. Skipping",
-                                section_name
-                            );
+                        _ => {
+                            return Err(format!(
+                                "{}: keyword '{}' has an invalid escape",
+                                source, keyword
+                            ))
                         }
-                    } else {
-                        println!("Warning: Encountered empty section name in config. Skipping");
+                    },
+                    c if REGEX_META_CHARACTERS.contains(c) => {
+                        return Err(format!(
+                        "{}: keyword '{}' contains '{}', escape it as '\\{}' to match it literally",
+                        source, keyword, c, c
+                    ))
                     }
+                    c => literal.push(c),
                 }
-                Ok(config)
             }
-            Err(e) => Err(e),
-        }
+            Ok(literal)
+        })
+        .collect()
+}
+
+fn check_provider(provider: &str, source: &str) -> Result<(), String> {
+    if provider == "github" || provider == "gitlab" {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}: unknown provider '{}', expected 'github' or 'gitlab'",
+            source, provider
+        ))
     }
-    pub fn from_env() -> Result<Config, String> {
-        let config = Config {
+}
+
+impl Config {
+    fn new() -> Config {
+        Config {
             general: GeneralConfig::new(),
-            github: GitHubConfig::from_env(),
-            gitlab: GitLabConfig::from_env(),
-        };
-        if config.github.is_none() && config.github.is_none() {
-            Err("Could not construct any config from env variables".to_string())
+            github: None,
+            gitlab: Vec::<GitLabConfig>::new(),
+            sources: BTreeMap::new(),
+            tokens: ResolvedTokens::default(),
+            layers: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn set_source(&mut self, name: &str, source: &str) {
+        self.sources.insert(name.to_string(), source.to_string());
+    }
+
+    /// source returns the layer the effective value of a general setting came from.
+    pub fn source(&self, name: &str) -> &str {
+        self.sources.get(name).map_or("default", |s| s.as_str())
+    }
+
+    fn set_general_from_ini(&mut self, key: &str, value: &str, source: &str) -> Result<(), String> {
+        match key {
+            "ignored_extensions" => self.general.ignored_extensions = split_list(value),
+            "remote" => self.general.remote = value.to_string(),
+            "provider" => {
+                check_provider(value, source)?;
+                self.general.provider = Some(value.to_string());
+            }
+            "keywords" => self.general.keywords = check_keywords(split_list(value), source)?,
+            "include" => self.general.include = split_list(value),
+            "exclude" | "ignore" => {
+                self.general.exclude = split_list(value);
+                self.set_source("exclude", source);
+                return Ok(());
+            }
+            "labels" => self.general.labels = split_list(value),
+            "body_template" => self.general.body_template = value.to_string(),
+            "max_file_size" => {
+                self.general.max_file_size = value
+                    .parse::<u64>()
+                    .map_err(|e| format!("{}: invalid max_file_size '{}': {}", source, value, e))?
+            }
+            _ => {
+                self.warnings.push(format!(
+                    "Unknown key {} in section [general]. Skipping",
+                    key
+                ));
+                return Ok(());
+            }
+        }
+        self.set_source(key, source);
+        Ok(())
+    }
+
+    pub fn from_file(file_name: &std::path::Path) -> Result<Config, String> {
+        let ini =
+            iniparser::parse_ini_file(file_name.to_str().unwrap(), iniparser::Duplicates::Reject)
+                .map_err(|e| e.to_string())?;
+        let source = format!("user config {}", file_name.display());
+        let mut config = Config::new();
+        config
+            .layers
+            .push(format!("config from {}", file_name.display()));
+        for section in ini.sections() {
+            let section_name = section.name();
+            if section_name.is_empty() {
+                config
+                    .warnings
+                    .push("Encountered empty section name in config. Skipping".to_string());
+                continue;
+            }
+            if section_name == "general" {
+                for key in section.keys() {
+                    config.set_general_from_ini(key, section.get(key).unwrap(), &source)?;
+                }
+                continue;
+            }
+            let token = if let Ok(token) = section.get("token") {
+                Some(TokenSource::Plain(token.to_string()))
+            } else if let Ok(command) = section.get("token_command") {
+                Some(TokenSource::Command(command.to_string()))
+            } else if let Ok(token_source) = section.get("token_source") {
+                Some(TokenSource::from_value(token_source).map_err(|e| {
+                    format!("{}: section [{}]: {}", file_name.display(), section_name, e)
+                })?)
+            } else {
+                None
+            };
+            if let Some(token) = token {
+                let source = format!(
+                    "{} in config file {} section [{}]",
+                    token,
+                    file_name.display(),
+                    section_name
+                );
+                let api_url = section
+                    .get("api_url")
+                    .ok()
+                    .map(|url| url.trim_end_matches('/').to_string());
+                if section_name == "github.com" {
+                    let mut github = GitHubConfig::new(token, &source);
+                    github.api_url = api_url;
+                    config.github = Some(github);
+                } else {
+                    let mut gitlab = GitLabConfig::new(section_name, token, &source);
+                    gitlab.api_url = api_url;
+                    config.gitlab.push(gitlab);
+                }
+            } else {
+                config.warnings.push(format!(
+                    "No token, token_command or token_source found in section {}. Skipping",
+                    section_name
+                ));
+            }
+        }
+        Ok(config)
+    }
+
+    /// from_env returns the config of the GITHUB_TOKEN and GITLAB_TOKEN environment variables,
+    /// None if neither is set.
+    pub fn from_env() -> Result<Option<Config>, String> {
+        let mut config = Config::new();
+        config.github = GitHubConfig::from_env();
+        config.gitlab = GitLabConfig::from_env()?;
+        if config.github.is_none() && config.gitlab.is_empty() {
+            Ok(None)
         } else {
-            Ok(config)
+            config.layers.push("config from environment".to_string());
+            Ok(Some(config))
+        }
+    }
+
+    // merge_repo_config layers the repository config over the user config.
+    fn merge_repo_config(&mut self, file_name: &std::path::Path) -> Result<(), String> {
+        let content = std::fs::read_to_string(file_name)
+            .map_err(|e| format!("{}: Unable to read file: {}", file_name.display(), e))?;
+        let repo_config: RepoConfig =
+            toml::from_str(&content).map_err(|e| format!("{}: {}", file_name.display(), e))?;
+        let source = format!("repository config {}", file_name.display());
+        self.layers
+            .push(format!("repository config from {}", file_name.display()));
+
+        if let Some(remote) = repo_config.remote {
+            self.general.remote = remote;
+            self.set_source("remote", &source);
+        }
+        if let Some(provider) = repo_config.provider {
+            check_provider(&provider, &source)?;
+            self.general.provider = Some(provider);
+            self.set_source("provider", &source);
+        }
+        if let Some(keywords) = repo_config.keywords {
+            self.general.keywords = check_keywords(keywords, &source)?;
+            self.set_source("keywords", &source);
+        }
+        if let Some(include) = repo_config.include {
+            self.general.include = include;
+            self.set_source("include", &source);
+        }
+        if let Some(exclude) = repo_config.exclude {
+            self.general.exclude = exclude;
+            self.set_source("exclude", &source);
+        }
+        if let Some(max_file_size) = repo_config.max_file_size {
+            self.general.max_file_size = max_file_size;
+            self.set_source("max_file_size", &source);
+        }
+        if let Some(overrides) = repo_config.overrides {
+            for o in overrides {
+                let matcher = Glob::new(&glob_pattern(&o.path))
+                    .map_err(|e| format!("{}: invalid override path '{}': {}", source, o.path, e))?
+                    .compile_matcher();
+                self.general.overrides.push(PathOverride {
+                    path: o.path,
+                    matcher,
+                    keywords: o
+                        .keywords
+                        .map(|keywords| check_keywords(keywords, &source))
+                        .transpose()?,
+                    labels: o.labels,
+                });
+            }
+            self.set_source("overrides", &source);
+        }
+        if let Some(ignored_extensions) = repo_config.ignored_extensions {
+            self.general.ignored_extensions = ignored_extensions;
+            self.set_source("ignored_extensions", &source);
+        }
+        if let Some(labels) = repo_config.labels {
+            self.general.labels = labels;
+            self.set_source("labels", &source);
+        }
+        if let Some(body_template) = repo_config.body_template {
+            self.general.body_template = body_template;
+            self.set_source("body_template", &source);
+        }
+        Ok(())
+    }
+
+    /// describe returns every effective setting with its value and the layer it came from.
+    /// Tokens are never part of the output, only where they came from.
+    pub fn describe(&self) -> Vec<(String, String, String)> {
+        let general = &self.general;
+        let mut settings: Vec<(String, String)> = vec![
+            ("remote".to_string(), general.remote.clone()),
+            (
+                "provider".to_string(),
+                general
+                    .provider
+                    .clone()
+                    .unwrap_or_else(|| "detected from host".to_string()),
+            ),
+            ("keywords".to_string(), general.keywords.join(";")),
+            ("include".to_string(), general.include.join(";")),
+            ("exclude".to_string(), general.exclude.join(";")),
+            (
+                "ignored_extensions".to_string(),
+                general.ignored_extensions.join(";"),
+            ),
+            ("labels".to_string(), general.labels.join(";")),
+            (
+                "body_template".to_string(),
+                general.body_template.replace('\n', "\\n"),
+            ),
+            (
+                "max_file_size".to_string(),
+                general.max_file_size.to_string(),
+            ),
+            (
+                "overrides".to_string(),
+                general
+                    .overrides
+                    .iter()
+                    .map(|o| {
+                        let mut settings = Vec::new();
+                        if let Some(keywords) = &o.keywords {
+                            settings.push(format!("keywords={}", keywords.join(";")));
+                        }
+                        if let Some(labels) = &o.labels {
+                            settings.push(format!("labels={}", labels.join(";")));
+                        }
+                        format!("{}: {}", o.path, settings.join(" "))
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            ),
+        ];
+        let mut description: Vec<(String, String, String)> = settings
+            .drain(..)
+            .map(|(name, value)| {
+                let source = self.source(&name).to_string();
+                (name, value, source)
+            })
+            .collect();
+        if let Some(github) = &self.github {
+            description.push((
+                "github.com token".to_string(),
+                "<hidden>".to_string(),
+                github.source.clone(),
+            ));
+            if let Some(api_url) = &github.api_url {
+                description.push((
+                    "github.com api_url".to_string(),
+                    api_url.clone(),
+                    "user config".to_string(),
+                ));
+            }
+        }
+        for gitlab in &self.gitlab {
+            let host = if gitlab.host.is_empty() {
+                "gitlab.com"
+            } else {
+                &gitlab.host
+            };
+            description.push((
+                format!("{} token", host),
+                "<hidden>".to_string(),
+                gitlab.source.clone(),
+            ));
+            if let Some(api_url) = &gitlab.api_url {
+                description.push((
+                    format!("{} api_url", host),
+                    api_url.clone(),
+                    "user config".to_string(),
+                ));
+            }
         }
+        description
     }
 }
+
+pub enum ProviderConfig {
+    GitHub(GitHubConfig),
+    GitLab(GitLabConfig),
+}
+
+impl Config {
+    /// provider_for_host returns the GitHub or GitLab config that holds the token for the given host.
+    /// Without a configured provider, github.com is GitHub and every other host is GitLab.
+    pub fn provider_for_host(&self, host: &str) -> Option<ProviderConfig> {
+        let gitlab = self
+            .gitlab
+            .iter()
+            .find(|c| c.host == host || (host == "gitlab.com" && c.host.is_empty()));
+        match self.general.provider.as_deref() {
+            // GitHub Enterprise tokens are stored in host sections like the GitLab ones
+            Some("github") if host != "github.com" => gitlab.map(|c| {
+                let mut github = GitHubConfig::new(c.token.clone(), &c.source);
+                github.api_url = c.api_url.clone();
+                ProviderConfig::GitHub(github)
+            }),
+            Some("gitlab") => gitlab.cloned().map(ProviderConfig::GitLab),
+            _ if host == "github.com" => self.github.clone().map(ProviderConfig::GitHub),
+            _ => gitlab.cloned().map(ProviderConfig::GitLab),
+        }
+    }
+
+    /// resolve_token returns the token of the host from the given source. The source is only
+    /// asked the first time, later calls return the same token.
+    pub fn resolve_token(&self, host: &str, token: &TokenSource) -> Result<String, String> {
+        if let Some(token) = self.tokens.0.lock().unwrap().get(host) {
+            return Ok(token.clone());
+        }
+        let resolved = token.resolve(host)?;
+        self.tokens
+            .0
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), resolved.clone());
+        Ok(resolved)
+    }
+}
+
+pub fn default_locations() -> Vec<std::path::PathBuf> {
+    let mut config_paths: Vec<std::path::PathBuf> = Vec::new();
+    if let Some(config_dir) = dirs::config_dir() {
+        config_paths.push(config_dir.join("issuefer"));
+    }
+    if let Some(config_dir) = dirs::home_dir() {
+        config_paths.push(config_dir.join(".issuefer"));
+    }
+    config_paths
+}
+
+/// load reads the user config from config_path or the default locations, falling back to the
+/// environment, and layers the repository config from the current directory over it.
+pub fn load(config_path: Option<&std::path::Path>) -> Result<Config, String> {
+    let mut config: Option<Config> = None;
+    if let Some(location) = config_path {
+        if !location.exists() {
+            return Err(format!("Config file {} does not exist", location.display()));
+        }
+        config = Some(Config::from_file(location)?);
+    } else {
+        for location in default_locations() {
+            if location.exists() {
+                config = Some(Config::from_file(&location)?);
+                break;
+            }
+        }
+    }
+    if config.is_none() {
+        config = Config::from_env()?;
+    }
+    let mut config = match config {
+        Some(config) => config,
+        None => {
+            return Err(format!(
+            "No configuration found at {} and no GITHUB_TOKEN/GITLAB_TOKEN environment variable",
+            default_locations()
+                .iter()
+                .map(|l| l.display().to_string())
+                .collect::<Vec<String>>()
+                .join(" or ")
+        ))
+        }
+    };
+
+    // The repository config is checked in at the root, outside of repositories it is looked up
+    // in the current directory
+    let repo_config = match git::toplevel() {
+        Ok(toplevel) => toplevel,
+        Err(_) => {
+            env::current_dir().map_err(|e| format!("Cannot determine current directory: {}", e))?
+        }
+    }
+    .join(REPO_CONFIG_FILE);
+    if repo_config.exists() {
+        config.merge_repo_config(&repo_config)?;
+    }
+
+    Ok(config)
+}
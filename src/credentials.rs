@@ -0,0 +1,149 @@
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// TokenSource describes where the token for a host comes from. Tokens are only resolved when
+/// an API for the host is actually needed, so a password manager is not asked for hosts that
+/// are not used in this run.
+#[derive(Clone)]
+pub enum TokenSource {
+    Plain(String),
+    /// Shell command whose first line of stdout is the token, e.g., 'pass show gitlab'
+    Command(String),
+    /// The password returned by 'git credential fill' for the host
+    GitCredential,
+    /// The freedesktop Secret Service entry with the attributes `service=issuefer host=<host>`
+    Keyring,
+}
+
+// Tokens must never end up in debug output, therefore Debug is implemented by hand.
+impl fmt::Debug for TokenSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenSource::Plain(_) => write!(f, "Plain(<redacted>)"),
+            TokenSource::Command(command) => write!(f, "Command({:?})", command),
+            TokenSource::GitCredential => write!(f, "GitCredential"),
+            TokenSource::Keyring => write!(f, "Keyring"),
+        }
+    }
+}
+
+impl fmt::Display for TokenSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenSource::Plain(_) => write!(f, "plain token"),
+            TokenSource::Command(command) => write!(f, "token command '{}'", command),
+            TokenSource::GitCredential => write!(f, "git credential helper"),
+            TokenSource::Keyring => write!(f, "Secret Service keyring"),
+        }
+    }
+}
+
+impl TokenSource {
+    /// from_value parses a token_source setting, i.e., 'git-credential' or 'keyring'.
+    pub fn from_value(value: &str) -> Result<TokenSource, String> {
+        match value {
+            "git-credential" => Ok(TokenSource::GitCredential),
+            "keyring" => Ok(TokenSource::Keyring),
+            _ => Err(format!(
+                "unknown token_source '{}', expected 'git-credential' or 'keyring'",
+                value
+            )),
+        }
+    }
+
+    /// resolve returns the token for the given host.
+    pub fn resolve(&self, host: &str) -> Result<String, String> {
+        let token = match self {
+            TokenSource::Plain(token) => token.clone(),
+            TokenSource::Command(command) => token_from_command(command)?,
+            TokenSource::GitCredential => token_from_git_credential(host)?,
+            TokenSource::Keyring => token_from_keyring(host)?,
+        };
+        if token.is_empty() {
+            return Err(format!("The {} returned an empty token for {}", self, host));
+        }
+        Ok(token)
+    }
+}
+
+fn first_line(output: &[u8]) -> String {
+    String::from_utf8_lossy(output)
+        .lines()
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_string()
+}
+
+fn token_from_command(command: &str) -> Result<String, String> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    };
+    match output {
+        Ok(output) => {
+            if output.status.success() {
+                Ok(first_line(&output.stdout))
+            } else {
+                Err(format!(
+                    "Token command '{}' failed with {}",
+                    command, output.status
+                ))
+            }
+        }
+        Err(e) => Err(format!(
+            "Error when executing token command '{}': {}",
+            command, e
+        )),
+    }
+}
+
+fn token_from_git_credential(host: &str) -> Result<String, String> {
+    let mut child = Command::new("git")
+        .args(["credential", "fill"])
+        // Never fall back to asking on the terminal, a helper has to know the credential
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Error when executing git credential fill: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        write!(stdin, "protocol=https\nhost={}\n\n", host)
+            .map_err(|e| format!("Error when writing to git credential fill: {}", e))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Error when executing git credential fill: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git credential fill found no credential for {}",
+            host
+        ));
+    }
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(password) = line.strip_prefix("password=") {
+            return Ok(password.to_string());
+        }
+    }
+    Err(format!(
+        "git credential fill returned no password for {}",
+        host
+    ))
+}
+
+fn token_from_keyring(host: &str) -> Result<String, String> {
+    let output = Command::new("secret-tool")
+        .args(["lookup", "service", "issuefer", "host", host])
+        .output()
+        .map_err(|e| format!("Error when executing secret-tool: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "No Secret Service entry with service=issuefer host={} found",
+            host
+        ));
+    }
+    Ok(first_line(&output.stdout))
+}
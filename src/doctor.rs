@@ -0,0 +1,199 @@
+use crate::config;
+use crate::git;
+
+struct Check {
+    name: String,
+    passed: bool,
+    details: String,
+    fix: String,
+}
+
+impl Check {
+    fn pass(name: &str, details: &str) -> Check {
+        Check {
+            name: name.to_string(),
+            passed: true,
+            details: details.to_string(),
+            fix: "".to_string(),
+        }
+    }
+    fn fail(name: &str, details: &str, fix: &str) -> Check {
+        Check {
+            name: name.to_string(),
+            passed: false,
+            details: details.to_string(),
+            fix: fix.to_string(),
+        }
+    }
+    fn print(&self) {
+        let status = if self.passed { "PASS" } else { "FAIL" };
+        println!("[{}] {}: {}", status, self.name, self.details);
+        if !self.fix.is_empty() {
+            println!("       Fix: {}", self.fix);
+        }
+    }
+}
+
+// run_checks performs the checks in the order issuefer itself needs them and stops at the
+// first failure a later check would depend on.
+fn run_checks(config: &Result<config::Config, String>, remote: &str) -> Vec<Check> {
+    let mut checks: Vec<Check> = Vec::new();
+
+    let locations: Vec<String> = config::default_locations()
+        .iter()
+        .map(|l| l.display().to_string())
+        .collect();
+    let config = match config {
+        Ok(config) => {
+            checks.push(Check::pass("Configuration", "found"));
+            config
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                "Configuration",
+                e,
+                &format!(
+                    "create or fix {} as described in README.md",
+                    locations.join(" or ")
+                ),
+            ));
+            return checks;
+        }
+    };
+
+    let config_path = match git::config_path() {
+        Ok(path) => {
+            checks.push(Check::pass("Git config", &path.display().to_string()));
+            path
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                "Git config",
+                &e,
+                "run issuefer from the top level directory of a git repository",
+            ));
+            return checks;
+        }
+    };
+
+    let remote_check = format!("Remote {}", remote);
+    let url = match git::remote_url(&config_path, remote) {
+        Ok(url) => {
+            checks.push(Check::pass(&remote_check, &url));
+            url
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                &remote_check,
+                &e,
+                &format!(
+                    "add the remote with 'git remote add {} <url>' or select another one with --remote",
+                    remote
+                ),
+            ));
+            return checks;
+        }
+    };
+
+    let (host, owner, repo) = match git::parse_remote_url(&url) {
+        Ok((host, owner, repo)) => {
+            checks.push(Check::pass(
+                "Remote URL",
+                &format!("host: {} owner: {} repo: {}", host, owner, repo),
+            ));
+            (host, owner, repo)
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                "Remote URL",
+                &e,
+                "use a remote URL of the form git@host:owner/repo.git or https://host/owner/repo",
+            ));
+            return checks;
+        }
+    };
+
+    match config.provider_for_host(&host) {
+        Some(config::ProviderConfig::GitHub(c)) => checks.push(Check::pass(
+            "Token",
+            &format!("GitHub token from {}", c.source),
+        )),
+        Some(config::ProviderConfig::GitLab(c)) => checks.push(Check::pass(
+            "Token",
+            &format!("GitLab token from {}", c.source),
+        )),
+        None => {
+            checks.push(Check::fail(
+                "Token",
+                &format!("no token configured for host {}", host),
+                &format!(
+                    "add a [{}] section with a token, token_command or token_source to {} or set GITHUB_TOKEN/GITLAB_TOKEN",
+                    host,
+                    locations.join(" or ")
+                ),
+            ));
+            return checks;
+        }
+    }
+
+    let api = match crate::backend::get_api_for_host(config, &host, owner, repo) {
+        Ok(api) => api,
+        Err(e) => {
+            checks.push(Check::fail(
+                "Token resolution",
+                &e,
+                "check the token_command or token_source setting and that the credential exists",
+            ));
+            return checks;
+        }
+    };
+    let token_info = match api.token_info() {
+        Ok(token_info) => {
+            checks.push(Check::pass(
+                "Authentication",
+                &format!("authenticated as '{}' against {}", token_info.user, host),
+            ));
+            token_info
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                "Authentication",
+                &e,
+                "check that the host is reachable and that the token is not expired or revoked",
+            ));
+            return checks;
+        }
+    };
+
+    let scopes = match &token_info.scopes {
+        Some(scopes) if scopes.is_empty() => "none".to_string(),
+        Some(scopes) => scopes.join(", "),
+        None => "not reported".to_string(),
+    };
+    match token_info.can_write_issues {
+        Some(true) => checks.push(Check::pass(
+            "Issue write scope",
+            &format!("token scopes: {}", scopes),
+        )),
+        Some(false) => checks.push(Check::fail(
+            "Issue write scope",
+            &format!("token scopes: {}", scopes),
+            "create a token with the 'repo' (GitHub) or 'api' (GitLab) scope",
+        )),
+        None => checks.push(Check::pass(
+            "Issue write scope",
+            "cannot be determined for this kind of token, make sure it may create issues",
+        )),
+    }
+
+    checks
+}
+
+// run prints the result of every check and returns true if all of them passed.
+pub fn run(config: &Result<config::Config, String>, remote: &str) -> bool {
+    let checks = run_checks(config, remote);
+    for check in &checks {
+        check.print();
+    }
+    checks.iter().all(|c| c.passed)
+}
@@ -0,0 +1,189 @@
+use crate::issueapi::{
+    HttpRequest, Issue, IssueAPI, IssueNumber, IssueUpdates, NewIssue, ReferenceKind, TokenInfo,
+};
+use crate::workspace::Workspace;
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Headers that carry credentials and are never printed
+const SECRET_HEADERS: &[&str] = &["authorization", "private-token"];
+
+// format_request prints an HTTP request the way it would be sent, with the token redacted.
+pub fn format_request(request: &HttpRequest) -> String {
+    let mut text = format!("{} {}\n", request.method, request.url);
+    for (name, value) in &request.headers {
+        let value = if SECRET_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+            "<redacted>"
+        } else {
+            value
+        };
+        text.push_str(&format!("{}: {}\n", name, value));
+    }
+    if let Some(body) = &request.body {
+        text.push_str(&format!("\n{}\n", String::from_utf8_lossy(body)));
+    }
+    text
+}
+
+// DryRunAPI reads issues through the wrapped API but only prints the requests that would
+// create issues. Created issues get placeholder numbers counting up from first_number.
+pub struct DryRunAPI {
+    inner: Box<dyn IssueAPI>,
+    next_number: Cell<IssueNumber>,
+}
+
+impl DryRunAPI {
+    pub fn new(inner: Box<dyn IssueAPI>, first_number: IssueNumber) -> DryRunAPI {
+        DryRunAPI {
+            inner,
+            next_number: Cell::new(first_number),
+        }
+    }
+}
+
+impl IssueAPI for DryRunAPI {
+    fn repo(&self) -> String {
+        self.inner.repo()
+    }
+
+    fn get_issues(&self) -> Result<Vec<Issue>, String> {
+        self.inner.get_issues()
+    }
+
+    fn get_closed_issues(&self) -> Result<Vec<Issue>, String> {
+        self.inner.get_closed_issues()
+    }
+
+    fn get_issues_since(
+        &self,
+        since: Option<&str>,
+        etag: Option<&str>,
+    ) -> Result<IssueUpdates, String> {
+        self.inner.get_issues_since(since, etag)
+    }
+
+    fn reference_kind(&self, number: IssueNumber) -> Result<ReferenceKind, String> {
+        self.inner.reference_kind(number)
+    }
+
+    fn close_issue(&self, number: IssueNumber) -> Result<(), String> {
+        let request = self.inner.close_issue_request(number)?;
+        println!(
+            "Dry run: would close #{} with\n{}",
+            number,
+            format_request(&request)
+        );
+        Ok(())
+    }
+
+    fn delete_issue(&self, number: IssueNumber) -> Result<(), String> {
+        let request = self.inner.delete_issue_request(number)?;
+        println!(
+            "Dry run: would delete #{} with\n{}",
+            number,
+            format_request(&request)
+        );
+        Ok(())
+    }
+
+    fn token_info(&self) -> Result<TokenInfo, String> {
+        self.inner.token_info()
+    }
+
+    fn close_issue_request(&self, number: IssueNumber) -> Result<HttpRequest, String> {
+        self.inner.close_issue_request(number)
+    }
+
+    fn delete_issue_request(&self, number: IssueNumber) -> Result<HttpRequest, String> {
+        self.inner.delete_issue_request(number)
+    }
+
+    fn create_issue_request(&self, new_issue: &NewIssue) -> Result<HttpRequest, String> {
+        self.inner.create_issue_request(new_issue)
+    }
+
+    fn create_issue(&self, new_issue: &NewIssue) -> Result<Issue, String> {
+        let request = self.inner.create_issue_request(new_issue)?;
+        println!("Dry run: would send\n{}", format_request(&request));
+        let number = self.next_number.get();
+        self.next_number.set(number + 1);
+        println!("Dry run: using #{} as placeholder issue number", number);
+        Ok(Issue {
+            number,
+            title: new_issue.title.clone(),
+            state: "open".to_string(),
+            updated_at: String::new(),
+            body: new_issue.body.clone(),
+        })
+    }
+
+    fn take_messages(&self) -> Vec<String> {
+        self.inner.take_messages()
+    }
+}
+
+// DryRunWorkspace keeps the changed files in memory, so later changes build on earlier ones,
+// and prints a unified diff for every change and the commit messages.
+#[derive(Default)]
+pub struct DryRunWorkspace {
+    files: RefCell<HashMap<PathBuf, Vec<u8>>>,
+}
+
+impl Workspace for DryRunWorkspace {
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>, io::Error> {
+        match self.files.borrow().get(path) {
+            Some(content) => Ok(content.clone()),
+            None => std::fs::read(path),
+        }
+    }
+
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<(), io::Error> {
+        let old = self.read_file(path)?;
+        let old = String::from_utf8_lossy(&old);
+        let new = String::from_utf8_lossy(content);
+        let name = path.display().to_string();
+        println!(
+            "Dry run: would change {}\n{}",
+            name,
+            similar::TextDiff::from_lines(old.as_ref(), new.as_ref())
+                .unified_diff()
+                .header(&format!("a/{}", name), &format!("b/{}", name))
+        );
+        self.files
+            .borrow_mut()
+            .insert(path.to_path_buf(), content.to_vec());
+        Ok(())
+    }
+
+    fn commit(&self, file_path: &str, message: &str) -> Result<(), String> {
+        println!(
+            "Dry run: would commit {} with message '{}'",
+            file_path, message
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_redacted() {
+        let request = reqwest::blocking::Client::new()
+            .post("https://api.github.com/repos/o/r/issues")
+            .header(reqwest::header::AUTHORIZATION, "token secret")
+            .header("PRIVATE-TOKEN", "secret")
+            .body("{\"title\":\"x\"}")
+            .build()
+            .unwrap();
+        let text = format_request(&HttpRequest::from(&request));
+        assert!(!text.contains("secret"));
+        assert!(text.starts_with("POST https://api.github.com/repos/o/r/issues\n"));
+        assert!(text.contains("authorization: <redacted>\n"));
+        assert!(text.ends_with("\n{\"title\":\"x\"}\n"));
+    }
+}
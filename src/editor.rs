@@ -0,0 +1,140 @@
+use crate::git;
+use crate::issueapi::NewIssue;
+use crate::todo::Todo;
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+const EDIT_FILE: &str = "ISSUE_EDITMSG.md";
+
+// Everything below this line is removed from the edited file, like git commit --cleanup=scissors.
+// Unlike # comments it keeps Markdown headings in the body.
+const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+// Lines of code shown above and below the TODO in the edited file
+const CONTEXT: usize = 5;
+
+// editor returns the editor command: $EDITOR, $VISUAL or the editor git uses.
+fn editor() -> Result<String, String> {
+    for var in &["EDITOR", "VISUAL"] {
+        if let Ok(editor) = env::var(var) {
+            if !editor.trim().is_empty() {
+                return Ok(editor);
+            }
+        }
+    }
+    let output = Command::new("git")
+        .args(["var", "GIT_EDITOR"])
+        .output()
+        .map_err(|e| format!("Could not run git var GIT_EDITOR: {}", e))?;
+    let editor = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || editor.is_empty() {
+        return Err("No editor found, set $EDITOR".to_string());
+    }
+    Ok(editor)
+}
+
+// edit_issue opens the editor on the title and body of the issue for the TODO. It returns None
+// if the title was emptied, which aborts reporting the TODO.
+pub fn edit_issue(todo: &Todo, issue: &NewIssue) -> Result<Option<NewIssue>, String> {
+    let editor = editor()?;
+    let path = git::git_dir()?.join("issuefer").join(EDIT_FILE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    fs::write(&path, edit_template(todo, issue))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    // Run through the shell like git does, so the editor may come with arguments
+    let status = if cfg!(windows) {
+        Command::new("cmd")
+            .args(["/C", &format!("{} \"{}\"", editor, path.display())])
+            .status()
+    } else {
+        Command::new("sh")
+            .args(["-c", &format!("{} \"$@\"", editor), &editor])
+            .arg(&path)
+            .status()
+    }
+    .map_err(|e| format!("Could not run editor '{}': {}", editor, e))?;
+    if !status.success() {
+        return Err(format!("Editor '{}' failed with {}", editor, status));
+    }
+
+    let content = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let _ = fs::remove_file(&path);
+    Ok(parse_edited(&content).map(|(title, body)| NewIssue {
+        title,
+        body,
+        labels: issue.labels.clone(),
+        assignees: issue.assignees.clone(),
+    }))
+}
+
+fn edit_template(todo: &Todo, issue: &NewIssue) -> String {
+    let mut text = format!("{}\n\n", issue.title);
+    if !issue.body.is_empty() {
+        text.push_str(&format!("{}\n\n", issue.body));
+    }
+    text.push_str(&format!("{}\n", SCISSORS));
+    text.push_str("# Do not modify or remove the line above, everything below it is ignored.\n");
+    text.push_str("# The first line is the title of the issue, the lines after it the body.\n");
+    text.push_str("# The title is also written back to the TODO. An empty title aborts.\n#\n");
+    text.push_str(&format!("# {}:{}\n", todo.file_path, todo.line_number + 1));
+    if let Ok(content) = fs::read(&todo.file_path) {
+        let content = String::from_utf8_lossy(&content);
+        let first = todo.line_number.saturating_sub(CONTEXT);
+        for (number, line) in content
+            .lines()
+            .enumerate()
+            .skip(first)
+            .take(todo.line_number - first + CONTEXT + 1)
+        {
+            let arrow = if number == todo.line_number { ">" } else { " " };
+            text.push_str(&format!("# {}{:5} {}\n", arrow, number + 1, line));
+        }
+    }
+    text
+}
+
+// parse_edited splits the edited file into title and body. It returns None if the title is
+// empty.
+fn parse_edited(content: &str) -> Option<(String, String)> {
+    let content = match content.find(SCISSORS) {
+        Some(end) => &content[..end],
+        None => content,
+    };
+    let content = content.trim_start();
+    let (title, body) = content.split_once('\n').unwrap_or((content, ""));
+    let title = title.trim();
+    if title.is_empty() {
+        return None;
+    }
+    Some((title.to_string(), body.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edited_file_is_split_at_the_scissors() {
+        let content = format!(
+            "  Better title \n\n## Details\nMore text\n\n{}\n# a.rs:1\n# > 1 // TODO: x\n",
+            SCISSORS
+        );
+        assert_eq!(
+            parse_edited(&content),
+            Some((
+                "Better title".to_string(),
+                "## Details\nMore text".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_edited("Title only"),
+            Some(("Title only".to_string(), String::new()))
+        );
+        assert_eq!(parse_edited(&format!("  \n{}\n# x\n", SCISSORS)), None);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::issueapi::{IssueAPI, NewIssue};
+use crate::pending;
+use crate::sync::{Action, IssueRef, PlannedTodo, SyncPlan};
+use crate::todo::{self, Todo};
+use crate::workspace::{self, commit_add, commit_delete, update_file, Workspace};
+
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+// ApiFactory returns the API of a repository on the host of the plan, given its repository path
+// or None for the project of the plan.
+pub type ApiFactory<'a> = dyn Fn(Option<&str>) -> Result<Box<dyn IssueAPI>, String> + 'a;
+
+// Outcome is what happened to an action, for the caller to show as the plan is applied.
+pub enum Outcome {
+    Done(String),
+    Warning(String),
+    Failed(String),
+}
+
+// Executor applies the actions of a plan, e.g., one saved by `issuefer plan` and reviewed
+// before.
+pub struct Executor<'a> {
+    pub workspace: &'a dyn Workspace,
+    pub make_api: &'a ApiFactory<'a>,
+    pub dry_run: bool,
+    pub report: &'a dyn Fn(Outcome),
+}
+
+// Created is an issue created by a CreateIssue action, for the AnnotateTodo actions after it.
+struct Created {
+    reference: String,
+    title: String,
+    pending_id: String,
+}
+
+impl<'a> Executor<'a> {
+    // apply applies the actions in order. A failed action does not stop the others, but false is
+    // returned.
+    pub fn apply(&self, plan: &SyncPlan) -> bool {
+        let mut apis = HashMap::new();
+        let mut created = HashMap::new();
+        let mut ok = true;
+        for action in &plan.actions {
+            let result = match action {
+                Action::CreateIssue {
+                    id,
+                    todo,
+                    title,
+                    body,
+                    labels,
+                    assignees,
+                } => {
+                    let issue = NewIssue {
+                        title: title.clone(),
+                        body: body.clone(),
+                        labels: labels.clone(),
+                        assignees: assignees.clone(),
+                    };
+                    self.api(&mut apis, plan.target_repo.as_deref())
+                        .and_then(|api| self.create_issue(plan, api, todo, issue))
+                        .map(|issue| {
+                            created.insert(id.clone(), issue);
+                        })
+                }
+                Action::AnnotateTodo { todo, issue } => match issue {
+                    IssueRef::Planned(id) => match created.get(id) {
+                        Some(issue) => self.annotate_todo(todo, issue),
+                        // Creating the issue failed and was reported already
+                        None => Ok(()),
+                    },
+                    IssueRef::Existing { repo, number } => {
+                        let created = Created {
+                            reference: todo::format_reference(repo.as_deref(), *number),
+                            title: todo.title.clone(),
+                            pending_id: String::new(),
+                        };
+                        self.annotate_todo(todo, &created)
+                    }
+                },
+                Action::RemoveTodo { todo } => self.remove_todo(todo),
+                Action::CloseIssue { repo, number, .. } => self
+                    .api(&mut apis, repo.as_deref())
+                    .and_then(|api| api.close_issue(*number))
+                    .map(|_| {
+                        (self.report)(Outcome::Done(format!(
+                            "Issue {} closed, its TODO is gone",
+                            todo::format_reference(repo.as_deref(), *number)
+                        )))
+                    })
+                    .map_err(|e| {
+                        format!(
+                            "Could not close issue {}: {}",
+                            todo::format_reference(repo.as_deref(), *number),
+                            e
+                        )
+                    }),
+                Action::UpdateTitle { todo, title } => self.update_title(todo, title),
+            };
+            if let Err(e) = result {
+                (self.report)(Outcome::Failed(e));
+                ok = false;
+            }
+        }
+        ok
+    }
+
+    fn api<'b>(
+        &self,
+        apis: &'b mut HashMap<Option<String>, Box<dyn IssueAPI>>,
+        repo: Option<&str>,
+    ) -> Result<&'b dyn IssueAPI, String> {
+        let key = repo.map(str::to_string);
+        if !apis.contains_key(&key) {
+            let api = (self.make_api)(repo)?;
+            apis.insert(key.clone(), api);
+        }
+        Ok(apis[&key].as_ref())
+    }
+
+    // locate returns the TODO as it is now, the plan may be older than the last change of the
+    // file.
+    fn locate(&self, todo: &PlannedTodo) -> Result<Todo, String> {
+        let todo = todo.to_todo();
+        workspace::locate(self.workspace, todo.clone())
+            .ok_or_else(|| format!("{}: the TODO is gone, expected '{}'", todo, todo.line))
+    }
+
+    // create_issue creates the issue in the project new issues are reported to. The creation is
+    // recorded as pending until the TODO is annotated, see pending::reconcile.
+    fn create_issue(
+        &self,
+        plan: &SyncPlan,
+        api: &dyn IssueAPI,
+        todo: &PlannedTodo,
+        mut issue: NewIssue,
+    ) -> Result<Created, String> {
+        let todo = self.locate(todo)?;
+        let (owner, repo) = match &plan.target_repo {
+            Some(path) => todo::split_repo_path(path)
+                .ok_or_else(|| format!("'{}' is not a repository path", path))?,
+            None => (plan.project.owner.clone(), plan.project.repo.clone()),
+        };
+        let creation = pending::PendingCreation {
+            id: pending::new_id(),
+            host: plan.project.host.clone(),
+            owner,
+            repo,
+            target_repo: plan.target_repo.clone(),
+            started_at: pending::search_start(SystemTime::now()),
+            project: plan.project.clone(),
+            file_path: todo.file_path.clone(),
+            todo: crate::cache::CachedTodo::from_todo(&todo),
+        };
+        issue.body = format!("{}\n\n{}", issue.body, creation.marker())
+            .trim_start()
+            .to_string();
+        if !self.dry_run {
+            pending::add(creation.clone()).map_err(|e| {
+                format!(
+                    "Could not record the issue creation, skipping '{}': {}",
+                    todo, e
+                )
+            })?;
+        }
+        // The request may have created the issue anyway, the next run checks that
+        let new_issue = api
+            .create_issue(&issue)
+            .map_err(|e| format!("Could not create new issue for '{}': {}", todo, e))?;
+        let reference = todo::format_reference(plan.target_repo.as_deref(), new_issue.number);
+        (self.report)(Outcome::Done(format!(
+            "Issue {} with title '{}' created successfully",
+            reference, new_issue.title
+        )));
+        Ok(Created {
+            reference,
+            title: issue.title,
+            pending_id: creation.id,
+        })
+    }
+
+    // annotate_todo adds the reference of the issue to the TODO. An edited title is written
+    // back, so the TODO and the issue stay consistent.
+    fn annotate_todo(&self, todo: &PlannedTodo, issue: &Created) -> Result<(), String> {
+        let todo = self.locate(todo)?;
+        let new_title = Some(issue.title.as_str()).filter(|title| *title != todo.title);
+        update_file(
+            self.workspace,
+            &todo,
+            Some(&todo::format_marker(&todo.keyword, Some(&issue.reference))),
+            new_title,
+        )
+        // The creation stays pending, so the next run references the issue
+        .map_err(|e| {
+            format!(
+                "Could not add {} to the TODO, the next run retries: {}",
+                issue.reference, e
+            )
+        })?;
+        // The TODO references the issue now, even if it could not be committed
+        let committed = commit_add(self.workspace, &todo.file_path, &issue.reference);
+        if !self.dry_run && !issue.pending_id.is_empty() {
+            if let Err(e) = pending::remove(&issue.pending_id) {
+                (self.report)(Outcome::Warning(format!(
+                    "Could not update pending issue creations: {}",
+                    e
+                )));
+            }
+        }
+        committed.map_err(|e| format!("Could not commit {}: {}", todo.file_path, e))
+    }
+
+    fn remove_todo(&self, todo: &PlannedTodo) -> Result<(), String> {
+        let todo = self.locate(todo)?;
+        update_file(self.workspace, &todo, None, None)
+            .map_err(|e| format!("Could not remove the TODO: {}", e))?;
+        commit_delete(self.workspace, &todo.file_path, &todo.reference())
+            .map_err(|e| format!("Could not commit {}: {}", todo.file_path, e))?;
+        (self.report)(Outcome::Done(format!(
+            "Todo to issue {} with title '{}' removed successfully",
+            todo.reference(),
+            todo.title
+        )));
+        Ok(())
+    }
+
+    // update_title gives the TODO the title of its issue, the marker stays as it is.
+    fn update_title(&self, todo: &PlannedTodo, title: &str) -> Result<(), String> {
+        let todo = self.locate(todo)?;
+        let marker = todo.line[todo.marker.clone()].to_string();
+        update_file(self.workspace, &todo, Some(&marker), Some(title))
+            .map_err(|e| format!("Could not change the title of the TODO: {}", e))?;
+        self.workspace
+            .commit(
+                &todo.file_path,
+                &format!("Update title of TODO {}", todo.reference()),
+            )
+            .map_err(|e| format!("Could not commit {}: {}", todo.file_path, e))?;
+        (self.report)(Outcome::Done(format!(
+            "TODO {} renamed from '{}' to '{}'",
+            todo.reference(),
+            todo.title,
+            title
+        )));
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+use crate::config;
+use crate::git;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::Gitignore;
+use ignore::WalkBuilder;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+pub const IGNORE_FILE: &str = ".issueferignore";
+
+fn build_globset(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(&config::glob_pattern(pattern))
+            .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// relative_path returns the path relative to the current directory with '/' as separator.
+pub fn relative_path(path: &Path, current_dir: &Path) -> String {
+    let path = path.strip_prefix(current_dir).unwrap_or(path);
+    let path = path.strip_prefix(".").unwrap_or(path);
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// root_dir returns the root of the repository, or the current directory outside of one.
+/// Patterns, i.e., include, exclude and the paths of overrides, match paths relative to it.
+/// Unlike git::toplevel, symlinks in the current directory are not resolved, so the paths of
+/// the files below the current directory start with it.
+pub fn root_dir() -> Result<PathBuf, String> {
+    let current_dir =
+        env::current_dir().map_err(|e| format!("Cannot determine current directory: {}", e))?;
+    Ok(match git::root_from_current_dir() {
+        Ok(cdup) => normalize(&current_dir.join(cdup)),
+        Err(_) => current_dir,
+    })
+}
+
+/// root_relative_path returns the path, absolute or relative to the current directory,
+/// relative to the root with '/' as separator, see root_dir.
+pub fn root_relative_path(path: &Path, root: &Path) -> String {
+    let path = normalize(&env::current_dir().unwrap_or_default().join(path));
+    relative_path(&path, root)
+}
+
+// IgnoreFiles tells whether the .issueferignore and .ignore files of the directories between
+// a file and the root of the repository exclude it, like walk does. As for .gitignore, deeper
+// files take precedence, and in the same directory .issueferignore takes precedence over .ignore.
+struct IgnoreFiles {
+    root: PathBuf,
+    matchers: HashMap<PathBuf, Vec<Gitignore>>,
+    warnings: Vec<String>,
+}
+
+impl IgnoreFiles {
+    fn new(root: PathBuf) -> IgnoreFiles {
+        IgnoreFiles {
+            root,
+            matchers: HashMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn matchers(dir: &Path, warnings: &mut Vec<String>) -> Vec<Gitignore> {
+        let mut matchers = Vec::new();
+        for name in &[IGNORE_FILE, ".ignore"] {
+            let path = dir.join(name);
+            if !path.is_file() {
+                continue;
+            }
+            let (matcher, error) = Gitignore::new(&path);
+            if let Some(e) = error {
+                warnings.push(format!("{}: {}", path.display(), e));
+            }
+            matchers.push(matcher);
+        }
+        matchers
+    }
+
+    // is_ignored takes the absolute path of a file.
+    fn is_ignored(&mut self, path: &Path) -> bool {
+        let mut dir = path.parent();
+        while let Some(current) = dir {
+            let warnings = &mut self.warnings;
+            let matchers = self
+                .matchers
+                .entry(current.to_path_buf())
+                .or_insert_with(|| IgnoreFiles::matchers(current, warnings));
+            for matcher in matchers.iter() {
+                let matched = matcher.matched_path_or_any_parents(path, false);
+                if matched.is_ignore() || matched.is_whitelist() {
+                    return matched.is_ignore();
+                }
+            }
+            if current == self.root {
+                break;
+            }
+            dir = current.parent();
+        }
+        false
+    }
+}
+
+/// SourceFiles are the files to scan.
+pub struct SourceFiles {
+    pub paths: Vec<String>,
+    /// Whether the paths are all files of the repository that can hold TODOs, i.e., no file was
+    /// left out because of a PATH, a pattern, an ignored extension or an ignore file, and no
+    /// untracked file that is not ignored was left out
+    pub complete: bool,
+    /// Problems that did not stop the search, e.g., unreadable directories
+    pub warnings: Vec<String>,
+}
+
+/// get_all_source_code_files returns the files below the given paths, or the current directory
+/// if there are none. Inside a git repository these are the tracked files, even those matching
+/// a .gitignore file, minus the ones excluded by .issueferignore and .ignore files. Outside of a git
+/// repository, or with untracked set, the directories are walked honouring .gitignore, .ignore
+/// and .issueferignore files.
+pub fn get_all_source_code_files(
+    config: &config::GeneralConfig,
+    paths: &[String],
+    untracked: bool,
+) -> Result<SourceFiles, String> {
+    let current_dir =
+        env::current_dir().map_err(|e| format!("Cannot determine current directory: {}", e))?;
+    let include = build_globset(&config.include)?;
+    let exclude = build_globset(&config.exclude)?;
+
+    let root = root_dir()?;
+    let mut warnings = Vec::new();
+    let candidates = if untracked {
+        walk(paths, &current_dir, &mut warnings)
+    } else {
+        match tracked(paths, &current_dir, &mut warnings) {
+            Ok(tracked) => tracked,
+            Err(e) => {
+                warnings.push(format!("{}, scanning all files that are not ignored", e));
+                walk(paths, &current_dir, &mut warnings)
+            }
+        }
+    };
+
+    let mut complete = paths.is_empty() && lists_all_files(&candidates, &current_dir);
+    let mut source_files: Vec<String> = Vec::new();
+    for path in candidates {
+        let root_path = root_relative_path(Path::new(&path), &root);
+        if (!config.include.is_empty() && !include.is_match(&root_path))
+            || exclude.is_match(&root_path)
+        {
+            complete = false;
+            continue;
+        }
+        if let Some(ext) = Path::new(&path).extension() {
+            if config
+                .ignored_extensions
+                .contains(&ext.to_string_lossy().to_string())
+            {
+                complete = false;
+                continue;
+            }
+        }
+        source_files.push(current_dir.join(&path).to_string_lossy().to_string());
+    }
+
+    Ok(SourceFiles {
+        paths: source_files,
+        complete,
+        warnings,
+    })
+}
+
+// lists_all_files tells whether the files, relative to the current directory, are all tracked
+// files and all untracked files that are not ignored of the repository.
+fn lists_all_files(files: &[String], current_dir: &Path) -> bool {
+    // Below the root, the files of the other directories are missing
+    let at_root = match (git::toplevel(), current_dir.canonicalize()) {
+        (Ok(toplevel), Ok(current_dir)) => toplevel == current_dir,
+        _ => false,
+    };
+    if !at_root {
+        return false;
+    }
+    let listed: HashSet<&str> = files.iter().map(String::as_str).collect();
+    match (git::tracked_files(&[]), git::untracked_files()) {
+        (Ok(tracked), Ok(untracked)) => tracked
+            .iter()
+            // Deleted files and submodules are tracked as well
+            .filter(|path| Path::new(path).is_file())
+            .chain(untracked.iter())
+            .all(|path| listed.contains(path.as_str())),
+        _ => false,
+    }
+}
+
+// tracked returns the tracked files below the paths that are not excluded by .issueferignore
+// and .ignore files, relative to the current directory.
+fn tracked(
+    paths: &[String],
+    current_dir: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<String>, String> {
+    let files = git::tracked_files(paths)?;
+    // git reports the root without symlinks, so the current directory has to be resolved too
+    let base = current_dir
+        .canonicalize()
+        .unwrap_or_else(|_| current_dir.to_path_buf());
+    let mut ignore_files = IgnoreFiles::new(git::toplevel()?);
+    let files = files
+        .into_iter()
+        .map(|path| relative_path(Path::new(&path), current_dir))
+        // Deleted files and submodules are tracked as well
+        .filter(|path| current_dir.join(path).is_file())
+        .filter(|path| !ignore_files.is_ignored(&normalize(&base.join(path))))
+        .collect();
+    warnings.append(&mut ignore_files.warnings);
+    Ok(files)
+}
+
+// normalize removes the '.' and '..' components of an absolute path without touching the
+// file system, e.g., for paths given as ../src.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// walk returns the files below the paths that are not ignored, relative to the current
+// directory.
+fn walk(paths: &[String], current_dir: &Path, warnings: &mut Vec<String>) -> Vec<String> {
+    let paths: Vec<String> = if paths.is_empty() {
+        vec![".".to_string()]
+    } else {
+        paths.to_vec()
+    };
+    let mut builder = WalkBuilder::new(&paths[0]);
+    for path in &paths[1..] {
+        builder.add(path);
+    }
+    builder
+        .hidden(false)
+        .require_git(false)
+        .add_custom_ignore_filename(IGNORE_FILE)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .sort_by_file_name(|a, b| a.cmp(b));
+
+    let mut files = Vec::new();
+    for entry in builder.build() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warnings.push(e.to_string());
+                continue;
+            }
+        };
+        if entry.file_type().is_some_and(|t| t.is_file()) {
+            files.push(relative_path(entry.path(), current_dir));
+        }
+    }
+    files
+}
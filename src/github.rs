@@ -1,6 +1,9 @@
-use crate::config;
-use crate::issueapi::{Issue, IssueAPI};
+use crate::http;
+use crate::issueapi::{
+    HttpRequest, Issue, IssueAPI, IssueNumber, IssueUpdates, NewIssue, ReferenceKind, TokenInfo,
+};
 
+use lazy_static::lazy_static;
 use regex::Regex;
 use std::fmt;
 
@@ -24,7 +27,7 @@ pub struct GitHubIssue {
     pub id: i64,
     #[serde(rename = "node_id")]
     pub node_id: String,
-    pub number: i64,
+    pub number: IssueNumber,
     pub title: String,
     pub user: User,
     pub labels: Vec<::serde_json::Value>,
@@ -102,7 +105,7 @@ pub struct CreatedIssue {
     pub id: i64,
     #[serde(rename = "node_id")]
     pub node_id: String,
-    pub number: i64,
+    pub number: IssueNumber,
     pub title: String,
     pub user: User,
     pub labels: Vec<::serde_json::Value>,
@@ -126,40 +129,128 @@ pub struct CreatedIssue {
 }
 
 pub struct GitHubAPI {
-    config: config::GitHubConfig,
+    token: String,
+    api_url: String,
+    graphql_url: String,
     owner: String,
     repo: String,
 }
 
 impl GitHubAPI {
-    pub fn new(config: config::GitHubConfig, owner: String, repo: String) -> GitHubAPI {
+    pub fn new(token: String, host: &str, owner: String, repo: String) -> GitHubAPI {
+        // GitHub Enterprise serves the REST API below /api/v3 on its own host
+        let (api_url, graphql_url) = if host == "github.com" {
+            (
+                "https://api.github.com".to_string(),
+                "https://api.github.com/graphql".to_string(),
+            )
+        } else {
+            (
+                format!("https://{}/api/v3", host),
+                format!("https://{}/api/graphql", host),
+            )
+        };
         GitHubAPI {
-            config,
+            token,
+            api_url,
+            graphql_url,
             owner,
             repo,
         }
     }
 
-    fn get_issues(&self, state: &str) -> Option<Vec<Issue>> {
+    /// with_api_url uses another base URL for the REST API, e.g., a proxy or a test server.
+    /// GraphQL requests go to `/graphql` below it, or to `/api/graphql` for GitHub Enterprise
+    /// style URLs ending in `/api/v3`.
+    pub fn with_api_url(mut self, api_url: &str) -> GitHubAPI {
+        let api_url = api_url.trim_end_matches('/');
+        self.graphql_url = match api_url.strip_suffix("/api/v3") {
+            Some(base) => format!("{}/api/graphql", base),
+            None => format!("{}/graphql", api_url),
+        };
+        self.api_url = api_url.to_string();
+        self
+    }
+
+    fn get_issues(&self, state: &str) -> Result<Vec<Issue>, String> {
         // Doc: https://developer.github.com/v3/issues/#get-an-issue
         let mut request_url = format!(
-            "https://api.github.com/repos/{owner}/{repo}/issues?state={state}",
+            "{api_url}/repos/{owner}/{repo}/issues?state={state}",
+            api_url = self.api_url,
             owner = self.owner,
             repo = self.repo,
             state = state,
         );
         let mut all_issues: Vec<Issue> = Vec::new();
         while !request_url.is_empty() {
-            match get_issues_from_url(&self.config.token, &request_url) {
-                Ok((mut issues, n)) => {
-                    request_url = n;
-                    all_issues.append(&mut issues);
-                }
-                Err(e) => eprintln!("Error getting GitHub issues: {:?}", e),
-            }
+            let (mut issues, next) = get_issues_from_url(&self.token, &request_url)
+                .map_err(|e| format!("Error getting GitHub issues: {}", e))?;
+            request_url = next;
+            all_issues.append(&mut issues);
         }
-        println!("Found {} closed issues on GitHub\n", all_issues.len());
-        Some(all_issues)
+        Ok(all_issues)
+    }
+
+    fn get_issues_since(
+        &self,
+        since: Option<&str>,
+        etag: Option<&str>,
+    ) -> Result<IssueUpdates, String> {
+        let mut request_url = reqwest::Url::parse(&format!(
+            "{api_url}/repos/{owner}/{repo}/issues?state=all&per_page=100",
+            api_url = self.api_url,
+            owner = self.owner,
+            repo = self.repo,
+        ))
+        .map_err(|e| format!("Invalid GitHub API URL: {}", e))?;
+        // Encoded, the + of a time zone offset would be read as a space
+        if let Some(since) = since {
+            request_url.query_pairs_mut().append_pair("since", since);
+        }
+
+        // Only the first page is requested conditionally, a 304 does not count against the
+        // rate limit
+        let mut request = reqwest::blocking::Client::new()
+            .get(request_url)
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("token {token}", token = self.token),
+            )
+            .header(reqwest::header::USER_AGENT, "hyper/0.5.2");
+        if let Some(etag) = etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        let resp = http::send(request, "GitHub")?;
+        if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(IssueUpdates::NotModified);
+        }
+        if !resp.status().is_success() {
+            return Err(http::error(resp, "GitHub"));
+        }
+        let new_etag = resp
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.to_string());
+        let (mut next, _) = parse_link_header(
+            resp.headers()
+                .get("Link")
+                .map(|x| x.to_str().unwrap_or(""))
+                .unwrap_or(""),
+        );
+        let text = resp
+            .text()
+            .map_err(|e| format!("Error reading response body: {}", e))?;
+        let mut all_issues = get_issues_from_response(&text)?;
+        while !next.is_empty() {
+            let (mut issues, n) = get_issues_from_url(&self.token, &next)?;
+            next = n;
+            all_issues.append(&mut issues);
+        }
+        Ok(IssueUpdates::Updated {
+            issues: all_issues,
+            etag: new_etag,
+        })
     }
 }
 
@@ -169,19 +260,17 @@ impl fmt::Display for GitHubAPI {
     }
 }
 
-fn call_github_api(
-    token: &str,
-    request_url: &str,
-) -> std::result::Result<reqwest::blocking::Response, reqwest::Error> {
-    let client = reqwest::blocking::Client::new();
-    client
-        .get(request_url)
-        .header(
-            reqwest::header::AUTHORIZATION,
-            format!("token {token}", token = token),
-        )
-        .header(reqwest::header::USER_AGENT, "hyper/0.5.2")
-        .send()
+fn call_github_api(token: &str, request_url: &str) -> Result<reqwest::blocking::Response, String> {
+    http::send(
+        reqwest::blocking::Client::new()
+            .get(request_url)
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("token {token}", token = token),
+            )
+            .header(reqwest::header::USER_AGENT, "hyper/0.5.2"),
+        "GitHub",
+    )
 }
 
 fn get_issues_from_response(text: &str) -> Result<Vec<Issue>, String> {
@@ -202,6 +291,8 @@ fn get_issues_from_response(text: &str) -> Result<Vec<Issue>, String> {
                     number: github_issue.number,
                     title: github_issue.title,
                     state: github_issue.state,
+                    updated_at: github_issue.updated_at,
+                    body: github_issue.body.as_str().unwrap_or("").to_string(),
                 };
                 issues.push(issue);
             }
@@ -211,9 +302,17 @@ fn get_issues_from_response(text: &str) -> Result<Vec<Issue>, String> {
     }
 }
 
+// graphql_data returns the data of a GraphQL answer, GraphQL reports errors with status 200.
+fn graphql_data(answer: serde_json::Value) -> Result<serde_json::Value, String> {
+    if let Some(errors) = answer.get("errors") {
+        return Err(format!("GitHub GraphQL error: {}", errors));
+    }
+    Ok(answer["data"].clone())
+}
+
 fn parse_link_header(link_header: &str) -> (String, String) {
     lazy_static! {
-        static ref LINK_RE: Regex = Regex::new(r#"(?m)<([\w\.:\?=\&/]+)>; rel="next""#).unwrap();
+        static ref LINK_RE: Regex = Regex::new(r#"(?m)<([^>]+)>; rel="next""#).unwrap();
     }
 
     if let Some(x) = LINK_RE.captures(link_header) {
@@ -227,97 +326,274 @@ fn parse_link_header(link_header: &str) -> (String, String) {
 }
 
 fn get_issues_from_url(token: &str, url: &str) -> Result<(Vec<Issue>, String), String> {
-    let resp = call_github_api(token, url);
-    match resp {
-        Ok(resp) => {
-            if resp.status().is_success() {
-                let (next, last) = parse_link_header(
-                    resp.headers()
-                        .get("Link")
-                        .map(|x| x.to_str().unwrap_or(""))
-                        .unwrap_or(""),
-                );
-                match resp.text() {
-                    Ok(text) => match get_issues_from_response(&text) {
-                        Ok(issues) => {
-                            if url != last && !next.is_empty() {
-                                return Ok((issues, next));
-                            } else {
-                                return Ok((issues, "".to_string()));
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Error parsing issues from API response: {:?}", e);
-                        }
-                    },
-                    Err(e) => {
-                        eprintln!("Error reading response body: {:?}", e);
-                    }
-                }
-            } else if resp.status().is_server_error() {
-                eprintln!("server error!");
-            } else {
-                eprintln!(
-                    "Something else happened. Status: {:?}, Body: {:?}",
-                    resp.status(),
-                    resp.text()
-                );
-            }
-        }
-        Err(e) => {
-            eprintln!("Error in GitHub API request: {:?}", e);
-        }
+    let resp = call_github_api(token, url)?;
+    if !resp.status().is_success() {
+        return Err(http::error(resp, "GitHub"));
+    }
+    let (next, last) = parse_link_header(
+        resp.headers()
+            .get("Link")
+            .map(|x| x.to_str().unwrap_or(""))
+            .unwrap_or(""),
+    );
+    let text = resp
+        .text()
+        .map_err(|e| format!("Error reading response body: {}", e))?;
+    let issues = get_issues_from_response(&text)?;
+    if url != last && !next.is_empty() {
+        Ok((issues, next))
+    } else {
+        Ok((issues, "".to_string()))
     }
-    Err("Some error".to_string())
 }
 
-impl IssueAPI for GitHubAPI {
-    fn repo(&self) -> String {
-        format!("GitHub {}/{}", self.owner, self.repo)
+impl GitHubAPI {
+    fn authorized(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> reqwest::blocking::RequestBuilder {
+        request
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("token {token}", token = self.token),
+            )
+            .header(reqwest::header::USER_AGENT, "hyper/0.5.2")
     }
-    fn get_closed_issues(&self) -> Option<Vec<Issue>> {
-        self.get_issues("closed")
+
+    fn send(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> Result<serde_json::Value, String> {
+        let request = self
+            .authorized(request)
+            .build()
+            .map_err(|e| format!("Error building GitHub API request: {}", e))?;
+        self.execute(request)
     }
-    fn get_issues(&self) -> Option<Vec<Issue>> {
-        self.get_issues("all")
+
+    fn execute(&self, request: reqwest::blocking::Request) -> Result<serde_json::Value, String> {
+        let resp = http::execute(request, "GitHub")?;
+        if !resp.status().is_success() {
+            return Err(http::error(resp, "GitHub"));
+        }
+        resp.json()
+            .map_err(|e| format!("Error parsing json response: {}", e))
+    }
+
+    // graphql sends a GraphQL query and returns its data, deleting issues is only possible
+    // with GraphQL.
+    fn graphql(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        let answer = self.send(self.graphql_request(query, variables))?;
+        graphql_data(answer)
+    }
+
+    fn graphql_request(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> reqwest::blocking::RequestBuilder {
+        reqwest::blocking::Client::new()
+            .post(&self.graphql_url)
+            .json(&serde_json::json!({ "query": query, "variables": variables }))
+    }
+
+    // build_close_issue_request builds the request that closes the issue.
+    fn build_close_issue_request(
+        &self,
+        number: IssueNumber,
+    ) -> Result<reqwest::blocking::Request, String> {
+        // Doc: https://developer.github.com/v3/issues/#update-an-issue
+        self.authorized(
+            reqwest::blocking::Client::new()
+                .patch(&format!(
+                    "{}/repos/{}/{}/issues/{}",
+                    self.api_url, self.owner, self.repo, number
+                ))
+                .json(&serde_json::json!({ "state": "closed" })),
+        )
+        .build()
+        .map_err(|e| format!("Error building GitHub API request: {}", e))
+    }
+
+    // build_delete_issue_request looks up the node id of the issue and builds the request that
+    // deletes it.
+    fn build_delete_issue_request(
+        &self,
+        number: IssueNumber,
+    ) -> Result<reqwest::blocking::Request, String> {
+        // Doc: https://docs.github.com/en/graphql/reference/mutations#deleteissue
+        let data = self.graphql(
+            "query($owner: String!, $repo: String!, $number: Int!) { repository(owner: $owner, name: $repo) { issue(number: $number) { id } } }",
+            serde_json::json!({ "owner": self.owner, "repo": self.repo, "number": number }),
+        )?;
+        let id = data["repository"]["issue"]["id"]
+            .as_str()
+            .ok_or_else(|| format!("Issue #{} not found", number))?;
+        self.authorized(self.graphql_request(
+            "mutation($id: ID!) { deleteIssue(input: {issueId: $id}) { clientMutationId } }",
+            serde_json::json!({ "id": id }),
+        ))
+        .build()
+        .map_err(|e| format!("Error building GitHub API request: {}", e))
     }
 
-    fn create_issue(&self, title: &str) -> Option<Issue> {
-        // TODO (#5): Implement proper error handling when creating GitHub issues
-        let mut issue_body = std::collections::HashMap::new();
-        issue_body.insert("title", title);
+    // build_create_issue_request builds the request that creates the issue.
+    fn build_create_issue_request(
+        &self,
+        new_issue: &NewIssue,
+    ) -> Result<reqwest::blocking::Request, String> {
+        let mut issue_body = serde_json::json!({ "title": new_issue.title });
+        if !new_issue.body.is_empty() {
+            issue_body["body"] = serde_json::json!(new_issue.body);
+        }
+        if !new_issue.labels.is_empty() {
+            issue_body["labels"] = serde_json::json!(new_issue.labels);
+        }
+        if !new_issue.assignees.is_empty() {
+            issue_body["assignees"] = serde_json::json!(new_issue.assignees);
+        }
         let request_url = format!(
-            "https://api.github.com/repos/{owner}/{repo}/issues?state=all",
+            "{api_url}/repos/{owner}/{repo}/issues?state=all",
+            api_url = self.api_url,
             owner = self.owner,
             repo = self.repo
         );
-        let resp = reqwest::blocking::Client::new()
+        reqwest::blocking::Client::new()
             .post(&request_url)
             .json(&issue_body)
             .header(
                 reqwest::header::AUTHORIZATION,
-                format!("token {token}", token = self.config.token),
+                format!("token {token}", token = self.token),
             )
             .header(reqwest::header::USER_AGENT, "hyper/0.5.2")
-            .send()
-            .unwrap();
-        if resp.status().is_success() {
-            let github_issue: CreatedIssue = resp.json().unwrap();
-            let issue = Issue {
-                number: github_issue.number,
-                title: github_issue.title,
-                state: github_issue.state,
-            };
-            return Some(issue);
-        } else if resp.status().is_server_error() {
-            println!("server error!");
+            .build()
+            .map_err(|e| format!("Error building GitHub API request: {}", e))
+    }
+}
+
+impl IssueAPI for GitHubAPI {
+    fn repo(&self) -> String {
+        format!("GitHub {}/{}", self.owner, self.repo)
+    }
+    fn get_closed_issues(&self) -> Result<Vec<Issue>, String> {
+        self.get_issues("closed")
+    }
+    fn get_issues(&self) -> Result<Vec<Issue>, String> {
+        self.get_issues("all")
+    }
+    fn get_issues_since(
+        &self,
+        since: Option<&str>,
+        etag: Option<&str>,
+    ) -> Result<IssueUpdates, String> {
+        GitHubAPI::get_issues_since(self, since, etag)
+    }
+
+    fn reference_kind(&self, number: IssueNumber) -> Result<ReferenceKind, String> {
+        // Doc: https://developer.github.com/v3/issues/#get-an-issue
+        let resp = call_github_api(
+            &self.token,
+            &format!(
+                "{}/repos/{}/{}/issues/{}",
+                self.api_url, self.owner, self.repo, number
+            ),
+        )?;
+        // Deleted issues answer with 410 Gone
+        if resp.status() == reqwest::StatusCode::NOT_FOUND
+            || resp.status() == reqwest::StatusCode::GONE
+        {
+            return Ok(ReferenceKind::Missing);
+        }
+        if !resp.status().is_success() {
+            return Err(http::error(resp, "GitHub"));
+        }
+        let issue: serde_json::Value = resp
+            .json()
+            .map_err(|e| format!("Error parsing json response: {}", e))?;
+        if issue.get("pull_request").is_some() {
+            Ok(ReferenceKind::PullRequest)
         } else {
-            println!(
-                "Something else happened. Status: {:?}, Body: {:?}",
-                resp.status(),
-                resp.text()
-            );
+            Ok(ReferenceKind::Issue)
+        }
+    }
+
+    fn close_issue(&self, number: IssueNumber) -> Result<(), String> {
+        self.execute(self.build_close_issue_request(number)?)
+            .map(|_| ())
+    }
+
+    fn close_issue_request(&self, number: IssueNumber) -> Result<HttpRequest, String> {
+        self.build_close_issue_request(number)
+            .map(|request| HttpRequest::from(&request))
+    }
+
+    fn delete_issue(&self, number: IssueNumber) -> Result<(), String> {
+        self.execute(self.build_delete_issue_request(number)?)
+            .and_then(graphql_data)
+            .map(|_| ())
+    }
+
+    fn delete_issue_request(&self, number: IssueNumber) -> Result<HttpRequest, String> {
+        self.build_delete_issue_request(number)
+            .map(|request| HttpRequest::from(&request))
+    }
+
+    fn token_info(&self) -> Result<TokenInfo, String> {
+        // Doc: https://developer.github.com/v3/users/#get-the-authenticated-user
+        let resp = call_github_api(&self.token, &format!("{}/user", self.api_url))?;
+        if !resp.status().is_success() {
+            return Err(http::error(resp, "GitHub"));
+        }
+        // Classic tokens list their scopes in X-OAuth-Scopes, fine-grained tokens do not send it.
+        let scopes: Option<Vec<String>> = resp
+            .headers()
+            .get("X-OAuth-Scopes")
+            .and_then(|x| x.to_str().ok())
+            .map(|x| {
+                x.split(',')
+                    .map(|scope| scope.trim().to_string())
+                    .filter(|scope| !scope.is_empty())
+                    .collect()
+            });
+        let user: serde_json::Value = resp
+            .json()
+            .map_err(|e| format!("Error parsing json response: {}", e))?;
+        let can_write_issues = scopes.as_ref().map(|s| {
+            s.iter()
+                .any(|scope| scope == "repo" || scope == "public_repo")
+        });
+        Ok(TokenInfo {
+            user: user["login"].as_str().unwrap_or("").to_string(),
+            scopes,
+            can_write_issues,
+        })
+    }
+
+    fn create_issue_request(&self, new_issue: &NewIssue) -> Result<HttpRequest, String> {
+        self.build_create_issue_request(new_issue)
+            .map(|request| HttpRequest::from(&request))
+    }
+
+    fn create_issue(&self, new_issue: &NewIssue) -> Result<Issue, String> {
+        let resp = self
+            .build_create_issue_request(new_issue)
+            .and_then(|request| http::execute(request, "GitHub"))?;
+        if !resp.status().is_success() {
+            return Err(http::error(resp, "GitHub"));
         }
-        None
+        let github_issue = resp
+            .json::<CreatedIssue>()
+            .map_err(|e| format!("Error parsing json response: {}", e))?;
+        Ok(Issue {
+            number: github_issue.number,
+            title: github_issue.title,
+            state: github_issue.state,
+            updated_at: github_issue.updated_at,
+            body: github_issue.body.as_str().unwrap_or("").to_string(),
+        })
     }
 }
@@ -1,5 +1,7 @@
-use crate::config;
-use crate::issueapi::{Issue, IssueAPI};
+use crate::http;
+use crate::issueapi::{
+    HttpRequest, Issue, IssueAPI, IssueNumber, IssueUpdates, NewIssue, ReferenceKind, TokenInfo,
+};
 
 use std::fmt;
 
@@ -10,7 +12,7 @@ use serde::{Deserialize, Serialize};
 #[serde(rename_all = "camelCase")]
 pub struct GitLabIssue {
     pub id: i64,
-    pub iid: i64,
+    pub iid: IssueNumber,
     #[serde(rename = "project_id")]
     pub project_id: i64,
     pub title: String,
@@ -112,7 +114,7 @@ pub struct References {
 #[serde(rename_all = "camelCase")]
 pub struct CreatedIssue {
     pub id: i64,
-    pub iid: i64,
+    pub iid: IssueNumber,
     #[serde(rename = "project_id")]
     pub project_id: i64,
     pub title: String,
@@ -159,19 +161,134 @@ pub struct CreatedIssue {
 }
 
 pub struct GitLabAPI {
-    config: config::GitLabConfig,
+    token: String,
+    api_url: String,
     owner: String,
     repo: String,
 }
 
 impl GitLabAPI {
-    pub fn new(config: config::GitLabConfig, owner: String, repo: String) -> GitLabAPI {
+    pub fn new(token: String, host: String, owner: String, repo: String) -> GitLabAPI {
         GitLabAPI {
-            config,
+            token,
+            api_url: format!("https://{}/api/v4", host),
             owner: owner.replace("/", "%2F"),
             repo,
         }
     }
+
+    /// with_api_url uses another base URL for the API instead of `https://<host>/api/v4`, e.g.,
+    /// a proxy or a test server.
+    pub fn with_api_url(mut self, api_url: &str) -> GitLabAPI {
+        self.api_url = api_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    fn project_url(&self) -> String {
+        format!(
+            "{api_url}/projects/{owner}%2F{repo}",
+            api_url = self.api_url,
+            owner = self.owner,
+            repo = self.repo
+        )
+    }
+}
+
+impl GitLabAPI {
+    fn call_api(&self, request_url: &str) -> Result<serde_json::Value, String> {
+        let resp = http::send(
+            reqwest::blocking::Client::new()
+                .get(request_url)
+                .header("PRIVATE-TOKEN", &self.token)
+                .header(reqwest::header::USER_AGENT, "hyper/0.5.2"),
+            "GitLab",
+        )?;
+        if !resp.status().is_success() {
+            return Err(http::error(resp, "GitLab"));
+        }
+        resp.json()
+            .map_err(|e| format!("Error parsing json response: {}", e))
+    }
+
+    // user_id looks up the id of a user, GitLab assigns issues by id instead of user name.
+    fn user_id(&self, username: &str) -> Result<u64, String> {
+        let mut request_url = reqwest::Url::parse(&format!("{}/users", self.api_url))
+            .map_err(|e| format!("Invalid GitLab API URL: {}", e))?;
+        request_url
+            .query_pairs_mut()
+            .append_pair("username", username);
+        let users = self.call_api(request_url.as_str())?;
+        users
+            .get(0)
+            .and_then(|user| user["id"].as_u64())
+            .ok_or_else(|| format!("GitLab user '{}' does not exist", username))
+    }
+
+    // build_create_issue_request builds the request that creates the issue.
+    fn build_create_issue_request(
+        &self,
+        new_issue: &NewIssue,
+    ) -> Result<reqwest::blocking::Request, String> {
+        let request_url = format!("{}/issues", self.project_url());
+        let assignee_ids = new_issue
+            .assignees
+            .iter()
+            .map(|username| self.user_id(username))
+            .collect::<Result<Vec<u64>, String>>()?;
+        let mut body = serde_json::json!({
+            "title": new_issue.title,
+            "description": new_issue.body,
+            "labels": new_issue.labels.join(","),
+        });
+        if !assignee_ids.is_empty() {
+            body["assignee_ids"] = serde_json::json!(assignee_ids);
+        }
+        reqwest::blocking::Client::new()
+            .post(&request_url)
+            .header("PRIVATE-TOKEN", &self.token)
+            .header(reqwest::header::USER_AGENT, "hyper/0.5.2")
+            .json(&body)
+            .build()
+            .map_err(|e| format!("Error building GitLab API request: {}", e))
+    }
+}
+
+impl GitLabAPI {
+    fn build_close_issue_request(
+        &self,
+        number: IssueNumber,
+    ) -> Result<reqwest::blocking::Request, String> {
+        // Doc: https://docs.gitlab.com/ee/api/issues.html#edit-issue
+        reqwest::blocking::Client::new()
+            .put(&format!("{}/issues/{}", self.project_url(), number))
+            .header("PRIVATE-TOKEN", &self.token)
+            .header(reqwest::header::USER_AGENT, "hyper/0.5.2")
+            .json(&serde_json::json!({"state_event": "close"}))
+            .build()
+            .map_err(|e| format!("Error building GitLab API request: {}", e))
+    }
+
+    fn build_delete_issue_request(
+        &self,
+        number: IssueNumber,
+    ) -> Result<reqwest::blocking::Request, String> {
+        // Doc: https://docs.gitlab.com/ee/api/issues.html#delete-an-issue
+        reqwest::blocking::Client::new()
+            .delete(&format!("{}/issues/{}", self.project_url(), number))
+            .header("PRIVATE-TOKEN", &self.token)
+            .header(reqwest::header::USER_AGENT, "hyper/0.5.2")
+            .build()
+            .map_err(|e| format!("Error building GitLab API request: {}", e))
+    }
+
+    // execute sends a request whose answer is only checked for success.
+    fn execute(&self, request: reqwest::blocking::Request) -> Result<(), String> {
+        let resp = http::execute(request, "GitLab")?;
+        if !resp.status().is_success() {
+            return Err(http::error(resp, "GitLab"));
+        }
+        Ok(())
+    }
 }
 
 impl fmt::Display for GitLabAPI {
@@ -184,83 +301,177 @@ impl IssueAPI for GitLabAPI {
     fn repo(&self) -> String {
         format!("GitLab {}/{}", self.owner, self.repo)
     }
-    fn get_closed_issues(&self) -> Option<Vec<Issue>> {
+    fn get_closed_issues(&self) -> Result<Vec<Issue>, String> {
         self.get_issues()
     }
-    fn get_issues(&self) -> Option<Vec<Issue>> {
-        // TODO (#21): Implement proper error handling when getting issues from GitLab
-        // TODO (#22): Support fetching additional pages of issues from GitLab
+    fn get_issues(&self) -> Result<Vec<Issue>, String> {
+        match self.get_issues_since(None, None) {
+            Ok(IssueUpdates::Updated { issues, .. }) => Ok(issues),
+            Ok(IssueUpdates::NotModified) => Ok(Vec::new()),
+            Err(e) => Err(format!("Error getting GitLab issues: {}", e)),
+        }
+    }
 
-        let request_url = format!(
-            "https://{host}/api/v4/projects/{owner}%2F{repo}/issues",
-            host = self.config.host,
-            owner = self.owner,
-            repo = self.repo
-        );
+    fn get_issues_since(
+        &self,
+        since: Option<&str>,
+        etag: Option<&str>,
+    ) -> Result<IssueUpdates, String> {
+        // Doc: https://docs.gitlab.com/ee/api/issues.html#list-project-issues
+        let mut request_url =
+            reqwest::Url::parse(&format!("{}/issues?per_page=100", self.project_url()))
+                .map_err(|e| format!("Invalid GitLab API URL: {}", e))?;
+        // Encoded, the + of a time zone offset would be read as a space
+        if let Some(since) = since {
+            request_url
+                .query_pairs_mut()
+                .append_pair("updated_after", since);
+        }
         let client = reqwest::blocking::Client::new();
-        let resp = client
-            .get(&request_url)
-            .header("PRIVATE-TOKEN", &self.config.token)
-            .header(reqwest::header::USER_AGENT, "hyper/0.5.2")
-            .send()
-            .unwrap();
-        if resp.status().is_success() {
-            let text = resp.text().unwrap();
-            let deserialized: Vec<GitLabIssue> = serde_json::from_str(&text).unwrap();
-            let mut issues = Vec::new();
+        let mut issues = Vec::new();
+        let mut new_etag = None;
+        let mut page = "1".to_string();
+        while !page.is_empty() {
+            let mut request = client
+                .get(&format!("{}&page={}", request_url, page))
+                .header("PRIVATE-TOKEN", &self.token)
+                .header(reqwest::header::USER_AGENT, "hyper/0.5.2");
+            if let (Some(etag), "1") = (etag, page.as_str()) {
+                request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+            }
+            let resp = http::send(request, "GitLab")?;
+            if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+                return Ok(IssueUpdates::NotModified);
+            }
+            if !resp.status().is_success() {
+                return Err(http::error(resp, "GitLab"));
+            }
+            if page == "1" {
+                new_etag = resp
+                    .headers()
+                    .get(reqwest::header::ETAG)
+                    .and_then(|x| x.to_str().ok())
+                    .map(|x| x.to_string());
+            }
+            page = resp
+                .headers()
+                .get("X-Next-Page")
+                .and_then(|x| x.to_str().ok())
+                .unwrap_or("")
+                .to_string();
+            let deserialized: Vec<GitLabIssue> = resp
+                .json()
+                .map_err(|e| format!("Error parsing json response: {}", e))?;
             for gitlab_issue in deserialized {
-                let issue = Issue {
+                issues.push(Issue {
                     number: gitlab_issue.iid,
                     title: gitlab_issue.title,
                     state: gitlab_issue.state,
-                };
-                issues.push(issue);
+                    updated_at: gitlab_issue.updated_at,
+                    body: gitlab_issue.description.unwrap_or_default(),
+                });
             }
-            return Some(issues);
-        } else if resp.status().is_server_error() {
-            println!("server error!");
-        } else {
-            println!(
-                "Something else happened. Status: {:?}, Body: {:?}",
-                resp.status(),
-                resp.text()
-            );
         }
-        None
+        Ok(IssueUpdates::Updated {
+            issues,
+            etag: new_etag,
+        })
     }
 
-    fn create_issue(&self, title: &str) -> Option<Issue> {
-        // TODO (#23): Implement proper error handling when creating GitLab issues
-        let request_url = format!(
-            "https://{host}/api/v4/projects/{owner}%2F{repo}/issues",
-            host = self.config.host,
-            owner = self.owner,
-            repo = self.repo
-        );
-        let resp = reqwest::blocking::Client::new()
-            .post(&request_url)
-            .header("PRIVATE-TOKEN", &self.config.token)
-            .header(reqwest::header::USER_AGENT, "hyper/0.5.2")
-            .query(&[("title", title)])
-            .send()
-            .unwrap();
-        if resp.status().is_success() {
-            let gitlab_issue: CreatedIssue = resp.json().unwrap();
-            let issue = Issue {
-                number: gitlab_issue.iid,
-                title: gitlab_issue.title,
-                state: gitlab_issue.state,
-            };
-            return Some(issue);
-        } else if resp.status().is_server_error() {
-            println!("server error!");
-        } else {
-            println!(
-                "Something else happened when creating issue with the url '{}'. Status: {:?}",
-                request_url,
-                resp.status()
-            );
+    fn reference_kind(&self, number: IssueNumber) -> Result<ReferenceKind, String> {
+        // Issues and merge requests are numbered independently on GitLab, so an issue is
+        // looked up first
+        let project_url = self.project_url();
+        let status = |url: String| -> Result<reqwest::StatusCode, String> {
+            http::send(
+                reqwest::blocking::Client::new()
+                    .get(&url)
+                    .header("PRIVATE-TOKEN", &self.token)
+                    .header(reqwest::header::USER_AGENT, "hyper/0.5.2"),
+                "GitLab",
+            )
+            .map(|resp| resp.status())
+        };
+        for (path, kind) in [
+            ("issues", ReferenceKind::Issue),
+            ("merge_requests", ReferenceKind::PullRequest),
+        ] {
+            let status = status(format!("{}/{}/{}", project_url, path, number))?;
+            if status.is_success() {
+                return Ok(kind);
+            }
+            if status != reqwest::StatusCode::NOT_FOUND {
+                return Err(format!("GitLab answered with status {}", status));
+            }
+        }
+        Ok(ReferenceKind::Missing)
+    }
+
+    fn close_issue(&self, number: IssueNumber) -> Result<(), String> {
+        self.execute(self.build_close_issue_request(number)?)
+    }
+
+    fn close_issue_request(&self, number: IssueNumber) -> Result<HttpRequest, String> {
+        self.build_close_issue_request(number)
+            .map(|request| HttpRequest::from(&request))
+    }
+
+    fn delete_issue(&self, number: IssueNumber) -> Result<(), String> {
+        self.execute(self.build_delete_issue_request(number)?)
+    }
+
+    fn delete_issue_request(&self, number: IssueNumber) -> Result<HttpRequest, String> {
+        self.build_delete_issue_request(number)
+            .map(|request| HttpRequest::from(&request))
+    }
+
+    fn token_info(&self) -> Result<TokenInfo, String> {
+        // Doc: https://docs.gitlab.com/ee/api/users.html#for-normal-users-1
+        let user = self.call_api(&format!("{}/user", self.api_url))?;
+        // Only personal access tokens can be introspected, other tokens leave the scopes unknown.
+        let scopes: Option<Vec<String>> = self
+            .call_api(&format!("{}/personal_access_tokens/self", self.api_url))
+            .ok()
+            .and_then(|token| {
+                token["scopes"].as_array().map(|scopes| {
+                    scopes
+                        .iter()
+                        .filter_map(|scope| scope.as_str())
+                        .map(|scope| scope.to_string())
+                        .collect()
+                })
+            });
+        let can_write_issues = scopes
+            .as_ref()
+            .map(|s: &Vec<String>| s.iter().any(|scope| scope == "api"));
+        Ok(TokenInfo {
+            user: user["username"].as_str().unwrap_or("").to_string(),
+            scopes,
+            can_write_issues,
+        })
+    }
+
+    fn create_issue_request(&self, new_issue: &NewIssue) -> Result<HttpRequest, String> {
+        self.build_create_issue_request(new_issue)
+            .map(|request| HttpRequest::from(&request))
+    }
+
+    fn create_issue(&self, new_issue: &NewIssue) -> Result<Issue, String> {
+        let resp = self
+            .build_create_issue_request(new_issue)
+            .and_then(|request| http::execute(request, "GitLab"))?;
+        if !resp.status().is_success() {
+            return Err(http::error(resp, "GitLab"));
         }
-        None
+        let gitlab_issue = resp
+            .json::<CreatedIssue>()
+            .map_err(|e| format!("Error parsing json response: {}", e))?;
+        Ok(Issue {
+            number: gitlab_issue.iid,
+            title: gitlab_issue.title,
+            state: gitlab_issue.state,
+            updated_at: gitlab_issue.updated_at,
+            body: gitlab_issue.description.as_str().unwrap_or("").to_string(),
+        })
     }
 }
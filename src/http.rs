@@ -0,0 +1,98 @@
+use reqwest::blocking::{Client, Request, RequestBuilder, Response};
+use reqwest::StatusCode;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// A rate limited request is retried once if the limit resets within this time, otherwise the
+// error tells when to try again
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+// send builds and sends the request, see execute.
+pub(crate) fn send(request: RequestBuilder, provider: &str) -> Result<Response, String> {
+    let request = request
+        .build()
+        .map_err(|e| format!("Error building {} API request: {}", provider, e))?;
+    execute(request, provider)
+}
+
+// execute sends the request. A rate limited request is retried once when the limit resets, if
+// that is soon enough. Other unsuccessful answers are returned as they are, see error.
+pub(crate) fn execute(request: Request, provider: &str) -> Result<Response, String> {
+    let client = Client::new();
+    let retry = request.try_clone();
+    let resp = client
+        .execute(request)
+        .map_err(|e| format!("Error in {} API request: {}", provider, e))?;
+    let wait = match rate_limit_wait(&resp) {
+        Some(wait) => wait,
+        None => return Ok(resp),
+    };
+    match retry {
+        Some(retry) if wait <= MAX_RATE_LIMIT_WAIT => {
+            thread::sleep(wait);
+            client
+                .execute(retry)
+                .map_err(|e| format!("Error in {} API request: {}", provider, e))
+        }
+        _ => Err(format!(
+            "{} rate limit exceeded, try again in {} s",
+            provider,
+            wait.as_secs()
+        )),
+    }
+}
+
+// rate_limit_wait returns how long to wait if the answer says that the rate limit is exceeded.
+// GitHub answers with 403 and X-RateLimit-* headers (or Retry-After for its secondary limits),
+// GitLab with 429 and RateLimit-* headers.
+fn rate_limit_wait(resp: &Response) -> Option<Duration> {
+    let header = |name: &str| {
+        resp.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+    let retry_after = header("Retry-After");
+    let exhausted = header("X-RateLimit-Remaining") == Some(0);
+    let limited = resp.status() == StatusCode::TOO_MANY_REQUESTS
+        || (resp.status() == StatusCode::FORBIDDEN && (exhausted || retry_after.is_some()));
+    if !limited {
+        return None;
+    }
+    if let Some(secs) = retry_after {
+        return Some(Duration::from_secs(secs));
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Some(
+        header("X-RateLimit-Reset")
+            .or_else(|| header("RateLimit-Reset"))
+            .map_or(MAX_RATE_LIMIT_WAIT, |reset| {
+                Duration::from_secs(reset.saturating_sub(now))
+            }),
+    )
+}
+
+// error describes an unsuccessful answer together with the message of its body, if there is
+// one, e.g., "GitHub answered with status 404 Not Found: Not Found".
+pub(crate) fn error(resp: Response, provider: &str) -> String {
+    let status = resp.status();
+    let body: serde_json::Value = resp.json().unwrap_or(serde_json::Value::Null);
+    let mut message = ["message", "error"]
+        .iter()
+        .find_map(|key| match &body[key] {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(message) => Some(message.clone()),
+            message => Some(message.to_string()),
+        });
+    // GitHub lists the fields a request failed on in errors, e.g., for 422 Validation Failed
+    if let (Some(message), Some(errors)) = (&mut message, body["errors"].as_array()) {
+        message.push_str(&format!(" {}", serde_json::Value::from(errors.clone())));
+    }
+    match message {
+        Some(message) => format!("{} answered with status {}: {}", provider, status, message),
+        None => format!("{} answered with status {}", provider, status),
+    }
+}
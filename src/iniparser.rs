@@ -1,5 +1,4 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fmt;
 
 #[derive(Debug, Clone)]
 pub struct Key {
@@ -22,14 +21,18 @@ impl Section {
     pub fn name(&self) -> &str {
         &self.name
     }
+    // get returns the value of the key. If the key occurs more than once the last value wins.
     pub fn get(&self, name: &str) -> Result<&str, String> {
-        for key in &self.keys {
+        for key in self.keys.iter().rev() {
             if key.name == name {
-                return Ok(&key.value());
+                return Ok(key.value());
             }
         }
         Err("Not found".to_string())
     }
+    pub fn keys(&self) -> Vec<&str> {
+        self.keys.iter().map(|key| key.name.as_str()).collect()
+    }
 }
 
 pub struct Ini {
@@ -45,7 +48,7 @@ impl Ini {
     pub fn section(&self, name: &str) -> Result<&Section, String> {
         for section in &self.sections {
             if section.name == name {
-                return Ok(&section);
+                return Ok(section);
             }
         }
         Err("Not found".to_string())
@@ -55,56 +58,423 @@ impl Ini {
     }
 }
 
-pub fn parse_ini_file(file_name: &str) -> Result<Ini, String> {
-    // TODO (#15): Make ini parsing prettier
-    let f = File::open(file_name).expect("Unable to open file");
-    let f = BufReader::new(f);
+// quote_value returns the value in a form that parses back to the same value.
+fn quote_value(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['"', '\\', '#', ';', '\n', '\t']);
+    if !needs_quotes {
+        return value.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
 
-    let mut ini: Ini = Ini::new();
+impl fmt::Display for Ini {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, section) in self.sections.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            if !section.name.is_empty() {
+                writeln!(f, "[{}]", section.name)?;
+            }
+            for key in &section.keys {
+                writeln!(f, "{} = {}", key.name, quote_value(&key.value))?;
+            }
+        }
+        Ok(())
+    }
+}
 
-    let mut section_name: String = "".to_string();
-    let mut keys: Vec<Key> = Vec::new();
-    for line in f.lines() {
-        let line = line.expect("Unable to read line");
-        let trimmed_line = line.trim();
-        if trimmed_line.is_empty() {
-            continue;
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(
+                f,
+                "{}:{}:{}: {}",
+                self.file, self.line, self.column, self.message
+            )
+        }
+    }
+}
+
+// Duplicates controls what happens when a section or a key inside a section appears twice.
+// Git configs legitimately repeat both (e.g., several fetch refspecs), issuefer configs do not.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Duplicates {
+    Reject,
+    Allow,
+}
+
+struct Parser<'a> {
+    file: &'a str,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, column: usize, message: &str) -> ParseError {
+        ParseError {
+            file: self.file.to_string(),
+            line: self.line,
+            column,
+            message: message.to_string(),
+        }
+    }
+
+    // parse_section_header parses '[name]' and returns the name. chars starts at the '['.
+    fn parse_section_header(&self, chars: &[char], offset: usize) -> Result<String, ParseError> {
+        let mut in_quotes = false;
+        let mut end = None;
+        for (i, &c) in chars.iter().enumerate().skip(1) {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                ']' if !in_quotes => {
+                    end = Some(i);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let end = match end {
+            Some(end) => end,
+            None => {
+                return Err(self.error(
+                    offset + chars.len() + 1,
+                    "expected ']' at the end of the section header",
+                ))
+            }
+        };
+        let rest: String = chars[end + 1..].iter().collect();
+        let rest_trimmed = rest.trim_start();
+        if !rest_trimmed.is_empty() && !rest_trimmed.starts_with(['#', ';']) {
+            let column = offset + end + 2 + (rest.len() - rest_trimmed.len());
+            return Err(self.error(column, "unexpected text after section header"));
+        }
+        let name: String = chars[1..end].iter().collect();
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err(self.error(offset + 1, "empty section name"));
+        }
+        Ok(name)
+    }
+
+    // parse_value parses everything after the '='. Double quotes group text that may contain
+    // comment characters or surrounding whitespace, backslash escapes are \" \\ \n \t and \b,
+    // and an unquoted '#' or ';' at the start or after whitespace starts a comment.
+    fn parse_value(&self, chars: &[char], offset: usize) -> Result<String, ParseError> {
+        let mut value = String::new();
+        let mut keep_len = 0;
+        let mut in_quotes = false;
+        let mut quote_column = 0;
+        let mut i = chars.iter().take_while(|c| c.is_whitespace()).count();
+        while i < chars.len() {
+            let c = chars[i];
+            let column = offset + i + 1;
+            match c {
+                '"' => {
+                    in_quotes = !in_quotes;
+                    quote_column = column;
+                }
+                '\\' => {
+                    i += 1;
+                    match chars.get(i) {
+                        Some('"') => value.push('"'),
+                        Some('\\') => value.push('\\'),
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some('b') => value.push('\u{8}'),
+                        Some(_) => return Err(self.error(column, "unknown escape sequence")),
+                        None => {
+                            return Err(self.error(column, "line continuations are not supported"))
+                        }
+                    }
+                    keep_len = value.len();
+                }
+                '#' | ';' if !in_quotes && (value.is_empty() || chars[i - 1].is_whitespace()) => {
+                    break;
+                }
+                _ => {
+                    value.push(c);
+                    if in_quotes || !c.is_whitespace() {
+                        keep_len = value.len();
+                    }
+                }
+            }
+            i += 1;
+        }
+        if in_quotes {
+            return Err(self.error(quote_column, "unterminated quoted value"));
         }
-        let patterns: &[_] = &['#', ';'];
-        if trimmed_line.starts_with(patterns) {
+        value.truncate(keep_len);
+        Ok(value)
+    }
+
+    fn parse_key(&self, chars: &[char], offset: usize) -> Result<Key, ParseError> {
+        let eq = chars.iter().position(|&c| c == '=');
+        let name_end = eq.unwrap_or(chars.len());
+        let name: String = chars[..name_end].iter().collect();
+        let name = name.trim_end().to_string();
+        if name.is_empty() {
+            return Err(self.error(offset + 1, "expected key name before '='"));
+        }
+        if let Some(pos) = name
+            .chars()
+            .position(|c| !(c.is_alphanumeric() || c == '-' || c == '_' || c == '.'))
+        {
+            return Err(self.error(offset + pos + 1, "invalid character in key name"));
+        }
+        match eq {
+            Some(eq) => Ok(Key {
+                name,
+                value: self.parse_value(&chars[eq + 1..], offset + eq + 1)?,
+            }),
+            // A key without a value is a boolean flag in git configs
+            None => Ok(Key {
+                name,
+                value: "true".to_string(),
+            }),
+        }
+    }
+}
+
+// parse_ini_str parses the content of an ini file. file is only used in error messages.
+pub fn parse_ini_str(file: &str, content: &str, duplicates: Duplicates) -> Result<Ini, ParseError> {
+    let mut ini: Ini = Ini::new();
+    let mut parser = Parser { file, line: 0 };
+    let mut current: Option<usize> = None;
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+
+    for (cnt, line) in content.lines().enumerate() {
+        parser.line = cnt + 1;
+        let chars: Vec<char> = line.chars().collect();
+        let offset = chars.iter().take_while(|c| c.is_whitespace()).count();
+        let trimmed = &chars[offset..];
+        if trimmed.is_empty() || trimmed[0] == '#' || trimmed[0] == ';' {
             continue;
         }
-        if line.starts_with('[') && line.ends_with(']') {
-            if !keys.is_empty() {
-                let section: Section = Section {
-                    name: section_name,
-                    keys: keys.clone(),
-                };
-                ini.sections.push(section);
-                keys.clear();
+        if trimmed[0] == '[' {
+            let name = parser.parse_section_header(trimmed, offset)?;
+            if let Some(pos) = ini.sections.iter().position(|s| s.name == name) {
+                if duplicates == Duplicates::Reject {
+                    return Err(parser.error(offset + 1, &format!("duplicate section [{}]", name)));
+                }
+                // Repeated sections continue the existing one, as git does
+                current = Some(pos);
+            } else {
+                ini.sections.push(Section {
+                    name,
+                    keys: Vec::new(),
+                });
+                current = Some(ini.sections.len() - 1);
             }
-            let patterns: &[_] = &['[', ']'];
-            section_name = trimmed_line.trim_matches(patterns).trim().to_string();
         } else {
-            let v: Vec<&str> = trimmed_line.split('=').collect();
-            if v.len() < 2 {
-                println!("Error parsing line '{}'. Skipping it.", trimmed_line);
-                continue;
+            let key = parser.parse_key(trimmed, offset)?;
+            let pos = match current {
+                Some(pos) => pos,
+                None => {
+                    ini.sections.push(Section {
+                        name: "".to_string(),
+                        keys: Vec::new(),
+                    });
+                    ini.sections.len() - 1
+                }
+            };
+            current = Some(pos);
+            let section = &mut ini.sections[pos];
+            if duplicates == Duplicates::Reject && section.keys.iter().any(|k| k.name == key.name) {
+                return Err(parser.error(
+                    offset + 1,
+                    &format!("duplicate key '{}' in section [{}]", key.name, section.name),
+                ));
             }
-            keys.push(Key {
-                name: (*v.get(0).unwrap()).trim().to_string(),
-                value: (*v.get(1).unwrap()).trim().to_string(),
-            });
+            section.keys.push(key);
         }
     }
 
-    if !keys.is_empty() {
-        let section: Section = Section {
-            name: section_name,
-            keys,
-        };
-        ini.sections.push(section);
+    Ok(ini)
+}
+
+pub fn parse_ini_file(file_name: &str, duplicates: Duplicates) -> Result<Ini, ParseError> {
+    match std::fs::read_to_string(file_name) {
+        Ok(content) => parse_ini_str(file_name, &content, duplicates),
+        Err(e) => Err(ParseError {
+            file: file_name.to_string(),
+            line: 0,
+            column: 0,
+            message: format!("Unable to read file: {}", e),
+        }),
     }
+}
 
-    Ok(ini)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Ini, ParseError> {
+        parse_ini_str("config", content, Duplicates::Reject)
+    }
+
+    fn value(ini: &Ini, section: &str, key: &str) -> String {
+        ini.section(section).unwrap().get(key).unwrap().to_string()
+    }
+
+    #[test]
+    fn quoted_values_and_escapes() {
+        let ini = parse(concat!(
+            "[s]\n",
+            "plain =   some value  \n",
+            "spaces = \"  padded  \"\n",
+            "comment_chars = \"a # b ; c\"\n",
+            "escapes = \"say \\\"hi\\\"\\\\\\n\\tdone\"\n",
+            "mixed = pre\"quoted part\"post\n",
+            "empty = \"\"\n",
+            "flag\n",
+        ))
+        .unwrap();
+        assert_eq!(value(&ini, "s", "plain"), "some value");
+        assert_eq!(value(&ini, "s", "spaces"), "  padded  ");
+        assert_eq!(value(&ini, "s", "comment_chars"), "a # b ; c");
+        assert_eq!(value(&ini, "s", "escapes"), "say \"hi\"\\\n\tdone");
+        assert_eq!(value(&ini, "s", "mixed"), "prequoted partpost");
+        assert_eq!(value(&ini, "s", "empty"), "");
+        assert_eq!(value(&ini, "s", "flag"), "true");
+    }
+
+    #[test]
+    fn inline_comments() {
+        let ini = parse(concat!(
+            "# leading comment\n",
+            "; another one\n",
+            "[s] # after the header\n",
+            "a = md;png # docs and images\n",
+            "b = value ; comment\n",
+            "c = #only a comment\n",
+            "d = issue#12\n",
+        ))
+        .unwrap();
+        assert_eq!(value(&ini, "s", "a"), "md;png");
+        assert_eq!(value(&ini, "s", "b"), "value");
+        assert_eq!(value(&ini, "s", "c"), "");
+        assert_eq!(value(&ini, "s", "d"), "issue#12");
+    }
+
+    #[test]
+    fn duplicates() {
+        let keys = "[s]\na = 1\na = 2\n";
+        let e = parse(keys).err().unwrap();
+        assert_eq!((e.line, e.column), (3, 1));
+        assert_eq!(e.message, "duplicate key 'a' in section [s]");
+        let ini = parse_ini_str("config", keys, Duplicates::Allow).unwrap();
+        assert_eq!(value(&ini, "s", "a"), "2");
+
+        let sections = "[s]\na = 1\n[t]\n  [s]\nb = 2\n";
+        let e = parse(sections).err().unwrap();
+        assert_eq!((e.line, e.column), (4, 3));
+        assert_eq!(e.message, "duplicate section [s]");
+        let ini = parse_ini_str("config", sections, Duplicates::Allow).unwrap();
+        assert_eq!(ini.sections().len(), 2);
+        assert_eq!(ini.section("s").unwrap().keys(), ["a", "b"]);
+        assert!(ini.section("t").unwrap().keys().is_empty());
+    }
+
+    #[test]
+    fn empty_sections_and_files() {
+        let ini = parse("[a]\n[b]\n\n[c]\nk = v\n").unwrap();
+        let names: Vec<&str> = ini.sections().iter().map(|s| s.name()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert!(ini.section("a").unwrap().keys().is_empty());
+        assert!(parse("").unwrap().sections().is_empty());
+        assert!(parse("\u{feff}# only comments\n")
+            .unwrap()
+            .sections()
+            .is_empty());
+    }
+
+    #[test]
+    fn error_positions() {
+        for (content, line, column, message) in &[
+            (
+                "[s\n",
+                1,
+                3,
+                "expected ']' at the end of the section header",
+            ),
+            ("[s] x\n", 1, 5, "unexpected text after section header"),
+            ("[ ]\n", 1, 1, "empty section name"),
+            ("[s]\n  = v\n", 2, 3, "expected key name before '='"),
+            ("[s]\nk!y = v\n", 2, 2, "invalid character in key name"),
+            ("[s]\nk = \"open\n", 2, 5, "unterminated quoted value"),
+            ("[s]\nk = a\\qb\n", 2, 6, "unknown escape sequence"),
+            (
+                "[s]\nk = a\\\n",
+                2,
+                6,
+                "line continuations are not supported",
+            ),
+        ] {
+            let e = parse(content).err().unwrap();
+            assert_eq!(
+                (e.line, e.column, e.message.as_str()),
+                (*line, *column, *message),
+                "{:?}",
+                content
+            );
+            assert_eq!(
+                e.to_string(),
+                format!("config:{}:{}: {}", line, column, message)
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let content = concat!(
+            "top = level\n",
+            "[github.com]\n",
+            "token = \"  secret # with ; comment chars\\\\\"\n",
+            "[general]\n",
+            "ignored_extensions = md;png\n",
+            "empty = \"\"\n",
+            "multi = \"a\\nb\\tc \\\"d\\\"\"\n",
+            "[empty]\n",
+        );
+        let ini = parse(content).unwrap();
+        let written = ini.to_string();
+        let again = parse(&written).unwrap();
+        assert_eq!(again.to_string(), written);
+        assert_eq!(again.sections().len(), ini.sections().len());
+        for section in ini.sections() {
+            let other = again.section(section.name()).unwrap();
+            assert_eq!(other.keys(), section.keys());
+            for key in section.keys() {
+                assert_eq!(other.get(key), section.get(key), "{}", key);
+            }
+        }
+        assert_eq!(
+            value(&again, "github.com", "token"),
+            "  secret # with ; comment chars\\"
+        );
+    }
 }
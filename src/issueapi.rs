@@ -1,13 +1,116 @@
-#[derive(Clone)]
+use serde::{Deserialize, Serialize};
+
+/// IssueNumber identifies an issue within a project. TODOs use 0 for "not reported yet".
+pub type IssueNumber = u64;
+
+/// Issue is an issue of a project as the backends return it.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Issue {
-    pub number: i64,
+    pub number: IssueNumber,
     pub title: String,
     pub state: String,
+    pub updated_at: String,
+    #[serde(default)]
+    pub body: String,
+}
+
+/// IssueUpdates is the answer to a request for the issues updated since an earlier request.
+pub enum IssueUpdates {
+    NotModified,
+    /// Issues updated since the given time and the ETag of the answer, if the provider sent one
+    Updated {
+        issues: Vec<Issue>,
+        etag: Option<String>,
+    },
+}
+
+/// ReferenceKind tells what an issue number that is not in the issue list refers to.
+pub enum ReferenceKind {
+    Issue,
+    /// A pull request on GitHub or a merge request on GitLab
+    PullRequest,
+    Missing,
+}
+
+/// NewIssue holds everything needed to create an issue.
+pub struct NewIssue {
+    pub title: String,
+    pub body: String,
+    pub labels: Vec<String>,
+    /// User names of the assignees
+    pub assignees: Vec<String>,
+}
+
+/// HttpRequest is an HTTP request as a backend would send it, e.g., to show it in a dry run.
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+#[cfg(any(feature = "github", feature = "gitlab"))]
+impl From<&reqwest::blocking::Request> for HttpRequest {
+    fn from(request: &reqwest::blocking::Request) -> HttpRequest {
+        HttpRequest {
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            headers: request
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.to_string(),
+                        value.to_str().unwrap_or("<binary>").to_string(),
+                    )
+                })
+                .collect(),
+            body: request
+                .body()
+                .and_then(|b| b.as_bytes())
+                .map(|b| b.to_vec()),
+        }
+    }
+}
+
+/// TokenInfo describes the user a token authenticates as and what it is allowed to do.
+/// scopes and can_write_issues are None when the provider does not report them.
+pub struct TokenInfo {
+    pub user: String,
+    pub scopes: Option<Vec<String>>,
+    pub can_write_issues: Option<bool>,
 }
 
+/// IssueAPI is implemented by the backends of every supported issue tracker.
 pub trait IssueAPI {
-    fn get_issues(&self) -> Option<Vec<Issue>>;
-    fn get_closed_issues(&self) -> Option<Vec<Issue>>;
-    fn create_issue(&self, title: &str) -> Option<Issue>;
+    fn get_issues(&self) -> Result<Vec<Issue>, String>;
+    fn get_closed_issues(&self) -> Result<Vec<Issue>, String>;
+    /// get_issues_since returns the issues in all states that were updated at or after since
+    /// (an ISO 8601 timestamp), or all issues if since is None. If etag is given and nothing
+    /// changed since the request that returned it, NotModified is returned.
+    fn get_issues_since(
+        &self,
+        since: Option<&str>,
+        etag: Option<&str>,
+    ) -> Result<IssueUpdates, String>;
+    fn create_issue(&self, issue: &NewIssue) -> Result<Issue, String>;
+    /// create_issue_request returns the HTTP request create_issue sends, without sending it.
+    fn create_issue_request(&self, issue: &NewIssue) -> Result<HttpRequest, String>;
+    fn close_issue(&self, number: IssueNumber) -> Result<(), String>;
+    /// close_issue_request returns the HTTP request close_issue sends, without sending it.
+    fn close_issue_request(&self, number: IssueNumber) -> Result<HttpRequest, String>;
+    /// delete_issue deletes the issue for good, which usually needs admin rights on the project.
+    fn delete_issue(&self, number: IssueNumber) -> Result<(), String>;
+    /// delete_issue_request returns the HTTP request delete_issue sends to delete the issue,
+    /// without sending it. Requests that only look up the issue may be sent.
+    fn delete_issue_request(&self, number: IssueNumber) -> Result<HttpRequest, String>;
+    /// reference_kind looks up what the number refers to in the project.
+    fn reference_kind(&self, number: IssueNumber) -> Result<ReferenceKind, String>;
+    fn token_info(&self) -> Result<TokenInfo, String>;
     fn repo(&self) -> String;
+    /// take_messages returns and forgets the notes about how issues were fetched since the
+    /// last call, e.g., whether a cache was used, for the caller to show.
+    fn take_messages(&self) -> Vec<String> {
+        Vec::new()
+    }
 }
@@ -0,0 +1,407 @@
+use crate::git;
+use crate::issueapi::{
+    HttpRequest, Issue, IssueAPI, IssueNumber, IssueUpdates, NewIssue, ReferenceKind, TokenInfo,
+};
+
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const ISSUE_CACHE_VERSION: u32 = 2;
+
+// Deleted issues and issues moved to another project are never returned as updated, so all
+// issues are fetched again after this long and replace the cached ones
+const FULL_REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Serialize, Deserialize, Default)]
+struct IssueCacheFile {
+    version: u32,
+    // Latest updated_at of all cached issues, used as since= for the next refresh
+    since: Option<String>,
+    etag: Option<String>,
+    // Seconds since the epoch of the last time all issues were fetched
+    #[serde(default)]
+    refreshed_at: Option<u64>,
+    issues: BTreeMap<IssueNumber, Issue>,
+}
+
+// parse_timestamp returns the seconds and nanoseconds since the epoch of an ISO 8601 time as
+// the providers send it, e.g., 2020-05-17T10:00:00Z or 2020-05-17T12:00:00.000+02:00.
+fn parse_timestamp(timestamp: &str) -> Option<(i64, u32)> {
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = timestamp.get(range)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    let separators = timestamp.as_bytes();
+    if separators.len() < 19
+        || separators[4] != b'-'
+        || separators[7] != b'-'
+        || !matches!(separators[10], b'T' | b't' | b' ')
+        || separators[13] != b':'
+        || separators[16] != b':'
+    {
+        return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+
+    let mut rest = &timestamp[19..];
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        // Only the first 9 digits matter, padded to nanoseconds
+        let value: String = fraction[..digits]
+            .chars()
+            .chain("000000000".chars())
+            .take(9)
+            .collect();
+        nanos = value.parse().ok()?;
+        rest = &fraction[digits..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.get(..1)? {
+                "+" => 1,
+                "-" => -1,
+                _ => return None,
+            };
+            let offset = rest[1..].replace(':', "");
+            if offset.len() != 4 || !offset.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let (hours, minutes): (i64, i64) =
+                (offset[..2].parse().ok()?, offset[2..].parse().ok()?);
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    // Days since 1970-01-01 of the civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some((
+        days * 86400 + hour * 3600 + minute * 60 + second - offset,
+        nanos,
+    ))
+}
+
+// is_later tells whether the time a is after the time b. Times that cannot be parsed are
+// never later than the ones that can.
+fn is_later(a: &str, b: &str) -> bool {
+    match (parse_timestamp(a), parse_timestamp(b)) {
+        (Some(a), Some(b)) => a > b,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// CachedIssueAPI keeps the issues of a project in `<git dir>/issuefer/issues-<host>-<owner>-<repo>.json`
+/// and only fetches the issues that were updated since the last run from the wrapped API.
+/// Without a wrapped API (offline mode) only the cache is used.
+pub struct CachedIssueAPI {
+    inner: Option<Box<dyn IssueAPI>>,
+    name: String,
+    path: Option<PathBuf>,
+    messages: RefCell<Vec<String>>,
+}
+
+impl CachedIssueAPI {
+    pub fn new(
+        inner: Option<Box<dyn IssueAPI>>,
+        host: &str,
+        owner: &str,
+        repo: &str,
+    ) -> CachedIssueAPI {
+        let file_name = format!("issues-{}-{}-{}.json", host, owner, repo)
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        CachedIssueAPI {
+            inner,
+            name: format!("{}/{}", owner, repo),
+            path: git::git_dir()
+                .ok()
+                .map(|dir| dir.join("issuefer").join(file_name)),
+            messages: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn note(&self, message: String) {
+        self.messages.borrow_mut().push(message);
+    }
+
+    fn load(&self) -> IssueCacheFile {
+        let path = match &self.path {
+            Some(path) if path.exists() => path,
+            _ => return IssueCacheFile::default(),
+        };
+        match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                serde_json::from_str::<IssueCacheFile>(&content).map_err(|e| e.to_string())
+            }) {
+            Ok(cache_file) if cache_file.version == ISSUE_CACHE_VERSION => cache_file,
+            Ok(_) => IssueCacheFile::default(),
+            Err(e) => {
+                self.note(format!(
+                    "Warn: Issue cache {} is corrupt ({}), fetching all issues",
+                    path.display(),
+                    e
+                ));
+                IssueCacheFile::default()
+            }
+        }
+    }
+
+    fn save(&self, cache_file: &IssueCacheFile) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let content = serde_json::to_string(cache_file).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, content).map_err(|e| e.to_string())?;
+        fs::rename(&tmp_path, path).map_err(|e| e.to_string())
+    }
+
+    // refresh returns the cached issues merged with the issues updated since the last refresh.
+    fn refresh(&self) -> Result<Vec<Issue>, String> {
+        let mut cache_file = self.load();
+        let inner = match &self.inner {
+            Some(inner) => inner,
+            None => {
+                if cache_file.since.is_none() {
+                    return Err(format!(
+                        "No cached issues for {}, run issuefer once without --offline",
+                        self.name
+                    ));
+                }
+                self.note(format!(
+                    "Offline: using {} cached issues",
+                    cache_file.issues.len()
+                ));
+                return Ok(cache_file.issues.into_values().collect());
+            }
+        };
+
+        let full_refresh = cache_file.refreshed_at.is_none_or(|refreshed_at| {
+            now().saturating_sub(refreshed_at) > FULL_REFRESH_INTERVAL.as_secs()
+        });
+        let (since, etag) = if full_refresh {
+            (None, None)
+        } else {
+            (cache_file.since.as_deref(), cache_file.etag.as_deref())
+        };
+        match inner.get_issues_since(since, etag)? {
+            IssueUpdates::NotModified => {
+                self.note("Issues unchanged since last run".to_string());
+            }
+            IssueUpdates::Updated { issues, etag } => {
+                if full_refresh {
+                    self.note(format!("Fetched all {} issues", issues.len()));
+                    // Issues the provider does not list anymore are dropped
+                    cache_file.issues.clear();
+                    cache_file.since = None;
+                    cache_file.refreshed_at = Some(now());
+                } else {
+                    self.note(format!("Fetched {} updated issues", issues.len()));
+                }
+                for issue in issues {
+                    if cache_file
+                        .since
+                        .as_deref()
+                        .is_none_or(|since| is_later(&issue.updated_at, since))
+                    {
+                        cache_file.since = Some(issue.updated_at.clone());
+                    }
+                    cache_file.issues.insert(issue.number, issue);
+                }
+                cache_file.version = ISSUE_CACHE_VERSION;
+                cache_file.etag = etag;
+                // The cache file is stored even without any issues, so --offline works for
+                // projects without issues, too
+                if cache_file.since.is_none() {
+                    cache_file.since = Some("1970-01-01T00:00:00Z".to_string());
+                }
+                if let Err(e) = self.save(&cache_file) {
+                    self.note(format!("Warn: Could not write issue cache: {}", e));
+                }
+            }
+        }
+        Ok(cache_file.issues.into_values().collect())
+    }
+}
+
+impl IssueAPI for CachedIssueAPI {
+    fn repo(&self) -> String {
+        match &self.inner {
+            Some(inner) => inner.repo(),
+            None => format!("{} (offline)", self.name),
+        }
+    }
+
+    fn get_closed_issues(&self) -> Result<Vec<Issue>, String> {
+        self.get_issues().map(|issues| {
+            issues
+                .into_iter()
+                .filter(|issue| issue.state == "closed")
+                .collect()
+        })
+    }
+
+    fn get_issues(&self) -> Result<Vec<Issue>, String> {
+        self.refresh()
+            .map_err(|e| format!("Error getting issues: {}", e))
+    }
+
+    fn get_issues_since(
+        &self,
+        since: Option<&str>,
+        etag: Option<&str>,
+    ) -> Result<IssueUpdates, String> {
+        match &self.inner {
+            Some(inner) => inner.get_issues_since(since, etag),
+            None => Err("Cannot fetch issues in offline mode".to_string()),
+        }
+    }
+
+    fn reference_kind(&self, number: IssueNumber) -> Result<ReferenceKind, String> {
+        match &self.inner {
+            Some(inner) => inner.reference_kind(number),
+            None => Err("Cannot look up references in offline mode".to_string()),
+        }
+    }
+
+    fn close_issue(&self, number: IssueNumber) -> Result<(), String> {
+        match &self.inner {
+            Some(inner) => inner.close_issue(number),
+            None => Err("Cannot close issues in offline mode".to_string()),
+        }
+    }
+
+    fn close_issue_request(&self, number: IssueNumber) -> Result<HttpRequest, String> {
+        match &self.inner {
+            Some(inner) => inner.close_issue_request(number),
+            None => Err("Cannot close issues in offline mode".to_string()),
+        }
+    }
+
+    fn delete_issue(&self, number: IssueNumber) -> Result<(), String> {
+        match &self.inner {
+            Some(inner) => inner.delete_issue(number),
+            None => Err("Cannot delete issues in offline mode".to_string()),
+        }
+    }
+
+    fn delete_issue_request(&self, number: IssueNumber) -> Result<HttpRequest, String> {
+        match &self.inner {
+            Some(inner) => inner.delete_issue_request(number),
+            None => Err("Cannot delete issues in offline mode".to_string()),
+        }
+    }
+
+    fn token_info(&self) -> Result<TokenInfo, String> {
+        match &self.inner {
+            Some(inner) => inner.token_info(),
+            None => Err("Cannot check the token in offline mode".to_string()),
+        }
+    }
+
+    fn create_issue_request(&self, new_issue: &NewIssue) -> Result<HttpRequest, String> {
+        match &self.inner {
+            Some(inner) => inner.create_issue_request(new_issue),
+            None => Err("Cannot create issues in offline mode".to_string()),
+        }
+    }
+
+    fn create_issue(&self, new_issue: &NewIssue) -> Result<Issue, String> {
+        match &self.inner {
+            Some(inner) => inner.create_issue(new_issue),
+            None => Err("Cannot create issues in offline mode".to_string()),
+        }
+    }
+
+    fn take_messages(&self) -> Vec<String> {
+        self.messages.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_parsed() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some((0, 0)));
+        assert_eq!(
+            parse_timestamp("2000-02-29T12:34:56Z"),
+            Some((951_827_696, 0))
+        );
+        assert_eq!(
+            parse_timestamp("2000-02-29T14:34:56.5+02:00"),
+            Some((951_827_696, 500_000_000))
+        );
+        assert_eq!(
+            parse_timestamp("2000-02-29T07:34:56.123456789123-0500"),
+            Some((951_827_696, 123_456_789))
+        );
+        for invalid in [
+            "",
+            "2000-02-29",
+            "2000-02-29T12:34:56",
+            "2000-13-01T00:00:00Z",
+            "2000-02-29T12:34:56.Z",
+            "2000-02-29T12:34:56+2",
+        ] {
+            assert_eq!(parse_timestamp(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn mixed_formats_are_compared_by_time() {
+        // As strings, '.' sorts before 'Z' and the offset is ignored
+        assert!(is_later("2020-05-17T10:00:00.001Z", "2020-05-17T10:00:00Z"));
+        assert!(is_later(
+            "2020-05-17T10:00:01Z",
+            "2020-05-17T10:00:00.000+00:00"
+        ));
+        assert!(!is_later(
+            "2020-05-17T11:00:00+02:00",
+            "2020-05-17T10:00:00Z"
+        ));
+        assert!(is_later("2020-05-17T10:00:00Z", "garbage"));
+        assert!(!is_later("garbage", "2020-05-17T10:00:00Z"));
+    }
+}
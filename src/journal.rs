@@ -0,0 +1,489 @@
+use crate::git;
+use crate::issueapi::{
+    HttpRequest, Issue, IssueAPI, IssueNumber, IssueUpdates, NewIssue, ReferenceKind, TokenInfo,
+};
+use crate::rewrite;
+use crate::workspace::Workspace;
+
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const JOURNAL_DIR: &str = "journal";
+const ENTRIES_FILE: &str = "entries.jsonl";
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Entry {
+    IssueCreated {
+        host: String,
+        owner: String,
+        repo: String,
+        number: IssueNumber,
+    },
+    // original names the file in the journal directory that holds the content of the file
+    // before the run, it is written once per file. hash is the hash of the new content.
+    FileRewritten {
+        path: PathBuf,
+        original: String,
+        hash: String,
+    },
+    Committed {
+        commit: String,
+        message: String,
+    },
+}
+
+// Journal records everything a run changed in <git dir>/issuefer/journal, so it can be
+// undone. Every change is appended to entries.jsonl as soon as it is made, so the journal
+// survives a crash, and the original content of every changed file is kept next to it. The
+// journal of a run replaces the one of the previous run as soon as the run changes something.
+pub struct Journal {
+    dir: Option<PathBuf>,
+    started: Cell<bool>,
+    originals: RefCell<HashMap<PathBuf, String>>,
+}
+
+fn journal_dir() -> Option<PathBuf> {
+    git::git_dir()
+        .ok()
+        .map(|dir| dir.join("issuefer").join(JOURNAL_DIR))
+}
+
+// content_hash returns the 64 bit FNV-1a hash of the content in hex. It only has to tell
+// whether a file changed after the run, and unlike DefaultHasher it is stable across builds.
+fn content_hash(content: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in content {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+impl Journal {
+    pub fn new() -> Journal {
+        Journal {
+            dir: journal_dir(),
+            started: Cell::new(false),
+            originals: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, entry: Entry) {
+        if let Err(e) = self.append(&entry) {
+            println!("Warn: Could not write journal: {}", e);
+        }
+    }
+
+    // start replaces the journal of the previous run when this run changes something first.
+    fn start(&self, dir: &Path) -> Result<(), String> {
+        if self.started.replace(true) {
+            return Ok(());
+        }
+        if dir.exists() {
+            fs::remove_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))
+    }
+
+    // save_original keeps the content of the file at path before the run changed it and
+    // returns the name to record it under. Only the first change of a file stores it.
+    fn save_original(&self, path: &Path, content: &[u8]) -> Result<String, String> {
+        if let Some(name) = self.originals.borrow().get(path) {
+            return Ok(name.clone());
+        }
+        let name = format!("{}.orig", self.originals.borrow().len() + 1);
+        if let Some(dir) = &self.dir {
+            self.start(dir)?;
+            let original = dir.join(&name);
+            fs::write(&original, content).map_err(|e| format!("{}: {}", original.display(), e))?;
+        }
+        self.originals
+            .borrow_mut()
+            .insert(path.to_path_buf(), name.clone());
+        Ok(name)
+    }
+
+    fn append(&self, entry: &Entry) -> Result<(), String> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        self.start(dir)?;
+        let mut line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        line.push('\n');
+        let path = dir.join(ENTRIES_FILE);
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        file.write_all(line.as_bytes())
+            .and_then(|()| file.sync_data())
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// load returns the entries of the journal of the last run.
+pub fn load() -> Result<Vec<Entry>, String> {
+    let path = journal_dir()
+        .ok_or("Not a git repository")?
+        .join(ENTRIES_FILE);
+    if !path.exists() {
+        return Err("There is no run to undo".to_string());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_entries(&content).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// parse_entries reads one entry per line. A last line without newline was cut off by a crash
+// while it was written, the change it describes was never made and it is ignored.
+fn parse_entries(content: &str) -> Result<Vec<Entry>, String> {
+    let complete = &content[..content.rfind('\n').map_or(0, |i| i + 1)];
+    complete
+        .lines()
+        .enumerate()
+        .map(|(i, line)| serde_json::from_str(line).map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect()
+}
+
+// read_original returns the content a file had before the last run.
+fn read_original(name: &str) -> Result<Vec<u8>, String> {
+    let path = journal_dir().ok_or("Not a git repository")?.join(name);
+    fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// remove deletes the journal once the run has been undone.
+pub fn remove() -> Result<(), String> {
+    match journal_dir() {
+        Some(dir) => fs::remove_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e)),
+        None => Ok(()),
+    }
+}
+
+// JournalingWorkspace records every file change and commit of the wrapped workspace.
+pub struct JournalingWorkspace {
+    inner: Box<dyn Workspace>,
+    journal: Rc<Journal>,
+}
+
+impl JournalingWorkspace {
+    pub fn new(inner: Box<dyn Workspace>, journal: Rc<Journal>) -> JournalingWorkspace {
+        JournalingWorkspace { inner, journal }
+    }
+}
+
+impl Workspace for JournalingWorkspace {
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>, io::Error> {
+        self.inner.read_file(path)
+    }
+
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<(), io::Error> {
+        let full_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let before = self.inner.read_file(path)?;
+        // The original is stored before the file is changed, so a crash cannot lose it
+        let original = self.journal.save_original(&full_path, &before);
+        self.inner.write_file(path, content)?;
+        match original {
+            Ok(original) => self.journal.record(Entry::FileRewritten {
+                path: full_path,
+                original,
+                hash: content_hash(content),
+            }),
+            Err(e) => println!("Warn: Could not write journal: {}", e),
+        }
+        Ok(())
+    }
+
+    fn commit(&self, file_path: &str, message: &str) -> Result<(), String> {
+        let head_before = git::head().ok();
+        let result = self.inner.commit(file_path, message);
+        // Only commits that were actually made are recorded
+        if let Ok(head) = git::head() {
+            if Some(&head) != head_before.as_ref() {
+                self.journal.record(Entry::Committed {
+                    commit: head,
+                    message: message.to_string(),
+                });
+            }
+        }
+        result
+    }
+}
+
+// JournalingAPI records every issue created through the wrapped API.
+pub struct JournalingAPI {
+    inner: Box<dyn IssueAPI>,
+    journal: Rc<Journal>,
+    host: String,
+    owner: String,
+    repo: String,
+}
+
+impl JournalingAPI {
+    pub fn new(
+        inner: Box<dyn IssueAPI>,
+        journal: Rc<Journal>,
+        host: &str,
+        owner: &str,
+        repo: &str,
+    ) -> JournalingAPI {
+        JournalingAPI {
+            inner,
+            journal,
+            host: host.to_string(),
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+        }
+    }
+}
+
+impl IssueAPI for JournalingAPI {
+    fn repo(&self) -> String {
+        self.inner.repo()
+    }
+
+    fn get_issues(&self) -> Result<Vec<Issue>, String> {
+        self.inner.get_issues()
+    }
+
+    fn get_closed_issues(&self) -> Result<Vec<Issue>, String> {
+        self.inner.get_closed_issues()
+    }
+
+    fn get_issues_since(
+        &self,
+        since: Option<&str>,
+        etag: Option<&str>,
+    ) -> Result<IssueUpdates, String> {
+        self.inner.get_issues_since(since, etag)
+    }
+
+    fn reference_kind(&self, number: IssueNumber) -> Result<ReferenceKind, String> {
+        self.inner.reference_kind(number)
+    }
+
+    fn close_issue(&self, number: IssueNumber) -> Result<(), String> {
+        self.inner.close_issue(number)
+    }
+
+    fn delete_issue(&self, number: IssueNumber) -> Result<(), String> {
+        self.inner.delete_issue(number)
+    }
+
+    fn token_info(&self) -> Result<TokenInfo, String> {
+        self.inner.token_info()
+    }
+
+    fn close_issue_request(&self, number: IssueNumber) -> Result<HttpRequest, String> {
+        self.inner.close_issue_request(number)
+    }
+
+    fn delete_issue_request(&self, number: IssueNumber) -> Result<HttpRequest, String> {
+        self.inner.delete_issue_request(number)
+    }
+
+    fn create_issue_request(&self, new_issue: &NewIssue) -> Result<HttpRequest, String> {
+        self.inner.create_issue_request(new_issue)
+    }
+
+    fn create_issue(&self, new_issue: &NewIssue) -> Result<Issue, String> {
+        let issue = self.inner.create_issue(new_issue)?;
+        self.journal.record(Entry::IssueCreated {
+            host: self.host.clone(),
+            owner: self.owner.clone(),
+            repo: self.repo.clone(),
+            number: issue.number,
+        });
+        Ok(issue)
+    }
+
+    fn take_messages(&self) -> Vec<String> {
+        self.inner.take_messages()
+    }
+}
+
+// undo_commits resets the commits of the journal and restores the files they changed. It
+// refuses to do anything unless the commits are still the newest ones on HEAD and every file
+// still has the content the run left behind. In a dry run it only prints what it would do.
+pub fn undo_commits(entries: &[Entry], dry_run: bool) -> Result<(), String> {
+    let commits: Vec<&String> = entries
+        .iter()
+        .filter_map(|e| match e {
+            Entry::Committed { commit, .. } => Some(commit),
+            _ => None,
+        })
+        .collect();
+    if !commits.is_empty() {
+        let last = git::last_commits(commits.len())?;
+        if !last.iter().eq(commits.iter().rev().copied()) {
+            return Err(
+                "The commits of the last run are not at HEAD anymore, undo them manually"
+                    .to_string(),
+            );
+        }
+    }
+
+    // Every file must still look like the run left it before anything is changed
+    let mut last_content: Vec<(&PathBuf, &String, &String)> = Vec::new();
+    for entry in entries.iter() {
+        if let Entry::FileRewritten {
+            path,
+            original,
+            hash,
+        } = entry
+        {
+            match last_content.iter_mut().find(|(p, _, _)| *p == path) {
+                Some(file) => file.2 = hash,
+                None => last_content.push((path, original, hash)),
+            }
+        }
+    }
+    for (path, _, hash) in &last_content {
+        let current = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if &content_hash(&current) != *hash {
+            return Err(format!(
+                "{} changed since the last run, undo manually",
+                path.display()
+            ));
+        }
+    }
+    let mut originals = Vec::new();
+    for (path, original, _) in last_content {
+        originals.push((path, read_original(original)?));
+    }
+
+    if dry_run {
+        if !commits.is_empty() {
+            println!("Dry run: would reset {} commits", commits.len());
+        }
+        for (path, _) in originals {
+            println!("Dry run: would restore {}", path.display());
+        }
+        return Ok(());
+    }
+    if let Some(first) = commits.first() {
+        git::reset_to(&format!("{}^", first))?;
+        println!("Reset {} commits", commits.len());
+    }
+    for (path, content) in originals {
+        rewrite::write_atomically(path, &content)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        println!("Restored {}", path.display());
+    }
+    Ok(())
+}
+
+// undo reverts the last run: it resets its commits, restores the files it changed and closes,
+// or with delete set deletes, the issues it created. A dry run prints the commits it would
+// reset, the files it would restore and the requests it would send.
+pub fn undo(
+    config: &crate::config::Config,
+    delete: bool,
+    force_yes: bool,
+    dry_run: bool,
+) -> Result<(), String> {
+    let entries = load()?;
+    println!("The last run made these changes:");
+    for entry in &entries {
+        match entry {
+            Entry::IssueCreated {
+                owner,
+                repo,
+                number,
+                ..
+            } => println!("    created issue {}/{}#{}", owner, repo, number),
+            Entry::FileRewritten { path, .. } => println!("    changed {}", path.display()),
+            Entry::Committed { commit, message } => {
+                println!(
+                    "    committed {} '{}'",
+                    &commit[..commit.len().min(10)],
+                    message
+                )
+            }
+        }
+    }
+    let question = if delete {
+        "Do you want to undo them and delete the issues?"
+    } else {
+        "Do you want to undo them and close the issues?"
+    };
+    if !dry_run && !force_yes && !crate::ask_yes_no(question) {
+        return Ok(());
+    }
+
+    undo_commits(&entries, dry_run)?;
+
+    let journal = Journal::new();
+    for entry in entries.iter().rev() {
+        if let Entry::IssueCreated {
+            host,
+            owner,
+            repo,
+            number,
+        } = entry
+        {
+            let result =
+                crate::backend::get_repo_api(config, host, owner.clone(), repo.clone(), false)
+                    .and_then(|api| {
+                        let api: Box<dyn IssueAPI> = if dry_run {
+                            Box::new(crate::dryrun::DryRunAPI::new(api, 0))
+                        } else {
+                            api
+                        };
+                        if delete {
+                            api.delete_issue(*number)
+                        } else {
+                            api.close_issue(*number)
+                        }
+                    });
+            match result {
+                Ok(()) if dry_run => {}
+                Ok(()) if delete => println!("Deleted issue {}/{}#{}", owner, repo, number),
+                Ok(()) => println!("Closed issue {}/{}#{}", owner, repo, number),
+                Err(e) if dry_run => return Err(e),
+                Err(e) => {
+                    eprintln!(
+                        "Error: Could not undo issue {}/{}#{}: {}",
+                        owner, repo, number, e
+                    );
+                    // Issues that could not be undone are kept, so undo can be run again
+                    journal.record(entry.clone());
+                }
+            }
+        }
+    }
+    if !dry_run && !journal.started.get() {
+        remove()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cut_off_entries_are_ignored() {
+        let content = "{\"kind\":\"committed\",\"commit\":\"abc\",\"message\":\"Add TODO #1\"}\n\
+                       {\"kind\":\"issue_created\",\"host\":\"github.com\",\"ow";
+        let entries = parse_entries(content).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(matches!(&entries[0], Entry::Committed { commit, .. } if commit == "abc"));
+
+        assert!(parse_entries("{}\n").is_err());
+        assert!(parse_entries("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn content_hashes_tell_contents_apart() {
+        assert_eq!(content_hash(b""), "cbf29ce484222325");
+        assert_eq!(content_hash(b"a"), "af63dc4c8601ec8c");
+        assert_ne!(content_hash(b"ab"), content_hash(b"ba"));
+    }
+}
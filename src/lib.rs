@@ -0,0 +1,74 @@
+//! issuefer turns TODO comments into issues and removes the TODOs again once their issues are
+//! closed. This crate is the library behind the `issuefer` command line tool:
+//!
+//! - [`files`] finds the files to scan and [`scanner`] finds the [`Todo`]s in them, using
+//!   [`todo::TodoParser`] for single lines. With the cargo feature `tree-sitter`, `syntax`
+//!   finds the comments of the main languages, so only those are scanned.
+//! - [`IssueAPI`] is implemented by the backends [`github`] and [`gitlab`], each behind the
+//!   cargo feature of the same name. [`backend`] picks the backend for a host, and
+//!   [`issuecache`] caches the issues of a project.
+//! - [`config`] loads the configuration, i.e., the keywords, file filters and tokens.
+//! - [`sync`] plans a run as a [`sync::SyncPlan`] of actions, e.g., the TODOs to report and
+//!   the TODOs to clean up, which can be saved as JSON and applied later.
+//! - [`workspace`] rewrites TODOs in the sources and commits the changes.
+//!
+//! The library neither prints nor exits. Errors are returned, and warnings and progress notes
+//! are returned as messages for the caller to show, e.g., [`scanner::Scan::warnings`],
+//! [`config::Config::warnings`] and [`IssueAPI::take_messages`].
+//!
+//! ```no_run
+//! use issuefer::{backend, config, files, git, scanner, sync};
+//!
+//! let config = config::load(None)?;
+//! let files = files::get_all_source_code_files(&config.general, &[], false)?;
+//! let todos = scanner::get_all_todos_from_source_code_files(&config.general, &files.paths, None).todos;
+//!
+//! let (host, owner, repo) = git::host_owner_repo("origin")?;
+//! let api = backend::get_repo_api(&config, &host, owner.clone(), repo.clone(), false)?;
+//! let (foreign, _messages) = sync::get_foreign_issues(&config, &host, &todos, false);
+//! let issues = sync::KnownIssues {
+//!     project: api.get_issues()?,
+//!     target: None,
+//!     foreign,
+//! };
+//! let project = sync::Project { host, owner, repo };
+//! let plan = sync::SyncPlan::new(
+//!     project,
+//!     None,
+//!     &todos,
+//!     &issues,
+//!     &config.general,
+//!     sync::PlanOptions::default(),
+//! );
+//! for action in &plan.actions {
+//!     println!("{}", action.describe());
+//! }
+//! println!("{}", plan.to_json()?);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod backend;
+pub mod cache;
+pub mod config;
+pub mod credentials;
+pub mod files;
+pub mod git;
+#[cfg(feature = "github")]
+pub mod github;
+#[cfg(feature = "gitlab")]
+pub mod gitlab;
+#[cfg(any(feature = "github", feature = "gitlab"))]
+mod http;
+mod iniparser;
+pub mod issueapi;
+pub mod issuecache;
+pub mod rewrite;
+pub mod scanner;
+pub mod sync;
+#[cfg(feature = "tree-sitter")]
+pub mod syntax;
+pub mod todo;
+pub mod workspace;
+
+pub use issueapi::{Issue, IssueAPI, IssueNumber, NewIssue};
+pub use todo::Todo;
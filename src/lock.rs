@@ -0,0 +1,327 @@
+use crate::git;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const LOCK_FILE: &str = "lock";
+
+// Locks older than this are stale even if their PID is in use, e.g., by another process after
+// a reboot
+const LOCK_TIMEOUT: Duration = Duration::from_secs(12 * 60 * 60);
+
+lazy_static! {
+    // Paths of the locks held by this process, so release can remove them before exiting
+    static ref HELD: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+}
+
+// RepoLock is an advisory lock in <git dir>/issuefer/lock that is held by runs that change
+// files or issues, so two runs in the same clone cannot report the same TODO twice. The lock
+// file is removed when the lock is dropped.
+pub struct RepoLock {
+    path: PathBuf,
+}
+
+// Holder is the run that holds the lock, as written to the lock file.
+#[derive(PartialEq)]
+struct Holder {
+    pid: u32,
+    started_at: u64,
+    // Empty for locks of older versions and if the name of the machine is unknown
+    host: String,
+}
+
+impl Holder {
+    fn parse(content: &str) -> Option<Holder> {
+        let mut lines = content.lines();
+        Some(Holder {
+            pid: lines.next()?.trim().parse().ok()?,
+            started_at: lines.next()?.trim().parse().ok()?,
+            host: lines.next().unwrap_or_default().trim().to_string(),
+        })
+    }
+
+    fn age(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Duration::from_secs(now.saturating_sub(self.started_at))
+    }
+
+    // A lock is stale once its process is gone or it timed out. The PID of a lock taken on
+    // another machine, e.g., sharing the repository over the network, cannot be checked, so
+    // such a lock is stale as well.
+    fn is_stale(&self, host: &str) -> bool {
+        (!self.host.is_empty() && !host.is_empty() && self.host != host)
+            || self.age() > LOCK_TIMEOUT
+            || !process_is_alive(self.pid)
+    }
+}
+
+// hostname returns the name of this machine, or an empty string if it is unknown.
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| {
+            std::process::Command::new("hostname")
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+        })
+        .map(|host| host.trim().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn process_is_alive(pid: u32) -> bool {
+    let proc_dir = std::path::Path::new("/proc");
+    if proc_dir.exists() {
+        return proc_dir.join(pid.to_string()).exists();
+    }
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map_or(true, |status| status.success())
+}
+
+#[cfg(not(unix))]
+fn process_is_alive(_pid: u32) -> bool {
+    // Without a portable way to check the process, locks are never taken over
+    true
+}
+
+// acquire takes the lock of the repository. It fails with a message naming the holder if
+// another run holds the lock. Stale locks are taken over, see Holder::is_stale.
+pub fn acquire() -> Result<RepoLock, String> {
+    let path = git::git_dir()?.join("issuefer").join(LOCK_FILE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    acquire_at(&path)
+}
+
+// acquire_at takes the lock at path. The lock file is written under a temporary name and
+// linked to path, so it is created atomically and never seen without its holder.
+fn acquire_at(path: &Path) -> Result<RepoLock, String> {
+    let pid = std::process::id();
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let tmp_path = path.with_extension(format!("{}.tmp", pid));
+    fs::File::create(&tmp_path)
+        .and_then(|mut file| {
+            writeln!(file, "{}\n{}\n{}", pid, started_at, hostname())?;
+            file.sync_all()
+        })
+        .map_err(|e| format!("{}: {}", tmp_path.display(), e))?;
+    let result = link_lock(path, &tmp_path);
+    let _ = fs::remove_file(&tmp_path);
+    result
+}
+
+fn link_lock(path: &Path, tmp_path: &Path) -> Result<RepoLock, String> {
+    let host = hostname();
+    // The second attempt follows the takeover of a stale lock
+    for _ in 0..2 {
+        match fs::hard_link(tmp_path, path) {
+            Ok(()) => {
+                HELD.lock().unwrap().push(path.to_path_buf());
+                return Ok(RepoLock {
+                    path: path.to_path_buf(),
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let content = fs::read_to_string(path).unwrap_or_default();
+                match Holder::parse(&content) {
+                    Some(holder) if !holder.is_stale(&host) => {
+                        return Err(format!(
+                            "Another issuefer run (PID {}, started {}s ago) is changing this repository. \
+                             Wait for it to finish. If no other run is active, delete {}",
+                            holder.pid,
+                            holder.age().as_secs(),
+                            path.display()
+                        ))
+                    }
+                    // Locks are written completely, but one of an older version may not be yet
+                    None if recently_modified(path) => {
+                        return Err(format!(
+                            "Another issuefer run is changing this repository. \
+                             Wait for it to finish. If no other run is active, delete {}",
+                            path.display()
+                        ))
+                    }
+                    stale => take_over(path, stale)?,
+                }
+            }
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+    Err(format!(
+        "Could not take the lock {}, another issuefer run started at the same time",
+        path.display()
+    ))
+}
+
+// take_over removes the stale lock at path of the holder. The lock is renamed first, which
+// only one of several runs taking it over at the same time can do. If the renamed lock is not
+// the stale one anymore, another run took it over in between and it is put back.
+fn take_over(path: &Path, stale: Option<Holder>) -> Result<(), String> {
+    let taken_path = path.with_extension(format!("{}.stale", std::process::id()));
+    match fs::rename(path, &taken_path) {
+        Ok(()) => {}
+        // Another run took it over first, the next attempt finds its lock
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    }
+    let taken = Holder::parse(&fs::read_to_string(&taken_path).unwrap_or_default());
+    if taken == stale {
+        println!("Warn: Removed stale lock {}", path.display());
+    } else {
+        // Failing to put it back means yet another run holds the lock now
+        let _ = fs::hard_link(&taken_path, path);
+    }
+    fs::remove_file(&taken_path).map_err(|e| format!("{}: {}", taken_path.display(), e))
+}
+
+fn recently_modified(path: &std::path::Path) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_none_or(|age| age < Duration::from_secs(10))
+}
+
+// release removes the lock held by this process. std::process::exit does not run destructors,
+// so it has to be called before exiting.
+pub fn release() {
+    for path in HELD.lock().unwrap().drain(..) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        let mut held = HELD.lock().unwrap();
+        if let Some(i) = held.iter().position(|path| path == &self.path) {
+            let _ = fs::remove_file(&self.path);
+            held.remove(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn lock_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "issuefer-lock-test-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(LOCK_FILE)
+    }
+
+    // dead_pid returns the PID of a process that has exited.
+    fn dead_pid() -> u32 {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        child.id()
+    }
+
+    #[test]
+    fn stale_holders() {
+        let holder = |age: u64, host: &str| {
+            Holder::parse(&format!(
+                "{}\n{}\n{}\n",
+                std::process::id(),
+                now() - age,
+                host
+            ))
+            .unwrap()
+        };
+        assert!(!holder(0, "here").is_stale("here"));
+        assert!(!holder(7200, "here").is_stale("here"));
+        // Locks of older versions have no host
+        assert!(!holder(0, "").is_stale("here"));
+        // The PID may belong to another process by now
+        assert!(holder(LOCK_TIMEOUT.as_secs() + 60, "here").is_stale("here"));
+        // The PID cannot be checked on another machine
+        assert!(holder(0, "elsewhere").is_stale("here"));
+        assert!(Holder::parse("garbage").is_none());
+    }
+
+    #[test]
+    fn contended_locks_are_refused() {
+        let path = lock_path("contended");
+        let lock = acquire_at(&path).unwrap();
+        let e = acquire_at(&path).err().unwrap();
+        assert!(e.contains(&format!("PID {}", std::process::id())), "{}", e);
+
+        drop(lock);
+        assert!(!path.exists());
+        assert!(acquire_at(&path).is_ok());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_locks_are_taken_over() {
+        let path = lock_path("stale");
+        fs::write(&path, format!("{}\n{}\n", dead_pid(), now() - 60)).unwrap();
+        let lock = acquire_at(&path).unwrap();
+        let holder = Holder::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(holder.pid, std::process::id());
+
+        // Only the temporary files of the takeover are gone
+        drop(lock);
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 0);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn timed_out_locks_are_taken_over() {
+        let path = lock_path("timed-out");
+        let started_at = now() - LOCK_TIMEOUT.as_secs() - 60;
+        fs::write(&path, format!("{}\n{}\n", std::process::id(), started_at)).unwrap();
+        let lock = acquire_at(&path).unwrap();
+        let holder = Holder::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(holder.started_at > started_at);
+        assert_eq!(holder.host, hostname());
+
+        drop(lock);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn locks_taken_over_in_between_are_put_back() {
+        let path = lock_path("put-back");
+        let stale = Holder {
+            pid: dead_pid(),
+            started_at: now() - 60,
+            host: String::new(),
+        };
+        // Another run replaced the stale lock with its own before this one renamed it
+        fs::write(&path, format!("{}\n{}\n", std::process::id(), now())).unwrap();
+        take_over(&path, Some(stale)).unwrap();
+        let holder = Holder::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(holder.pid, std::process::id());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,33 +1,38 @@
 #[macro_use]
 extern crate lazy_static;
-extern crate clap;
-extern crate dirs;
-extern crate regex;
-extern crate reqwest;
 
 use std::error::Error;
-use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::{env, io};
-
-use clap::{App, Arg};
-use regex::Regex;
-
-mod todo;
-use crate::todo::Todo;
-
-mod config;
-mod github;
-mod gitlab;
-mod iniparser;
-
-pub mod issueapi;
-use issueapi::{Issue, IssueAPI};
-
-fn ask_yes_no(question: &str) -> bool {
+use std::rc::Rc;
+use std::{env, fs, io};
+
+use clap::{App, Arg, SubCommand};
+
+use issuefer::issueapi::{IssueAPI, NewIssue};
+use issuefer::sync::{self, SyncPlan};
+use issuefer::todo::{self, Todo};
+use issuefer::workspace::{self, update_file, Workspace};
+use issuefer::{backend, cache, config, files, git, issueapi, rewrite, scanner};
+
+mod doctor;
+mod dryrun;
+mod editor;
+mod executor;
+mod journal;
+mod lock;
+mod pending;
+mod triage;
+mod validate;
+
+// ask repeats the question until it is answered with one of the choices and returns the answer.
+fn ask(question: &str, choices: &[char]) -> char {
+    let choices_text = choices
+        .iter()
+        .map(char::to_string)
+        .collect::<Vec<String>>()
+        .join("/");
     let mut ch = ' ';
-    while ch != 'y' && ch != 'n' {
-        println!("{} [y/n]", question);
+    while !choices.contains(&ch) {
+        println!("{} [{}]", question, choices_text);
         let mut line = String::new();
         if io::stdin().read_line(&mut line).is_ok() {
             line = line.trim().to_string();
@@ -36,381 +41,418 @@ fn ask_yes_no(question: &str) -> bool {
             }
         }
     }
-    if ch == 'y' {
-        return true;
-    }
-
-    false
+    ch
 }
 
-fn get_all_source_code_files(config: &config::GeneralConfig) -> Result<Vec<String>, io::Error> {
-    let mut source_files: Vec<String> = Vec::new();
-
-    let current_dir = env::current_dir()?;
+fn ask_yes_no(question: &str) -> bool {
+    ask(question, &['y', 'n']) == 'y'
+}
 
-    if !config.ignored_extensions.is_empty() {
-        println!(
-            "Files with extensions '{}' will be ignored\n",
-            config.ignored_extensions.join(";")
-        );
-    }
+// get_project_api returns the API of the project the remote points to.
+fn get_project_api(
+    config: &config::Config,
+    remote: &str,
+    offline: bool,
+) -> Result<Box<dyn IssueAPI>, String> {
+    let (host, owner, repo) = git::host_owner_repo(remote)?;
+    println!("Using host: {} owner: {} repo: {}", host, owner, repo);
+    backend::get_repo_api(config, &host, owner, repo, offline)
+}
 
-    {
-        let output = std::process::Command::new("git")
-            .args(&["ls-files"])
-            .output();
-        match output {
-            Ok(_v) => {
-                if _v.status.success() {
-                    for line in String::from_utf8(_v.stdout).unwrap().lines() {
-                        let filename = std::path::Path::new(&line);
-                        if let Some(ext) = filename.extension() {
-                            if config
-                                .ignored_extensions
-                                .contains(&ext.to_str().unwrap().to_string())
-                            {
-                                continue;
-                            }
-                        }
-                        source_files.push(format!(
-                            "{}/{}",
-                            current_dir.to_str().unwrap(),
-                            line.to_string()
-                        ));
-                    }
-                }
-            }
-            Err(e) => println!("Error when executing git ls-files: {:?}", e),
-        }
+// write_api returns the API used to change the repository with the given path, None for the
+// current project. Its changes are journaled, or only shown in a dry run.
+fn write_api(
+    config: &config::Config,
+    project: &sync::Project,
+    repo_path: Option<&str>,
+    offline: bool,
+    dry_run: bool,
+    journal: &Rc<journal::Journal>,
+) -> Result<Box<dyn IssueAPI>, String> {
+    let (owner, repo) = match repo_path {
+        Some(path) => todo::split_repo_path(path)
+            .ok_or_else(|| format!("'{}' is not of the form owner/repo", path))?,
+        None => (project.owner.clone(), project.repo.clone()),
+    };
+    let api = backend::get_repo_api(config, &project.host, owner.clone(), repo.clone(), offline)?;
+    if dry_run {
+        let first_number = fetch_issues(api.as_ref())
+            .ok()
+            .and_then(|issues| issues.iter().map(|i| i.number).max())
+            .unwrap_or(0)
+            + 1;
+        Ok(Box::new(dryrun::DryRunAPI::new(api, first_number)))
+    } else {
+        Ok(Box::new(journal::JournalingAPI::new(
+            api,
+            journal.clone(),
+            &project.host,
+            &owner,
+            &repo,
+        )))
     }
-
-    Ok(source_files)
 }
 
-fn parse_line(file_path: &str, line_number: usize, line: &str) -> Option<Todo> {
-    lazy_static! {
-        static ref TODO_RE: Regex = Regex::new(r"^\s*(//|#)\s*TODO:\s+(.*)$").unwrap();
-        static ref TODO_SEEN_RE: Regex =
-            Regex::new(r"^\s*(//|#)\s*TODO \(#(\d+)\):\s+(.*)$").unwrap();
+// report_outcome shows what happened to an action of a plan.
+fn report_outcome(outcome: executor::Outcome) {
+    match outcome {
+        executor::Outcome::Done(message) => println!("{}", message),
+        executor::Outcome::Warning(message) => println!("Warn: {}", message),
+        executor::Outcome::Failed(message) => eprintln!("Error: {}", message),
     }
+}
 
-    if let Some(x) = TODO_RE.captures(line) {
-        let t = Todo {
-            file_path: file_path.to_string(),
-            line_number,
-            title: x.get(2).map_or("", |m| m.as_str()).to_string(),
-            issue_number: 0,
-        };
-        return Some(t);
-    }
-
-    if let Some(x) = TODO_SEEN_RE.captures(line) {
-        let issue_number = x
-            .get(2)
-            .map_or("0", |m| m.as_str())
-            .to_string()
-            .parse::<u16>()
-            .unwrap();
-        let t = Todo {
-            file_path: file_path.to_string(),
-            line_number,
-            title: x.get(3).map_or("", |m| m.as_str()).to_string(),
-            issue_number,
-        };
-        return Some(t);
+// fetch_issues returns the issues of the project of the API and shows how they were fetched.
+fn fetch_issues(api: &dyn IssueAPI) -> Result<Vec<issueapi::Issue>, String> {
+    let issues = api.get_issues();
+    for message in api.take_messages() {
+        println!("{}\n", message);
     }
-    None
+    issues
 }
 
-fn get_todos_from_source_code_file(source_file: &str) -> Vec<Todo> {
-    let mut todos = Vec::new();
-
-    if let Ok(file) = File::open(source_file) {
-        let f = BufReader::new(file);
-
-        for (cnt, line) in f.lines().enumerate() {
-            if let Ok(content) = line {
-                let result = parse_line(source_file, cnt, &content);
-                if let Some(todo) = result {
-                    todos.push(todo)
-                }
+// skip_pending_creations drops the issues an earlier run may have created already, but that
+// could not be reconciled yet.
+fn skip_pending_creations(plan: &mut SyncPlan) {
+    let pending_creations = pending::load();
+    if pending_creations.is_empty() {
+        return;
+    }
+    let current_dir = env::current_dir().unwrap_or_default();
+    plan.retain(|action| match action {
+        sync::Action::CreateIssue { todo, .. } => {
+            let pending = pending_creations.iter().any(|p| {
+                files::relative_path(std::path::Path::new(&p.file_path), &current_dir) == todo.file
+                    && p.todo.line == todo.line
+            });
+            if pending {
+                println!(
+                    "An issue for '{}' may already exist, see the errors above. Skipping it",
+                    todo.to_todo()
+                );
             }
+            !pending
         }
-    } else {
-        println!("Warn: Could not read file {}", source_file);
-    }
-    todos
+        _ => true,
+    });
 }
 
-fn get_all_todos_from_source_code_files(source_files: &[String]) -> Vec<Todo> {
-    let mut all_todos = Vec::new();
-    for source_file in source_files {
-        let todos = get_todos_from_source_code_file(source_file);
-        all_todos.extend(todos);
+// print_plan lists what a run with -r and -c would change.
+fn print_plan(plan: &SyncPlan) {
+    let todos = |f: fn(&sync::Action) -> bool| -> Vec<Todo> {
+        plan.actions
+            .iter()
+            .filter(|a| f(a))
+            .filter_map(|a| a.todo().map(sync::PlannedTodo::to_todo))
+            .collect()
+    };
+    let unreported = todos(|a| matches!(a, sync::Action::CreateIssue { .. }));
+    if unreported.is_empty() {
+        println!("No unreported TODOs found");
+    } else {
+        println!("Found the following unreported TODOs:");
+        for todo in unreported {
+            println!("{}", todo);
+        }
+        println!("To report them run issuefer with the -r/--report flag");
     }
-    all_todos
-}
-
-fn parse_git_config(url: &str) -> Result<(String, String, String), String> {
-    let re: Regex;
-    if url.starts_with("ssh://") {
-        re = Regex::new(&r"ssh://git@([a-zA-Z.]+):?\d*/(\S+)/(\S+)\.git".to_string()).unwrap();
-    } else if url.starts_with("https://") {
-        re = Regex::new(&"https://(\\S+):?\\d*/(\\S+)/(\\S+)\\.git".to_string()).unwrap();
+    println!();
+    let cleanup = todos(|a| matches!(a, sync::Action::RemoveTodo { .. }));
+    if cleanup.is_empty() {
+        println!("No TODOs to clean up found");
     } else {
-        re = Regex::new(&"git@(\\S+):(\\S+)/(\\S+)\\.git".to_string()).unwrap();
+        println!("Found the following TODOs to clean up:");
+        for todo in cleanup {
+            println!("{}", todo);
+        }
+        println!("To clean them up run issuefer with the -c/--cleanup flag");
     }
-
-    if let Some(x) = re.captures(url) {
-        return Ok((
-            x.get(1).map_or("", |m| m.as_str()).to_string(),
-            x.get(2).map_or("", |m| m.as_str()).to_string(),
-            x.get(3).map_or("", |m| m.as_str()).to_string(),
-        ));
+    let other: Vec<&sync::Action> = plan
+        .actions
+        .iter()
+        .filter(|a| {
+            matches!(
+                a,
+                sync::Action::UpdateTitle { .. } | sync::Action::CloseIssue { .. }
+            )
+        })
+        .collect();
+    if !other.is_empty() {
+        println!("\nFound the following changes to issues and titles:");
+        for action in other {
+            println!("{}", action.describe());
+        }
+        println!("To apply them run issuefer with the -c/--cleanup flag");
     }
-
-    Err("Could not extract origin URL".to_string())
 }
 
-fn get_git_config_host_owner_repo() -> Result<(String, String, String), String> {
-    let current_dir = env::current_dir();
-    if current_dir.is_ok() {
-        let path = format!("{}/.git/config", current_dir.unwrap().to_str().unwrap());
-        if !std::path::Path::new(&path).exists() {
-            return Err(format!(
-                "Could ot open git config {}: Path does not exist",
-                path
-            ));
-        }
-
-        match iniparser::parse_ini_file(&path) {
-            Ok(ini) => {
-                if let Ok(section) = ini.section("remote \"origin\"") {
-                    if let Ok(url) = section.get("url") {
-                        parse_git_config(url)
-                    } else {
-                        Err("The git repo origin remote url does not exist.".to_string())
+// confirm_actions asks for every action of the plan whether to apply it and keeps the confirmed
+// ones. The issues to create can be edited before.
+fn confirm_actions(plan: &mut SyncPlan) {
+    let mut keep = Vec::new();
+    for action in plan.actions.iter_mut() {
+        let confirmed = match action {
+            sync::Action::AnnotateTodo { .. } => true,
+            sync::Action::CreateIssue {
+                todo,
+                title,
+                body,
+                labels,
+                assignees,
+                ..
+            } => {
+                let todo = todo.to_todo();
+                println!("{}", todo);
+                match ask(
+                    "Do you want to report this TODO? (e to edit the issue)",
+                    &['y', 'n', 'e'],
+                ) {
+                    'y' => true,
+                    'e' => {
+                        let issue = NewIssue {
+                            title: title.clone(),
+                            body: body.clone(),
+                            labels: labels.clone(),
+                            assignees: assignees.clone(),
+                        };
+                        match editor::edit_issue(&todo, &issue) {
+                            Ok(Some(issue)) => {
+                                *title = issue.title;
+                                *body = issue.body;
+                                true
+                            }
+                            Ok(None) => {
+                                println!("The title is empty, not reporting the TODO");
+                                false
+                            }
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                false
+                            }
+                        }
                     }
-                } else {
-                    Err("The git repo does not have an origin remote.".to_string())
+                    _ => false,
                 }
             }
-            Err(e) => Err(e),
-        }
-    } else {
-        Err(format!(
-            "Cannot determine current directory: {}",
-            current_dir.err().unwrap()
-        ))
-    }
-}
-
-fn get_project_api(config: &config::Config) -> Result<Box<dyn IssueAPI>, String> {
-    if let Ok((host, owner, repo)) = get_git_config_host_owner_repo() {
-        println!("Using host: {} owner: {} repo: {}", host, owner, repo);
-        if host == "github.com" {
-            if let Some(github_config) = &config.github {
-                return Ok(Box::new(github::GitHubAPI::new(
-                    github_config.clone(),
-                    owner,
-                    repo,
-                )));
+            sync::Action::RemoveTodo { todo } => {
+                println!("{}", todo.to_todo());
+                ask_yes_no("Do you want to remove this TODO?")
             }
-        } else if host == "gitlab.com" {
-            for c in &config.gitlab {
-                if c.host == "" {
-                    return Ok(Box::new(gitlab::GitLabAPI::new(c.clone(), owner, repo)));
-                }
+            sync::Action::UpdateTitle { .. } => {
+                println!("{}", action.describe());
+                ask_yes_no("Do you want to change the title of this TODO?")
             }
-        } else {
-            for c in &config.gitlab {
-                if c.host == host {
-                    return Ok(Box::new(gitlab::GitLabAPI::new(c.clone(), owner, repo)));
-                }
+            sync::Action::CloseIssue { .. } => {
+                println!("{}", action.describe());
+                ask_yes_no("Do you want to close this issue?")
             }
-        }
+        };
+        keep.push(confirmed);
     }
-
-    Err("No valid GitHub or GitLab remote origin found or token not specified. Check README.md how to set up issuefer.".to_string())
+    let mut keep = keep.into_iter();
+    plan.retain(|_| keep.next().unwrap_or(false));
 }
 
-// find_issue_by_title searches a list of issues by title and returns true if it finds an issue.
-fn find_issue_by_title(issues: &[Issue], title: &str) -> bool {
-    if let Some(_issue) = issues.iter().find(|&x| x.title == title) {
-        return true;
-    }
-    false
+// load_plan reads a plan saved by `issuefer plan`.
+fn load_plan(file: &str) -> Result<SyncPlan, String> {
+    let json = fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+    SyncPlan::from_json(&json).map_err(|e| format!("{}: {}", file, e))
 }
 
-// find_issue_by_number searches a list of issues by issue number and returns a copy if it finds it.
-fn find_issue_by_number(issues: &[Issue], number: i64) -> Option<Issue> {
-    if let Some(issue) = issues.iter().find(|&x| x.number == number) {
-        return Some(issue.clone());
+// ignore_todo marks the TODO as ignored, i.e., "TODO (II):", so it is never reported.
+// scan_sources finds the files to scan and the TODOs in them, printing the filters and the
+// problems found on the way.
+fn scan_sources(
+    config: &config::GeneralConfig,
+    paths: &[String],
+    untracked: bool,
+    no_cache: bool,
+) -> Result<(files::SourceFiles, scanner::Scan), String> {
+    if !config.ignored_extensions.is_empty() {
+        println!(
+            "Files with extensions '{}' will be ignored\n",
+            config.ignored_extensions.join(";")
+        );
     }
-    None
+    if !config.include.is_empty() {
+        println!(
+            "Only files matching '{}' will be scanned\n",
+            config.include.join(";")
+        );
+    }
+    if !config.exclude.is_empty() {
+        println!(
+            "Files matching '{}' will be ignored\n",
+            config.exclude.join(";")
+        );
+    }
+    let source_files = files::get_all_source_code_files(config, paths, untracked)?;
+    for warning in &source_files.warnings {
+        println!("Warn: {}", warning);
+    }
+    // Entries of files left out of the run are kept for later runs
+    let cache = if no_cache {
+        None
+    } else {
+        cache::ScanCache::load(source_files.complete)
+    };
+    let scan = scanner::get_all_todos_from_source_code_files(config, &source_files.paths, cache);
+    for warning in &scan.warnings {
+        println!("Warn: {}", warning);
+    }
+    if scan.binary_files > 0 || scan.large_files > 0 {
+        println!(
+            "Skipped {} binary files and {} files larger than {} bytes\n",
+            scan.binary_files, scan.large_files, config.max_file_size
+        );
+    }
+    Ok((source_files, scan))
 }
 
-fn compare_todos_and_issues(todos: &[Todo], issues: &[Issue]) -> Vec<Todo> {
-    let mut todos_to_create: Vec<Todo> = Vec::new();
-
-    for todo in todos {
-        if todo.issue_number == 0 && !find_issue_by_title(issues, &todo.title) {
-            todos_to_create.push(todo.clone());
+fn ignore_todo(workspace: &dyn Workspace, todo: &Todo) {
+    match update_file(
+        workspace,
+        todo,
+        Some(&todo::format_marker(&todo.keyword, Some(todo::IGNORED))),
+        None,
+    ) {
+        Ok(()) => {
+            if let Err(e) =
+                workspace.commit(&todo.file_path, &format!("Ignore TODO '{}'", todo.title))
+            {
+                println!("Warn: Could not commit {}: {}", todo.file_path, e);
+            }
+            println!("TODO '{}' is ignored from now on", todo.title);
         }
+        Err(e) => eprintln!("Error: Could not mark the TODO as ignored: {}", e),
     }
-
-    todos_to_create
 }
 
-fn commit(file_path: &str, message: &str) {
-    {
-        let output = std::process::Command::new("git")
-            .args(&["add", file_path])
-            .output();
-        match output {
-            Ok(_v) => {}
-            Err(e) => println!("Error when executing git add: {:?}", e),
-        }
+// fix_reference changes the reference of a TODO as validate::fix_reference and shows the result.
+fn fix_reference(workspace: &dyn Workspace, todo: &Todo, new_number: Option<u64>) {
+    match validate::fix_reference(workspace, todo, new_number) {
+        Ok(message) => println!("{}", message),
+        Err(e) => eprintln!("Error: {}", e),
     }
+}
 
-    {
-        let output = std::process::Command::new("git")
-            .args(&["commit", "-m", message])
-            .output();
-        match output {
-            Ok(_v) => {}
-            Err(e) => println!("Error when executing git commit: {:?}", e),
+// fix_dangling offers to point the TODO at an issue with the same title, if there is one, or
+// to remove the reference.
+fn fix_dangling(
+    workspace: &dyn Workspace,
+    todo: &Todo,
+    issues: &[issueapi::Issue],
+    force_yes: bool,
+) {
+    if let Some(issue) = issues.iter().find(|i| i.title == todo.title) {
+        if force_yes
+            || ask_yes_no(&format!(
+                "Issue #{} has the same title, reference it instead?",
+                issue.number
+            ))
+        {
+            fix_reference(workspace, todo, Some(issue.number));
+            return;
         }
     }
-}
-
-fn commit_add(file_path: &str, issue_number: i64) {
-    commit(file_path, &format!("Add TODO #{}", issue_number));
-}
-
-fn commit_delete(file_path: &str, issue_number: u16) {
-    commit(file_path, &format!("Remove TODO #{}", issue_number));
-}
-
-fn update_file(todo: &Todo, issue_number: i64, delete: bool) -> Result<(), io::Error> {
-    let output_file_path = format!("{}.issufer", &todo.file_path);
+    if force_yes
+        || ask_yes_no("Do you want to remove the reference, so the TODO is reported again?")
     {
-        let input_file = File::open(&todo.file_path)?;
-        let reader = BufReader::new(input_file);
-        let output_file = File::create(&output_file_path)?;
-        let mut writer = BufWriter::new(output_file);
-        for (cnt, line) in reader.lines().enumerate() {
-            let line = line?;
-            if delete {
-                let trimmed = line.trim();
-                if trimmed.starts_with(&format!("// TODO (#{}):", todo.issue_number)) {
-                    continue;
-                } else {
-                    writeln!(writer, "{}", line)?;
-                }
-            } else if cnt == todo.line_number {
-                let new_line = line.replace("// TODO:", &format!("// TODO (#{}):", issue_number));
-                writeln!(writer, "{}", new_line)?;
-            } else {
-                writeln!(writer, "{}", line)?;
-            }
-        }
+        fix_reference(workspace, todo, None);
     }
-
-    std::fs::rename(&output_file_path, &todo.file_path)?;
-
-    Ok(())
 }
 
-fn create_github_issues_from_todos(
-    api: Box<dyn IssueAPI>,
-    todos_to_create: &[Todo],
+// validate prints all problems with issue references and, with fix set, offers to fix them.
+// It returns true if no problems were found.
+fn validate(
+    api: &dyn IssueAPI,
+    workspace: &dyn Workspace,
+    todos: &[Todo],
+    issues: &[issueapi::Issue],
+    fix: bool,
     force_yes: bool,
-) {
-    if todos_to_create.is_empty() {
-        return;
+) -> bool {
+    let problems = validate::find_problems(api, todos, issues);
+    if problems.is_empty() {
+        println!("All issue references are valid");
+        return true;
     }
-    println!("Found the following unreported TODOs:");
-    for todo in todos_to_create {
-        println!("{}", todo);
-        if force_yes || ask_yes_no("Do you want to report this TODO?") {
-            if let Some(new_issue) = api.create_issue(&todo.title) {
-                update_file(&todo, new_issue.number, false).unwrap();
-                commit_add(&todo.file_path, new_issue.number);
+    for problem in &problems {
+        match problem {
+            validate::Problem::Dangling(todo) => {
+                println!("{}\n    #{} does not exist", todo, todo.issue_number);
+                if fix {
+                    fix_dangling(workspace, todo, issues, force_yes);
+                }
+            }
+            validate::Problem::PullRequest(todo) => {
                 println!(
-                    "Issue #{} with title '{}' created successfully",
-                    new_issue.number, new_issue.title
+                    "{}\n    #{} is a pull request, not an issue",
+                    todo, todo.issue_number
                 );
-            } else {
-                println!("Could not create new issue for '{}'", todo);
+                if fix
+                    && (force_yes
+                        || ask_yes_no(
+                            "Do you want to remove the reference, so the TODO is reported again?",
+                        ))
+                {
+                    fix_reference(workspace, todo, None);
+                }
             }
-        }
-    }
-}
-
-fn remove_todos(todos_to_remove: &[Todo], force_yes: bool) {
-    if todos_to_remove.is_empty() {
-        return;
-    }
-    println!("Found the following TODOs to remove:");
-    for todo in todos_to_remove {
-        println!("{}", todo);
-        if force_yes || ask_yes_no("Do you want to remove this TODO?") {
-            update_file(&todo, 0, true).unwrap();
-            commit_delete(&todo.file_path, todo.issue_number);
-            println!(
-                "Todo to issue #{} with title '{}' removed successfully",
-                todo.issue_number, todo.title
-            );
-        }
-    }
-}
-
-fn find_todos_to_cleanup(todos: &[Todo], issues: &[Issue]) -> Vec<Todo> {
-    let mut todos_to_cleanup: Vec<Todo> = Vec::new();
-
-    for todo in todos {
-        if todo.issue_number > 0 {
-            if let Some(issue) = find_issue_by_number(issues, todo.issue_number as i64) {
-                if issue.state == "closed" {
-                    todos_to_cleanup.push(todo.clone());
+            validate::Problem::Unknown(todo, e) => {
+                println!(
+                    "{}\n    #{} is not in the issue list and could not be looked up: {}",
+                    todo, todo.issue_number, e
+                );
+            }
+            validate::Problem::ConflictingTitles(number, referencing) => {
+                let issue_title = issues
+                    .iter()
+                    .find(|i| i.number == *number)
+                    .map_or("", |i| i.title.as_str());
+                println!(
+                    "#{} '{}' is referenced by TODOs with different titles:",
+                    number, issue_title
+                );
+                for todo in referencing {
+                    println!("    {}", todo);
+                }
+                if !fix {
+                    continue;
+                }
+                for todo in referencing
+                    .iter()
+                    .filter(|t| validate::differs_from_issue(t, issue_title))
+                {
+                    println!("{}", todo);
+                    if force_yes || ask_yes_no("The title differs from the issue, remove the reference so the TODO is reported as a new issue?") {
+                        fix_reference(workspace, todo, None);
+                    }
                 }
             }
         }
     }
-
-    todos_to_cleanup
+    if !fix {
+        println!("\nTo fix them interactively run 'issuefer validate --fix'");
+    }
+    false
 }
 
-fn get_default_config_locations() -> Vec<std::path::PathBuf> {
-    let mut config_paths: Vec<std::path::PathBuf> = Vec::new();
-    if let Some(config_dir) = dirs::config_dir() {
-        config_paths.push(config_dir.join("issuefer"));
-    }
-    if let Some(config_dir) = dirs::home_dir() {
-        config_paths.push(config_dir.join(".issuefer"));
-    }
-    config_paths
+// exit releases the repository lock and exits the process.
+fn exit(code: i32) -> ! {
+    lock::release();
+    std::process::exit(code)
 }
 
-fn get_config() -> Option<config::Config> {
-    let mut config: Option<config::Config> = None;
-    for location in get_default_config_locations() {
-        if location.exists() {
-            if let Ok(new_config) = config::Config::from_file(&location) {
-                println!("Using config from {}", location.to_str().unwrap());
-                config = Some(new_config);
-                break;
-            }
-        }
-    }
-    if config.is_none() {
-        if let Ok(new_config) = config::Config::from_env() {
-            println!("Using config from environment");
-            config = Some(new_config);
+// take_lock takes the repository lock or exits if another run holds it.
+fn take_lock() -> lock::RepoLock {
+    match lock::acquire() {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
         }
     }
-    config
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -436,6 +478,130 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .long("force-yes")
                 .help("Answer every question with yes (e.g., report all TODOs as issues)"),
         )
+        .arg(
+            Arg::with_name("remote")
+                .long("remote")
+                .takes_value(true)
+                .value_name("NAME")
+                .help("Git remote to report issues to (default: origin or [general] remote from config)"),
+        )
+        .arg(
+            Arg::with_name("untracked")
+                .short("u")
+                .long("untracked")
+                .help("Also scan files that are not tracked by git but not ignored"),
+        )
+        .arg(
+            Arg::with_name("no-cache")
+                .long("no-cache")
+                .help("Scan all files instead of reusing the results for unchanged files"),
+        )
+        .arg(
+            Arg::with_name("target-repo")
+                .long("target-repo")
+                .takes_value(true)
+                .value_name("OWNER/REPO")
+                .help("Report new TODOs to another repository on the same host, e.g., group/subgroup/project"),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Show the file changes, API requests and commits instead of making them"),
+        )
+        .arg(
+            Arg::with_name("no-tui")
+                .long("no-tui")
+                .help("Ask about every TODO line by line instead of showing the terminal UI"),
+        )
+        .arg(
+            Arg::with_name("sync-titles")
+                .long("sync-titles")
+                .help("With -c, also change the titles of TODOs whose open issue was renamed"),
+        )
+        .arg(
+            Arg::with_name("close-orphaned")
+                .long("close-orphaned")
+                .help("With -c, also close the open issues created by issuefer whose TODO is gone (needs a scan of all files)"),
+        )
+        .arg(
+            Arg::with_name("offline")
+                .long("offline")
+                .help("Use only the cached issues of the last run, do not contact the host"),
+        )
+        .arg(
+            Arg::with_name("include")
+                .long("include")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("GLOB")
+                .help("Only scan files matching GLOB, relative to the repository root"),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("GLOB")
+                .help("Do not scan files matching GLOB, relative to the repository root"),
+        )
+        .arg(
+            Arg::with_name("PATH")
+                .multiple(true)
+                .help("Files or directories to scan (default: current directory)"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Read the user config from FILE instead of the default locations"),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Shows the effective configuration and where each value came from"),
+        )
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("Checks that the issue references of all TODOs point at matching issues")
+                .arg(
+                    Arg::with_name("fix")
+                        .long("fix")
+                        .help("Offer to fix every problem found"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("undo")
+                .about("Reverts the last run: resets its commits, restores the files and closes the created issues")
+                .arg(
+                    Arg::with_name("delete")
+                        .long("delete")
+                        .help("Delete the created issues instead of closing them (needs admin rights)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("plan")
+                .about("Writes what a run with -r and -c would change to FILE, e.g., to review it before applying it")
+                .arg(
+                    Arg::with_name("FILE")
+                        .required(true)
+                        .help("File to write the plan to, as JSON"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("Applies a plan written by issuefer plan")
+                .arg(
+                    Arg::with_name("FILE")
+                        .required(true)
+                        .help("The plan to apply"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Checks the git remote, configuration and token and suggests fixes"),
+        )
         .get_matches();
 
     let report = matches.is_present("report");
@@ -444,64 +610,354 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     println!("IssueFER v0.1.0\n");
 
-    let config = get_config();
-    if config.is_none() {
-        eprintln!("No configuration found. See README.md for details on how to set up issuefer.");
-        std::process::exit(1);
+    let config = config::load(matches.value_of("config").map(std::path::Path::new));
+    if let Ok(config) = &config {
+        for warning in &config.warnings {
+            println!("Warning: {}", warning);
+        }
+        for layer in &config.layers {
+            println!("Using {}", layer);
+        }
     }
 
-    let config_value = config.unwrap();
+    let remote = match matches.value_of("remote") {
+        Some(remote) => remote.to_string(),
+        None => config
+            .as_ref()
+            .map_or("origin".to_string(), |c| c.general.remote.clone()),
+    };
 
-    let api: Box<dyn IssueAPI>;
-    match get_project_api(&config_value) {
-        Ok(new_api) => {
-            api = new_api;
+    if matches.subcommand_matches("doctor").is_some() {
+        println!();
+        if !doctor::run(&config, &remote) {
+            exit(1);
         }
+        return Ok(());
+    }
+
+    let mut config_value = match config {
+        Ok(config) => config,
         Err(e) => {
-            eprintln!("Could not determine host from git config: {}", e);
-            std::process::exit(1);
+            eprintln!(
+                "{}. See README.md for details on how to set up issuefer.",
+                e
+            );
+            exit(1);
+        }
+    };
+
+    if matches.subcommand_matches("config").is_some() {
+        println!();
+        for (name, value, source) in config_value.describe() {
+            println!("{} = {}\n    from {}", name, value, source);
         }
+        return Ok(());
     }
 
-    println!("IssueFER running for {}\n", api.repo());
+    if let Some(undo_matches) = matches.subcommand_matches("undo") {
+        println!();
+        let _lock = take_lock();
+        if let Err(e) = journal::undo(
+            &config_value,
+            undo_matches.is_present("delete"),
+            force_yes,
+            matches.is_present("dry-run"),
+        ) {
+            eprintln!("{}", e);
+            exit(1);
+        }
+        return Ok(());
+    }
 
-    let source_files = get_all_source_code_files(&config_value.general)?;
-    let source_code_todos = get_all_todos_from_source_code_files(&source_files);
-
-    let github_issues = api.get_closed_issues();
-    if let Some(issues) = github_issues {
-        let compared_todos_and_issues = compare_todos_and_issues(&source_code_todos, &issues);
-        if compared_todos_and_issues.is_empty() {
-            println!("No unreported TODOs found");
-        } else if report {
-            create_github_issues_from_todos(api, &compared_todos_and_issues, force_yes);
-        } else {
-            println!("Found the following unreported TODOs:");
-            for todo in compared_todos_and_issues {
+    if let Some(include) = matches.values_of("include") {
+        config_value
+            .general
+            .include
+            .extend(include.map(|p| p.to_string()));
+    }
+    if let Some(exclude) = matches.values_of("exclude") {
+        config_value
+            .general
+            .exclude
+            .extend(exclude.map(|p| p.to_string()));
+    }
+
+    let paths: Vec<String> = matches
+        .values_of("PATH")
+        .map_or(Vec::new(), |v| v.map(|p| p.to_string()).collect());
+    let untracked = matches.is_present("untracked");
+    let no_cache = matches.is_present("no-cache");
+
+    let offline = matches.is_present("offline");
+    let dry_run = matches.is_present("dry-run");
+    let fix = matches
+        .subcommand_matches("validate")
+        .is_some_and(|m| m.is_present("fix"));
+    let planning = matches.subcommand_matches("plan").is_some();
+    let apply_matches = matches.subcommand_matches("apply");
+    // Whether the run reports TODOs or cleans them up, plan only writes what it would do
+    let changing = ((report || cleanup) && !planning) || apply_matches.is_some();
+    // Runs that change files or issues must not overlap, read-only runs can
+    let _lock = if (changing || fix) && !dry_run {
+        Some(take_lock())
+    } else {
+        None
+    };
+    let journal = Rc::new(journal::Journal::new());
+    let workspace: Box<dyn Workspace> = if dry_run {
+        Box::new(dryrun::DryRunWorkspace::default())
+    } else {
+        Box::new(journal::JournalingWorkspace::new(
+            Box::new(workspace::GitWorkspace),
+            journal.clone(),
+        ))
+    };
+    let api: Box<dyn IssueAPI> = match get_project_api(&config_value, &remote, offline) {
+        Ok(new_api) => new_api,
+        Err(e) => {
+            eprintln!("Could not determine host from git config: {}", e);
+            if report || cleanup || planning || apply_matches.is_some() {
+                exit(1);
+            }
+            // Without a remote the TODOs can still be listed, e.g., for trees outside of git
+            let (_, scan) = scan_sources(&config_value.general, &paths, untracked, no_cache)?;
+            println!("\nFound the following TODOs:");
+            for todo in scan.todos {
                 println!("{}", todo);
             }
-            println!("To report them run issuefer with the -r/--report flag");
+            return Ok(());
         }
+    };
+
+    println!("IssueFER running for {}\n", api.repo());
+
+    // Creations of an interrupted run are finished first, so their TODOs are not reported twice
+    if changing && !dry_run && !offline {
+        pending::reconcile(&config_value, workspace.as_ref());
+    } else if !pending::load().is_empty() {
+        println!("Warn: A previous run was interrupted while creating issues, run issuefer with -r or -c to finish it\n");
+    }
+
+    let (host, owner, repo) = git::host_owner_repo(&remote)?;
+    let project = sync::Project { host, owner, repo };
+    let make_api = |repo_path: Option<&str>| {
+        write_api(
+            &config_value,
+            &project,
+            repo_path,
+            offline,
+            dry_run,
+            &journal,
+        )
+    };
+    let executor = executor::Executor {
+        workspace: workspace.as_ref(),
+        make_api: &make_api,
+        dry_run,
+        report: &report_outcome,
+    };
+
+    if let Some(apply_matches) = apply_matches {
         println!();
-        let todos_to_cleanup = find_todos_to_cleanup(&source_code_todos, &issues);
-        if todos_to_cleanup.is_empty() {
-            println!("No TODOs to clean up found");
-        } else if cleanup {
-            remove_todos(&todos_to_cleanup, force_yes);
-        } else {
-            println!("Found the following TODOs to clean up:");
-            for todo in find_todos_to_cleanup(&source_code_todos, &issues) {
-                println!("{}", todo);
+        let mut plan = match apply_matches.value_of("FILE").map(load_plan) {
+            Some(Ok(plan)) => plan,
+            Some(Err(e)) => {
+                eprintln!("Error: {}", e);
+                exit(1);
             }
-            println!("To clean them up run issuefer with the -c/--cleanup flag");
+            None => exit(1),
+        };
+        if plan.project != project {
+            eprintln!(
+                "Error: The plan is for {}/{} on {}, but the remote {} is {}/{} on {}",
+                plan.project.owner,
+                plan.project.repo,
+                plan.project.host,
+                remote,
+                project.owner,
+                project.repo,
+                project.host
+            );
+            exit(1);
         }
-    } else {
-        eprintln!("Could not fetch issues for current project");
-        std::process::exit(1);
+        skip_pending_creations(&mut plan);
+        if plan.is_empty() {
+            println!("The plan has nothing to do");
+            return Ok(());
+        }
+        println!("The plan does the following:");
+        for action in &plan.actions {
+            println!("{}", action.describe());
+        }
+        if !force_yes && !ask_yes_no("Do you want to apply the plan?") {
+            return Ok(());
+        }
+        if !executor.apply(&plan) {
+            exit(1);
+        }
+        return Ok(());
+    }
+
+    let (source_files, scan) = scan_sources(&config_value.general, &paths, untracked, no_cache)?;
+    let source_code_todos = scan.todos;
+
+    if let Some(validate_matches) = matches.subcommand_matches("validate") {
+        println!();
+        let issues = match fetch_issues(api.as_ref()) {
+            Ok(issues) => issues,
+            Err(e) => {
+                eprintln!("Could not fetch issues for current project: {}", e);
+                exit(1);
+            }
+        };
+        if !validate(
+            api.as_ref(),
+            workspace.as_ref(),
+            &source_code_todos,
+            &issues,
+            validate_matches.is_present("fix"),
+            force_yes,
+        ) {
+            exit(1);
+        }
+        return Ok(());
+    }
+
+    // TODOs referencing other repositories and --target-repo use the host of the remote
+    let target_repo = matches.value_of("target-repo");
+    let target_api = match target_repo {
+        Some(path) => {
+            let (target_owner, target_name) = todo::split_repo_path(path)
+                .ok_or_else(|| format!("--target-repo '{}' is not of the form owner/repo", path))?;
+            Some(backend::get_repo_api(
+                &config_value,
+                &project.host,
+                target_owner,
+                target_name,
+                offline,
+            )?)
+        }
+        None => None,
+    };
+
+    let issues = match fetch_issues(api.as_ref()) {
+        Ok(issues) => issues,
+        Err(e) => {
+            eprintln!("Could not fetch issues for current project: {}", e);
+            exit(1);
+        }
+    };
+    // Unreported TODOs are compared with the issues of the repository they are reported to
+    let target_issues = match &target_api {
+        Some(target_api) => match fetch_issues(target_api.as_ref()) {
+            Ok(target_issues) => Some(target_issues),
+            Err(e) => {
+                eprintln!("Could not fetch issues for {}: {}", target_api.repo(), e);
+                exit(1);
+            }
+        },
+        None => None,
+    };
+    let (foreign_issues, messages) =
+        sync::get_foreign_issues(&config_value, &project.host, &source_code_todos, offline);
+    for message in messages {
+        println!("{}\n", message);
+    }
+    let known_issues = sync::KnownIssues {
+        project: issues,
+        target: target_issues,
+        foreign: foreign_issues,
+    };
+    // Closing issues whose TODO is gone is only safe if all TODOs were seen
+    let close_orphaned = matches.is_present("close-orphaned");
+    let complete_scan = source_files.complete && scan.complete;
+    if close_orphaned && !complete_scan {
+        println!(
+            "Warn: --close-orphaned needs a complete scan, but files were left out or could not be scanned, not closing issues\n"
+        );
+    }
+    let mut plan = SyncPlan::new(
+        project.clone(),
+        target_repo.map(str::to_string),
+        &source_code_todos,
+        &known_issues,
+        &config_value.general,
+        sync::PlanOptions {
+            sync_titles: matches.is_present("sync-titles"),
+            close_orphaned: close_orphaned && complete_scan,
+        },
+    );
+
+    if let Some(plan_matches) = matches.subcommand_matches("plan") {
+        // Without -r and -c the plan has everything, like a run with both
+        if report || cleanup {
+            plan.retain(|action| match action {
+                sync::Action::CreateIssue { .. } | sync::Action::AnnotateTodo { .. } => report,
+                _ => cleanup,
+            });
+        }
+        let file = plan_matches.value_of("FILE").unwrap_or_default();
+        if let Err(e) = plan
+            .to_json()
+            .and_then(|json| fs::write(file, json + "\n").map_err(|e| e.to_string()))
+        {
+            eprintln!("Error: Could not write the plan to {}: {}", file, e);
+            exit(1);
+        }
+        println!();
+        for action in &plan.actions {
+            println!("{}", action.describe());
+        }
+        println!(
+            "Wrote {} actions to {}, apply them with: issuefer apply {}",
+            plan.actions.len(),
+            file,
+            file
+        );
+        return Ok(());
+    }
+
+    if !report && !cleanup {
+        print_plan(&plan);
+        return Ok(());
+    }
+    plan.retain(|action| match action {
+        sync::Action::CreateIssue { .. } | sync::Action::AnnotateTodo { .. } => report,
+        _ => cleanup,
+    });
+    skip_pending_creations(&mut plan);
+    if plan.is_empty() {
+        println!("No TODOs to report or clean up found");
+        return Ok(());
+    }
+
+    // Without -y, the plan is triaged in the terminal UI if there is a terminal
+    if !force_yes && !matches.is_present("no-tui") && triage::is_available() {
+        let ignore = match triage::run(&mut plan) {
+            Ok(Some(ignore)) => ignore,
+            Ok(None) => {
+                println!("Nothing selected, nothing changed");
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("Error: {}, run issuefer with --no-tui", e);
+                exit(1);
+            }
+        };
+        if plan.is_empty() && ignore.is_empty() {
+            println!("Nothing selected, nothing changed");
+            return Ok(());
+        }
+        for todo in &ignore {
+            ignore_todo(workspace.as_ref(), todo);
+        }
+    } else if !force_yes {
+        confirm_actions(&mut plan);
+    }
+    if !executor.apply(&plan) {
+        exit(1);
     }
 
-    // TODO (#6): Add option to ignore TODOs, mark them with '// TODO (II):'
-    // TODO (#7): Support more than just // at the beginning of the TODO lines
     // TODO (#8): C style multi-line comments with /* */ should be supported
     // TODO (#9): When encountering TODOs followed by commented lines  those lines shall be added to the body of the issue
 
@@ -0,0 +1,236 @@
+use crate::cache::CachedTodo;
+use crate::git;
+use crate::sync::Project;
+use crate::todo::{self, Todo};
+use crate::workspace::Workspace;
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const PENDING_FILE: &str = "pending.json";
+
+// Issues are searched from this long before the creation started, in case the clocks of this
+// machine and the host differ
+const CLOCK_SKEW: Duration = Duration::from_secs(15 * 60);
+
+// PendingCreation is an issue that is about to be created for a TODO. It is recorded before
+// the API call and removed once the TODO is annotated, so a run that died in between can be
+// reconciled by the next one.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PendingCreation {
+    pub id: String,
+    pub host: String,
+    pub owner: String,
+    pub repo: String,
+    // Repository path used in the reference, if the issue is created in another repository
+    pub target_repo: Option<String>,
+    pub started_at: String,
+    // Project of the TODO, the issue may be created in another repository
+    #[serde(default)]
+    pub project: Project,
+    pub file_path: String,
+    pub todo: CachedTodo,
+}
+
+impl PendingCreation {
+    // marker is hidden in the body of the issue, so the issue can be found again.
+    pub fn marker(&self) -> String {
+        self.project.marker(&self.id)
+    }
+}
+
+fn pending_path() -> Option<PathBuf> {
+    git::git_dir()
+        .ok()
+        .map(|dir| dir.join("issuefer").join(PENDING_FILE))
+}
+
+pub fn load() -> Vec<PendingCreation> {
+    let path = match pending_path() {
+        Some(path) if path.exists() => path,
+        _ => return Vec::new(),
+    };
+    match fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| serde_json::from_str(&content).map_err(|e| e.to_string()))
+    {
+        Ok(pending) => pending,
+        Err(e) => {
+            println!("Warn: {} is corrupt ({}), ignoring it", path.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+fn save(pending: &[PendingCreation]) -> Result<(), String> {
+    let path = match pending_path() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    if pending.is_empty() {
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| e.to_string())?;
+        }
+        return Ok(());
+    }
+    let content = serde_json::to_string(pending).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, content).map_err(|e| e.to_string())?;
+    fs::rename(&tmp_path, &path).map_err(|e| e.to_string())
+}
+
+// add records a creation before the issue is created.
+pub fn add(creation: PendingCreation) -> Result<(), String> {
+    let mut pending = load();
+    pending.push(creation);
+    save(&pending)
+}
+
+// remove drops a creation once its TODO is annotated.
+pub fn remove(id: &str) -> Result<(), String> {
+    let mut pending = load();
+    pending.retain(|p| p.id != id);
+    save(&pending)
+}
+
+// new_id returns an id that is unique enough to tell creations apart.
+pub fn new_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!("{:x}-{:x}", nanos, std::process::id())
+}
+
+// timestamp formats the time as ISO 8601 in UTC, e.g., 2020-05-17T10:00:00Z.
+pub fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, rest) = (secs / 86400, secs % 86400);
+    // Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
+
+pub fn search_start(now: SystemTime) -> String {
+    timestamp(now.checked_sub(CLOCK_SKEW).unwrap_or(UNIX_EPOCH))
+}
+
+// find_todo returns the TODO of the creation, also if lines were added or removed above it.
+fn find_todo(workspace: &dyn Workspace, creation: &PendingCreation) -> Option<Todo> {
+    crate::workspace::locate(workspace, creation.todo.to_todo(&creation.file_path))
+}
+
+// reconcile finishes the creations of earlier runs that died between creating the issue and
+// annotating the TODO. An issue whose body contains the marker of the creation is referenced
+// by the TODO instead of creating another one. Creations without such an issue are dropped,
+// so their TODOs are reported again.
+pub fn reconcile(config: &crate::config::Config, workspace: &dyn Workspace) {
+    for creation in load() {
+        let api = match crate::backend::get_repo_api(
+            config,
+            &creation.host,
+            creation.owner.clone(),
+            creation.repo.clone(),
+            false,
+        ) {
+            Ok(api) => api,
+            Err(e) => {
+                println!("Warn: Could not reconcile pending issue creations: {}", e);
+                return;
+            }
+        };
+        let issues = match api.get_issues_since(Some(&creation.started_at), None) {
+            Ok(crate::issueapi::IssueUpdates::Updated { issues, .. }) => issues,
+            Ok(crate::issueapi::IssueUpdates::NotModified) => Vec::new(),
+            Err(e) => {
+                println!("Warn: Could not reconcile pending issue creations: {}", e);
+                return;
+            }
+        };
+        let title = &creation.todo.title;
+        match issues.iter().find(|i| i.body.contains(&creation.marker())) {
+            Some(issue) => {
+                let reference =
+                    todo::format_reference(creation.target_repo.as_deref(), issue.number);
+                println!(
+                    "Issue {} for '{}' was created by an interrupted run, referencing it",
+                    reference, title
+                );
+                let result = find_todo(workspace, &creation)
+                    .ok_or_else(|| format!("the TODO '{}' is gone", title))
+                    .and_then(|todo| {
+                        crate::workspace::update_file(
+                            workspace,
+                            &todo,
+                            Some(&todo::format_marker(&todo.keyword, Some(&reference))),
+                            // The title may have been edited before the issue was created
+                            Some(issue.title.as_str()).filter(|t| *t != todo.title),
+                        )
+                        .map(|_| todo)
+                    });
+                match result {
+                    Ok(todo) => {
+                        if let Err(e) =
+                            crate::workspace::commit_add(workspace, &todo.file_path, &reference)
+                        {
+                            println!("Warn: Could not commit {}: {}", todo.file_path, e);
+                        }
+                    }
+                    Err(e) => {
+                        // Kept, so the next run does not report the TODO a second time
+                        eprintln!(
+                            "Error: Could not add {} to the TODO, add it manually: {}",
+                            reference, e
+                        );
+                        continue;
+                    }
+                }
+            }
+            None => println!(
+                "The issue for '{}' was not created by an interrupted run, it is reported again",
+                title
+            ),
+        }
+        if let Err(e) = remove(&creation.id) {
+            println!("Warn: Could not update pending issue creations: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let time = UNIX_EPOCH + Duration::from_secs(951_827_696);
+        assert_eq!(timestamp(time), "2000-02-29T12:34:56Z");
+        let time = UNIX_EPOCH + Duration::from_secs(1_589_709_600);
+        assert_eq!(search_start(time), "2020-05-17T09:45:00Z");
+    }
+}
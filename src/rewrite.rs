@@ -0,0 +1,257 @@
+use std::fs;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+pub enum LineEdit {
+    Keep,
+    /// Splice replaces a range of the line, as given to the edit, with the text. The rest of
+    /// the line is copied byte by byte.
+    Splice(Range<usize>, String),
+    Delete,
+}
+
+// split_lines splits content into lines, each with its original terminator (\n, \r\n or none
+// for a last line without newline).
+fn split_lines(content: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut lines = Vec::new();
+    let mut rest = content;
+    while !rest.is_empty() {
+        let (line, next) = match rest.iter().position(|&b| b == b'\n') {
+            Some(pos) => (&rest[..=pos], &rest[pos + 1..]),
+            None => (rest, &rest[rest.len()..]),
+        };
+        let text_len = if line.ends_with(b"\r\n") {
+            line.len() - 2
+        } else if line.ends_with(b"\n") {
+            line.len() - 1
+        } else {
+            line.len()
+        };
+        lines.push((&line[..text_len], &line[text_len..]));
+        rest = next;
+    }
+    lines
+}
+
+// raw_range maps a range of the lossily decoded line to the range of the original bytes.
+// Every invalid sequence was decoded to a single U+FFFD, so a range that starts or ends inside
+// one has no counterpart and None is returned.
+fn raw_range(text: &[u8], range: &Range<usize>) -> Option<Range<usize>> {
+    let (mut decoded, mut raw) = (0, 0);
+    let (mut start, mut end) = (None, None);
+    let mut map = |decoded: usize, raw: usize| {
+        if decoded == range.start {
+            start = Some(raw);
+        }
+        if decoded == range.end {
+            end = Some(raw);
+        }
+    };
+    for chunk in text.utf8_chunks() {
+        for (i, _) in chunk.valid().char_indices() {
+            map(decoded + i, raw + i);
+        }
+        decoded += chunk.valid().len();
+        raw += chunk.valid().len();
+        if !chunk.invalid().is_empty() {
+            map(decoded, raw);
+            decoded += char::REPLACEMENT_CHARACTER.len_utf8();
+            raw += chunk.invalid().len();
+        }
+    }
+    map(decoded, raw);
+    Some(start?..end?)
+}
+
+/// rewrite_content applies edit to every line, given the line number and the line without its
+/// terminator, decoded lossily if it is not valid UTF-8. Everything but spliced ranges is copied
+/// byte by byte, including terminators and a BOM at the start of the file. A splice that would
+/// replace bytes which are not valid UTF-8 with text is refused, as they would be lost.
+pub fn rewrite_content(
+    content: &[u8],
+    mut edit: impl FnMut(usize, &str) -> LineEdit,
+) -> Result<Vec<u8>, String> {
+    let (bom, content) = match content.strip_prefix(BOM) {
+        Some(rest) => (BOM, rest),
+        None => (&b""[..], content),
+    };
+    let mut output = Vec::with_capacity(content.len() + bom.len());
+    output.extend_from_slice(bom);
+    for (cnt, (text, terminator)) in split_lines(content).into_iter().enumerate() {
+        match edit(cnt, &String::from_utf8_lossy(text)) {
+            LineEdit::Keep => output.extend_from_slice(text),
+            LineEdit::Splice(range, new_text) => {
+                let range = raw_range(text, &range)
+                    .filter(|r| {
+                        new_text.is_empty() || std::str::from_utf8(&text[r.clone()]).is_ok()
+                    })
+                    .ok_or_else(|| {
+                        format!(
+                            "line {} is not valid UTF-8 where it would be changed",
+                            cnt + 1
+                        )
+                    })?;
+                output.extend_from_slice(&text[..range.start]);
+                output.extend_from_slice(new_text.as_bytes());
+                output.extend_from_slice(&text[range.end..]);
+            }
+            LineEdit::Delete => continue,
+        }
+        output.extend_from_slice(terminator);
+    }
+    Ok(output)
+}
+
+/// write_atomically replaces the file with content by writing a temporary file in the same
+/// directory and renaming it, so the file is either unchanged or completely written. The
+/// permissions of the original file are kept.
+pub fn write_atomically(path: &Path, content: &[u8]) -> Result<(), io::Error> {
+    let permissions = fs::metadata(path)?.permissions();
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp_path = path.with_file_name(format!(
+        ".{}.issuefer-{}.tmp",
+        file_name,
+        std::process::id()
+    ));
+    let result = (|| {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::set_permissions(&tmp_path, permissions)?;
+        fs::rename(&tmp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotate_second_line(content: &[u8]) -> Vec<u8> {
+        rewrite_content(content, |cnt, line| match line.find("TODO:") {
+            Some(pos) if cnt == 1 => LineEdit::Splice(pos..pos + 5, "TODO (#1):".to_string()),
+            _ => LineEdit::Keep,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn unchanged_content_round_trips() {
+        for content in &[
+            &b""[..],
+            b"\n",
+            b"a\r\nb\r\n",
+            b"a\nb\r\nc",
+            b"\xef\xbb\xbfa\r\n",
+            b"a\xff\nb\n",
+        ] {
+            assert_eq!(
+                rewrite_content(content, |_, _| LineEdit::Keep).unwrap(),
+                *content
+            );
+        }
+    }
+
+    #[test]
+    fn crlf_is_kept() {
+        assert_eq!(
+            annotate_second_line(b"a\r\n// TODO: x\r\nb\r\n"),
+            b"a\r\n// TODO (#1): x\r\nb\r\n"
+        );
+    }
+
+    #[test]
+    fn mixed_endings_are_kept() {
+        assert_eq!(
+            annotate_second_line(b"a\r\n// TODO: x\nb\r\nc\n"),
+            b"a\r\n// TODO (#1): x\nb\r\nc\n"
+        );
+    }
+
+    #[test]
+    fn missing_trailing_newline_is_kept() {
+        assert_eq!(
+            annotate_second_line(b"a\n// TODO: x"),
+            b"a\n// TODO (#1): x"
+        );
+    }
+
+    #[test]
+    fn bom_is_kept() {
+        assert_eq!(
+            annotate_second_line(b"\xef\xbb\xbfa\n// TODO: x\n"),
+            b"\xef\xbb\xbfa\n// TODO (#1): x\n"
+        );
+    }
+
+    #[test]
+    fn deleted_line_takes_its_terminator() {
+        let content = rewrite_content(b"a\r\n// TODO (#1): x\r\nb", |cnt, _| {
+            if cnt == 1 {
+                LineEdit::Delete
+            } else {
+                LineEdit::Keep
+            }
+        });
+        assert_eq!(content.unwrap(), b"a\r\nb");
+    }
+
+    #[test]
+    fn latin1_bytes_are_kept() {
+        assert_eq!(
+            annotate_second_line(b"caf\xe9\n\xe0 // TODO: d\xe9j\xe0 vu\n"),
+            b"caf\xe9\n\xe0 // TODO (#1): d\xe9j\xe0 vu\n"
+        );
+    }
+
+    #[test]
+    fn invalid_bytes_are_not_replaced() {
+        let content = b"// TODO: caf\xe9\n";
+        let result = rewrite_content(content, |_, line| {
+            LineEdit::Splice(3..line.len(), "TODO (#1): cafe".to_string())
+        });
+        assert_eq!(
+            result,
+            Err("line 1 is not valid UTF-8 where it would be changed".to_string())
+        );
+        let removed = rewrite_content(content, |_, line| {
+            LineEdit::Splice(2..line.len(), String::new())
+        });
+        assert_eq!(removed.unwrap(), b"//\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions_are_kept() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("issuefer-rewrite-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("script.sh");
+        fs::write(&path, "#!/bin/sh\n# TODO: x\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let content = annotate_second_line(&fs::read(&path).unwrap());
+        write_atomically(&path, &content).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "#!/bin/sh\n# TODO (#1): x\n"
+        );
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
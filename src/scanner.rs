@@ -0,0 +1,221 @@
+use crate::cache::{CachedTodo, ScanCache};
+use crate::config;
+use crate::files;
+use crate::git;
+use crate::todo::{Todo, TodoParser};
+
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+
+// Number of bytes at the start of a file that are checked for NUL bytes, the same heuristic git
+// uses to tell binary files apart from text files.
+const BINARY_SNIFF_LEN: usize = 8000;
+
+enum ScanResult {
+    /// The TODOs of the file and warnings about lines that could not be parsed
+    Todos(Vec<Todo>, Vec<String>),
+    Binary,
+    TooLarge,
+    Unreadable,
+}
+
+fn get_todos_from_source_code_file(
+    parser: &TodoParser,
+    source_file: &str,
+    max_file_size: u64,
+) -> ScanResult {
+    match fs::metadata(source_file) {
+        Ok(metadata) if metadata.len() > max_file_size => return ScanResult::TooLarge,
+        Ok(_) => {}
+        Err(_) => return ScanResult::Unreadable,
+    }
+    let content = match fs::read(source_file) {
+        Ok(content) => content,
+        Err(_) => return ScanResult::Unreadable,
+    };
+    if content[..content.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return ScanResult::Binary;
+    }
+    if !parser.may_contain_todo(&content) {
+        return ScanResult::Todos(Vec::new(), Vec::new());
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    {
+        if let Some(comments) = crate::syntax::comments(source_file, &content) {
+            return get_todos_from_comments(parser, source_file, &content, &comments);
+        }
+    }
+
+    let mut todos = Vec::new();
+    let mut warnings = Vec::new();
+    for (cnt, line) in content.split(|&b| b == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        // Invalid UTF-8 (e.g., Latin-1 files) must not hide the TODOs in the rest of the file
+        let line = String::from_utf8_lossy(line);
+        match parser.parse_line(source_file, cnt, &line) {
+            Ok(Some(todo)) => todos.push(todo),
+            Ok(None) => {}
+            Err(e) => warnings.push(e),
+        }
+    }
+    ScanResult::Todos(todos, warnings)
+}
+
+// get_todos_from_comments finds the TODOs in the comments of a file, so comment markers in
+// strings are not mistaken for TODOs and TODOs after code on the same line are found, too.
+// Like the line scanner, only comments starting with // or # are considered.
+#[cfg(feature = "tree-sitter")]
+fn get_todos_from_comments(
+    parser: &TodoParser,
+    source_file: &str,
+    content: &[u8],
+    comments: &[crate::syntax::Comment],
+) -> ScanResult {
+    let lines: Vec<&[u8]> = content.split(|&b| b == b'\n').collect();
+    let mut todos = Vec::new();
+    let mut warnings = Vec::new();
+    for comment in comments {
+        let line = lines[comment.line];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let (code, text) = line.split_at(comment.column.min(line.len()));
+        let code = String::from_utf8_lossy(code);
+        match parser.parse_line(source_file, comment.line, &String::from_utf8_lossy(text)) {
+            Ok(Some(mut todo)) => {
+                // The TODO keeps the whole line, so it can be rewritten like any other
+                todo.line = format!("{}{}", code, todo.line);
+                todo.marker = todo.marker.start + code.len()..todo.marker.end + code.len();
+                todos.push(todo);
+            }
+            Ok(None) => {}
+            Err(e) => warnings.push(e),
+        }
+    }
+    ScanResult::Todos(todos, warnings)
+}
+
+/// Scan is the result of scanning files.
+pub struct Scan {
+    pub todos: Vec<Todo>,
+    /// Whether every file was scanned completely, i.e., no file was skipped as binary, too
+    /// large or unreadable and no line looked like a TODO but could not be parsed
+    pub complete: bool,
+    /// Lines that looked like TODOs but could not be parsed, unreadable files and problems with
+    /// the cache
+    pub warnings: Vec<String>,
+    /// Number of files skipped as binary
+    pub binary_files: usize,
+    /// Number of files skipped as larger than max_file_size
+    pub large_files: usize,
+}
+
+/// get_all_todos_from_source_code_files scans the files in parallel. The TODOs are returned in
+/// the order of source_files. With a cache, files whose content is unchanged since an earlier
+/// run are not read again.
+pub fn get_all_todos_from_source_code_files(
+    config: &config::GeneralConfig,
+    source_files: &[String],
+    mut cache: Option<ScanCache>,
+) -> Scan {
+    let current_dir = env::current_dir().unwrap_or_default();
+    let root = files::root_dir().unwrap_or_else(|_| current_dir.clone());
+    let oids: HashMap<String, String> = if cache.is_some() {
+        git::blob_oids().unwrap_or_default()
+    } else {
+        HashMap::new()
+    };
+
+    let mut parsers: HashMap<Vec<String>, TodoParser> = HashMap::new();
+    let mut file_keywords: Vec<Vec<String>> = Vec::with_capacity(source_files.len());
+    let mut cache_keys: Vec<Option<String>> = Vec::with_capacity(source_files.len());
+    for source_file in source_files {
+        let relative_path = files::relative_path(std::path::Path::new(source_file), &current_dir);
+        let keywords = config
+            .keywords_for(&files::root_relative_path(
+                std::path::Path::new(source_file),
+                &root,
+            ))
+            .to_vec();
+        parsers
+            .entry(keywords.clone())
+            .or_insert_with(|| TodoParser::new(&keywords));
+        cache_keys.push(
+            oids.get(&relative_path)
+                .map(|oid| ScanCache::key(oid, &keywords)),
+        );
+        file_keywords.push(keywords);
+    }
+
+    let cached: Vec<Option<Vec<CachedTodo>>> = cache_keys
+        .iter()
+        .map(|key| match (&mut cache, key) {
+            (Some(cache), Some(key)) => cache.get(key),
+            _ => None,
+        })
+        .collect();
+
+    let results: Vec<ScanResult> = source_files
+        .par_iter()
+        .zip(file_keywords.par_iter())
+        .zip(cached.into_par_iter())
+        .map(|((source_file, keywords), cached)| match cached {
+            Some(cached) => ScanResult::Todos(
+                cached.iter().map(|t| t.to_todo(source_file)).collect(),
+                Vec::new(),
+            ),
+            None => get_todos_from_source_code_file(
+                &parsers[keywords],
+                source_file,
+                config.max_file_size,
+            ),
+        })
+        .collect();
+
+    let mut warnings: Vec<String> = cache
+        .as_mut()
+        .and_then(ScanCache::take_warning)
+        .into_iter()
+        .collect();
+    if let Some(mut cache) = cache {
+        for (key, result) in cache_keys.iter().zip(&results) {
+            // Files with warnings are not cached, so the warnings are shown on every run
+            if let (Some(key), ScanResult::Todos(todos, warnings)) = (key, result) {
+                if !warnings.is_empty() {
+                    continue;
+                }
+                cache.insert(key, todos.iter().map(CachedTodo::from_todo).collect());
+            }
+        }
+        if let Err(e) = cache.save() {
+            warnings.push(format!("Could not write scan cache: {}", e));
+        }
+    }
+
+    let mut all_todos = Vec::new();
+    let mut complete = true;
+    let mut binary_files = 0;
+    let mut large_files = 0;
+    for (source_file, result) in source_files.iter().zip(results) {
+        match result {
+            ScanResult::Todos(todos, mut file_warnings) => {
+                complete &= file_warnings.is_empty();
+                warnings.append(&mut file_warnings);
+                all_todos.extend(todos);
+                continue;
+            }
+            ScanResult::Binary => binary_files += 1,
+            ScanResult::TooLarge => large_files += 1,
+            ScanResult::Unreadable => warnings.push(format!("Could not read file {}", source_file)),
+        }
+        complete = false;
+    }
+    Scan {
+        todos: all_todos,
+        complete,
+        warnings,
+        binary_files,
+        large_files,
+    }
+}
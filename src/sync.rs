@@ -0,0 +1,694 @@
+use crate::backend;
+use crate::config;
+use crate::files;
+use crate::issueapi::{Issue, IssueNumber, NewIssue};
+use crate::todo::{self, Todo};
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::ops::Range;
+use std::path::Path;
+
+/// PLAN_VERSION is the version of the plan file format. Plans of other versions are refused.
+pub const PLAN_VERSION: u32 = 1;
+
+/// MARKER_PREFIX starts the hidden marker issuefer adds to the body of the issues it creates,
+/// i.e., `<!-- issuefer:<host>/<owner>/<repo>:<id> -->` with the project of the TODO.
+pub const MARKER_PREFIX: &str = "<!-- issuefer:";
+
+/// PlannedTodo is a TODO as it is stored in a plan, with a path relative to the repository root.
+/// Its fields are part of the plan format, see PLAN_VERSION, and do not change with the scan
+/// cache.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlannedTodo {
+    pub file: String,
+    pub line_number: usize,
+    pub keyword: String,
+    pub title: String,
+    pub issue_number: IssueNumber,
+    #[serde(default)]
+    pub issue_repo: Option<String>,
+    pub line: String,
+    pub marker: Range<usize>,
+}
+
+impl PlannedTodo {
+    pub fn new(todo: &Todo) -> PlannedTodo {
+        PlannedTodo {
+            file: files::relative_path(
+                Path::new(&todo.file_path),
+                &env::current_dir().unwrap_or_default(),
+            ),
+            line_number: todo.line_number,
+            keyword: todo.keyword.clone(),
+            title: todo.title.clone(),
+            issue_number: todo.issue_number,
+            issue_repo: todo.issue_repo.clone(),
+            line: todo.line.clone(),
+            marker: todo.marker.clone(),
+        }
+    }
+
+    pub fn to_todo(&self) -> Todo {
+        Todo {
+            file_path: self.file.clone(),
+            line_number: self.line_number,
+            keyword: self.keyword.clone(),
+            title: self.title.clone(),
+            issue_number: self.issue_number,
+            issue_repo: self.issue_repo.clone(),
+            line: self.line.clone(),
+            marker: self.marker.clone(),
+        }
+    }
+}
+
+/// IssueRef is the issue a TODO is annotated with: one created by an earlier action of the plan
+/// or an existing one.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueRef {
+    /// The id of the CreateIssue action
+    Planned(String),
+    Existing {
+        /// Repository path, None for the current project
+        repo: Option<String>,
+        number: IssueNumber,
+    },
+}
+
+/// Action is a single change of a plan.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Create an issue for an unreported TODO in the project new issues are reported to
+    CreateIssue {
+        id: String,
+        todo: PlannedTodo,
+        title: String,
+        body: String,
+        labels: Vec<String>,
+        #[serde(default)]
+        assignees: Vec<String>,
+    },
+    /// Add the issue reference to the TODO, e.g., "TODO:" becomes "TODO (#12):"
+    AnnotateTodo { todo: PlannedTodo, issue: IssueRef },
+    /// Remove the TODO because its issue is closed
+    RemoveTodo { todo: PlannedTodo },
+    /// Close an issue created by issuefer whose TODO is gone. repo is None for the current
+    /// project.
+    CloseIssue {
+        repo: Option<String>,
+        number: IssueNumber,
+        title: String,
+    },
+    /// Change the title of the TODO to the title of its issue
+    UpdateTitle { todo: PlannedTodo, title: String },
+}
+
+impl Action {
+    /// describe returns a one line description of the action for the user.
+    pub fn describe(&self) -> String {
+        match self {
+            Action::CreateIssue { todo, title, .. } => format!(
+                "Create issue '{}' for {}:{}",
+                title,
+                todo.file,
+                todo.line_number + 1
+            ),
+            Action::AnnotateTodo { todo, issue } => format!(
+                "Add {} to {}:{}",
+                match issue {
+                    IssueRef::Planned(id) => format!("the issue of {}", id),
+                    IssueRef::Existing { repo, number } =>
+                        todo::format_reference(repo.as_deref(), *number),
+                },
+                todo.file,
+                todo.line_number + 1
+            ),
+            Action::RemoveTodo { todo } => format!(
+                "Remove {}:{} of closed issue {}",
+                todo.file,
+                todo.line_number + 1,
+                todo::format_reference(todo.issue_repo.as_deref(), todo.issue_number)
+            ),
+            Action::CloseIssue {
+                repo,
+                number,
+                title,
+            } => format!(
+                "Close issue {} with title '{}', its TODO is gone",
+                todo::format_reference(repo.as_deref(), *number),
+                title
+            ),
+            Action::UpdateTitle { todo, title } => format!(
+                "Change the title of {}:{} to '{}' like issue {}",
+                todo.file,
+                todo.line_number + 1,
+                title,
+                todo::format_reference(todo.issue_repo.as_deref(), todo.issue_number)
+            ),
+        }
+    }
+
+    /// todo returns the TODO the action changes or reports, None for CloseIssue.
+    pub fn todo(&self) -> Option<&PlannedTodo> {
+        match self {
+            Action::CreateIssue { todo, .. }
+            | Action::AnnotateTodo { todo, .. }
+            | Action::RemoveTodo { todo }
+            | Action::UpdateTitle { todo, .. } => Some(todo),
+            Action::CloseIssue { .. } => None,
+        }
+    }
+}
+
+/// Project is the repository a plan is made for. A plan is only applied to the same project.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct Project {
+    pub host: String,
+    pub owner: String,
+    pub repo: String,
+}
+
+impl Project {
+    /// marker returns the hidden marker of the issue created for a TODO of the project, id tells
+    /// the creations apart.
+    pub fn marker(&self, id: &str) -> String {
+        format!("{}{} -->", self.marker_prefix(), id)
+    }
+
+    fn marker_prefix(&self) -> String {
+        format!(
+            "{}{}/{}/{}:",
+            MARKER_PREFIX, self.host, self.owner, self.repo
+        )
+    }
+
+    // created_issue returns true if the body has the marker of the project, i.e., the issue was
+    // created for one of its TODOs and not for those of other projects reporting to the same
+    // repository.
+    fn created_issue(&self, body: &str) -> bool {
+        body.to_lowercase()
+            .contains(&self.marker_prefix().to_lowercase())
+    }
+}
+
+/// KnownIssues are the issues the TODOs are compared with, in all states.
+pub struct KnownIssues {
+    /// Issues of the current project
+    pub project: Vec<Issue>,
+    /// Issues of the project new issues are reported to, if that is another one (--target-repo)
+    pub target: Option<Vec<Issue>>,
+    /// Issues of other projects referenced by TODOs by repository path, see get_foreign_issues
+    pub foreign: HashMap<String, Vec<Issue>>,
+}
+
+/// PlanOptions enables the actions that are not planned by default.
+#[derive(Clone, Copy, Default)]
+pub struct PlanOptions {
+    /// Plan UpdateTitle actions for TODOs whose open issue was renamed
+    pub sync_titles: bool,
+    /// Plan CloseIssue actions for open issues created by issuefer that no TODO references.
+    /// Only set this if all files of the project were scanned.
+    pub close_orphaned: bool,
+}
+
+/// SyncPlan is what a run changes, as a list of actions that can be saved as JSON, reviewed and
+/// applied later. Issues are created and TODOs annotated first, TODOs are removed last and from
+/// the bottom of each file up, so the line numbers of the other TODOs stay valid.
+#[derive(Serialize, Deserialize)]
+pub struct SyncPlan {
+    pub version: u32,
+    pub project: Project,
+    /// Repository path new issues are reported to, if that is not the project itself
+    pub target_repo: Option<String>,
+    pub actions: Vec<Action>,
+}
+
+impl SyncPlan {
+    /// new plans the run for the scanned TODOs. New issues are rendered as configured, see
+    /// new_issue.
+    pub fn new(
+        project: Project,
+        target_repo: Option<String>,
+        todos: &[Todo],
+        issues: &KnownIssues,
+        config: &config::GeneralConfig,
+        options: PlanOptions,
+    ) -> SyncPlan {
+        let target_issues = issues.target.as_ref().unwrap_or(&issues.project);
+        // Unreported TODOs are compared with the closed issues of the project they are reported to
+        let closed_target_issues: Vec<Issue> = target_issues
+            .iter()
+            .filter(|i| i.state == "closed")
+            .cloned()
+            .collect();
+        let mut actions = Vec::new();
+        for (n, todo) in compare_todos_and_issues(todos, &closed_target_issues)
+            .iter()
+            .enumerate()
+        {
+            let id = format!("new-{}", n + 1);
+            let issue = new_issue(config, todo);
+            let planned_todo = PlannedTodo::new(todo);
+            actions.push(Action::CreateIssue {
+                id: id.clone(),
+                todo: planned_todo.clone(),
+                title: issue.title,
+                body: issue.body,
+                labels: issue.labels,
+                assignees: issue.assignees,
+            });
+            actions.push(Action::AnnotateTodo {
+                todo: planned_todo,
+                issue: IssueRef::Planned(id),
+            });
+        }
+
+        if options.sync_titles {
+            for todo in todos.iter().filter(|t| t.issue_number > 0) {
+                let repo_issues = match &todo.issue_repo {
+                    Some(repo) => match issues.foreign.get(repo) {
+                        Some(issues) => issues,
+                        None => continue,
+                    },
+                    None => &issues.project,
+                };
+                if let Some(issue) = find_issue_by_number(repo_issues, todo.issue_number) {
+                    if issue.state != "closed" && issue.title != todo.title {
+                        actions.push(Action::UpdateTitle {
+                            todo: PlannedTodo::new(todo),
+                            title: issue.title,
+                        });
+                    }
+                }
+            }
+        }
+
+        if options.close_orphaned {
+            let target_repo = target_repo.as_deref();
+            let project_path = format!("{}/{}", project.owner, project.repo);
+            // TODOs without a repository path reference the project itself, and an explicit
+            // path of the project, e.g., owner/repo#12, references it as well
+            let target_path = target_repo.unwrap_or(&project_path);
+            for issue in target_issues {
+                let referenced = todos.iter().any(|t| {
+                    t.issue_number == issue.number
+                        && t.issue_repo
+                            .as_deref()
+                            .unwrap_or(&project_path)
+                            .eq_ignore_ascii_case(target_path)
+                });
+                if issue.state != "closed" && project.created_issue(&issue.body) && !referenced {
+                    actions.push(Action::CloseIssue {
+                        repo: target_repo.map(str::to_string),
+                        number: issue.number,
+                        title: issue.title.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut todos_to_cleanup = find_todos_to_cleanup(todos, &issues.project, &issues.foreign);
+        todos_to_cleanup.sort_by(|a, b| {
+            a.file_path
+                .cmp(&b.file_path)
+                .then(b.line_number.cmp(&a.line_number))
+        });
+        actions.extend(todos_to_cleanup.iter().map(|todo| Action::RemoveTodo {
+            todo: PlannedTodo::new(todo),
+        }));
+
+        SyncPlan {
+            version: PLAN_VERSION,
+            project,
+            target_repo,
+            actions,
+        }
+    }
+
+    /// retain keeps the actions f returns true for, f is called for every action in order.
+    /// AnnotateTodo actions of dropped CreateIssue actions are dropped as well.
+    pub fn retain<F: FnMut(&Action) -> bool>(&mut self, mut f: F) {
+        let mut dropped = Vec::new();
+        self.actions.retain(|action| {
+            let keep = f(action);
+            match action {
+                Action::CreateIssue { id, .. } if !keep => dropped.push(id.clone()),
+                Action::AnnotateTodo {
+                    issue: IssueRef::Planned(id),
+                    ..
+                } if dropped.contains(id) => return false,
+                _ => {}
+            }
+            keep
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+
+    /// from_json reads a saved plan and refuses plans of other format versions.
+    pub fn from_json(json: &str) -> Result<SyncPlan, String> {
+        let plan: SyncPlan =
+            serde_json::from_str(json).map_err(|e| format!("Invalid plan: {}", e))?;
+        if plan.version != PLAN_VERSION {
+            return Err(format!(
+                "The plan has version {}, this issuefer reads version {}",
+                plan.version, PLAN_VERSION
+            ));
+        }
+        Ok(plan)
+    }
+}
+
+/// render_body fills the body template with the details of the TODO.
+/// Supported placeholders are {title}, {keyword}, {file} and {line}.
+pub fn render_body(template: &str, todo: &Todo) -> String {
+    let file = files::relative_path(
+        Path::new(&todo.file_path),
+        &env::current_dir().unwrap_or_default(),
+    );
+    template
+        .replace("{title}", &todo.title)
+        .replace("{keyword}", &todo.keyword)
+        .replace("{file}", &file)
+        .replace("{line}", &(todo.line_number + 1).to_string())
+}
+
+/// new_issue returns the issue for a TODO as configured, i.e., with the rendered body template
+/// and the labels of its file.
+pub fn new_issue(config: &config::GeneralConfig, todo: &Todo) -> NewIssue {
+    NewIssue {
+        title: todo.title.clone(),
+        body: render_body(&config.body_template, todo),
+        labels: config
+            .labels_for(&files::root_relative_path(
+                Path::new(&todo.file_path),
+                &files::root_dir().unwrap_or_default(),
+            ))
+            .to_vec(),
+        assignees: Vec::new(),
+    }
+}
+
+/// find_issue_by_title searches a list of issues by title and returns true if it finds an issue.
+pub fn find_issue_by_title(issues: &[Issue], title: &str) -> bool {
+    if let Some(_issue) = issues.iter().find(|&x| x.title == title) {
+        return true;
+    }
+    false
+}
+
+/// find_issue_by_number searches a list of issues by issue number and returns a copy if it finds it.
+pub fn find_issue_by_number(issues: &[Issue], number: IssueNumber) -> Option<Issue> {
+    if let Some(issue) = issues.iter().find(|&x| x.number == number) {
+        return Some(issue.clone());
+    }
+    None
+}
+
+/// get_foreign_issues fetches the issues of every other repository on the host that is
+/// referenced by a TODO, using the credentials of the host. Repositories whose issues could
+/// not be fetched are left out; the messages for the caller to show tell about them and about
+/// how the issues were fetched.
+pub fn get_foreign_issues(
+    config: &config::Config,
+    host: &str,
+    todos: &[Todo],
+    offline: bool,
+) -> (HashMap<String, Vec<Issue>>, Vec<String>) {
+    let repos: BTreeSet<&String> = todos.iter().filter_map(|t| t.issue_repo.as_ref()).collect();
+    let mut foreign_issues = HashMap::new();
+    let mut messages = Vec::new();
+    for repo_path in repos {
+        let issues = todo::split_repo_path(repo_path)
+            .ok_or_else(|| format!("'{}' is not a repository path", repo_path))
+            .and_then(|(owner, repo)| backend::get_repo_api(config, host, owner, repo, offline))
+            .and_then(|api| {
+                let issues = api.get_issues();
+                messages.extend(api.take_messages());
+                issues
+            });
+        match issues {
+            Ok(issues) => {
+                foreign_issues.insert(repo_path.clone(), issues);
+            }
+            Err(e) => messages.push(format!(
+                "Warn: Skipping TODOs referencing {}: {}",
+                repo_path, e
+            )),
+        }
+    }
+    (foreign_issues, messages)
+}
+
+/// compare_todos_and_issues returns the unreported TODOs that have no issue with the same title.
+pub fn compare_todos_and_issues(todos: &[Todo], issues: &[Issue]) -> Vec<Todo> {
+    let mut todos_to_create: Vec<Todo> = Vec::new();
+
+    for todo in todos {
+        if todo.issue_number == 0 && !find_issue_by_title(issues, &todo.title) {
+            todos_to_create.push(todo.clone());
+        }
+    }
+
+    todos_to_create
+}
+
+/// find_todos_to_cleanup returns the TODOs whose issue is closed. The issues of other
+/// repositories referenced by TODOs are looked up in foreign_issues by repository path.
+pub fn find_todos_to_cleanup(
+    todos: &[Todo],
+    issues: &[Issue],
+    foreign_issues: &HashMap<String, Vec<Issue>>,
+) -> Vec<Todo> {
+    let mut todos_to_cleanup: Vec<Todo> = Vec::new();
+
+    for todo in todos {
+        if todo.issue_number > 0 {
+            let issues = match &todo.issue_repo {
+                Some(repo) => match foreign_issues.get(repo) {
+                    Some(issues) => issues,
+                    None => continue,
+                },
+                None => issues,
+            };
+            if let Some(issue) = find_issue_by_number(issues, todo.issue_number) {
+                if issue.state == "closed" {
+                    todos_to_cleanup.push(todo.clone());
+                }
+            }
+        }
+    }
+
+    todos_to_cleanup
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn todo(line_number: usize, title: &str, issue_number: IssueNumber) -> Todo {
+        let marker = if issue_number == 0 {
+            "TODO:".to_string()
+        } else {
+            format!("TODO (#{}):", issue_number)
+        };
+        Todo {
+            file_path: "a.rs".to_string(),
+            line_number,
+            keyword: "TODO".to_string(),
+            title: title.to_string(),
+            issue_number,
+            issue_repo: None,
+            line: format!("// {} {}", marker, title),
+            marker: 3..3 + marker.len(),
+        }
+    }
+
+    fn issue(number: IssueNumber, title: &str, state: &str, body: &str) -> Issue {
+        Issue {
+            number,
+            title: title.to_string(),
+            state: state.to_string(),
+            updated_at: String::new(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn plans_round_trip_through_json() {
+        let todos = vec![
+            todo(0, "new", 0),
+            todo(1, "done", 1),
+            todo(2, "old title", 2),
+            todo(3, "also done", 4),
+        ];
+        let issues = KnownIssues {
+            project: vec![
+                issue(1, "done", "closed", ""),
+                issue(2, "new title", "open", ""),
+                issue(
+                    3,
+                    "orphan",
+                    "open",
+                    "text\n\n<!-- issuefer:github.com/owner/repo:x -->",
+                ),
+                issue(4, "also done", "closed", ""),
+                issue(5, "manual", "open", ""),
+            ],
+            target: None,
+            foreign: HashMap::new(),
+        };
+        let project = Project {
+            host: "github.com".to_string(),
+            owner: "owner".to_string(),
+            repo: "repo".to_string(),
+        };
+        let options = PlanOptions {
+            sync_titles: true,
+            close_orphaned: true,
+        };
+        let plan = SyncPlan::new(
+            project.clone(),
+            None,
+            &todos,
+            &issues,
+            &config::GeneralConfig::new(),
+            options,
+        );
+        let plan = SyncPlan::from_json(&plan.to_json().unwrap()).unwrap();
+        assert_eq!(plan.project, project);
+        let described: Vec<String> = plan.actions.iter().map(Action::describe).collect();
+        assert_eq!(
+            described,
+            vec![
+                "Create issue 'new' for a.rs:1",
+                "Add the issue of new-1 to a.rs:1",
+                "Change the title of a.rs:3 to 'new title' like issue #2",
+                "Close issue #3 with title 'orphan', its TODO is gone",
+                // From the bottom of the file up
+                "Remove a.rs:4 of closed issue #4",
+                "Remove a.rs:2 of closed issue #1",
+            ]
+        );
+
+        let mut plan = plan;
+        plan.retain(|action| !matches!(action, Action::CreateIssue { .. }));
+        assert_eq!(plan.actions.len(), 4);
+        assert!(SyncPlan::from_json(
+            &plan
+                .to_json()
+                .unwrap()
+                .replace(&format!("\"version\": {}", PLAN_VERSION), "\"version\": 99")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn explicit_references_to_the_project_keep_issues_open() {
+        let mut explicit = todo(0, "explicit", 1);
+        explicit.issue_repo = Some("Owner/Repo".to_string());
+        let mut other = todo(1, "other project", 2);
+        other.issue_repo = Some("owner/other".to_string());
+        let issues = KnownIssues {
+            project: vec![
+                issue(
+                    1,
+                    "explicit",
+                    "open",
+                    "<!-- issuefer:github.com/owner/repo:a -->",
+                ),
+                issue(
+                    2,
+                    "other project",
+                    "open",
+                    "<!-- issuefer:github.com/owner/repo:b -->",
+                ),
+            ],
+            target: None,
+            foreign: HashMap::new(),
+        };
+        let project = Project {
+            host: "github.com".to_string(),
+            owner: "owner".to_string(),
+            repo: "repo".to_string(),
+        };
+        let options = PlanOptions {
+            sync_titles: false,
+            close_orphaned: true,
+        };
+        let plan = SyncPlan::new(
+            project,
+            None,
+            &[explicit, other],
+            &issues,
+            &config::GeneralConfig::new(),
+            options,
+        );
+        let described: Vec<String> = plan.actions.iter().map(Action::describe).collect();
+        assert_eq!(
+            described,
+            ["Close issue #2 with title 'other project', its TODO is gone"]
+        );
+    }
+
+    #[test]
+    fn only_issues_of_the_project_are_closed() {
+        let project = |repo: &str| Project {
+            host: "github.com".to_string(),
+            owner: "owner".to_string(),
+            repo: repo.to_string(),
+        };
+        let server_issues = vec![
+            issue(1, "of a", "open", &project("client-a").marker("x")),
+            issue(2, "of b", "open", &project("client-b").marker("y")),
+            issue(3, "manual", "open", ""),
+        ];
+        let options = PlanOptions {
+            sync_titles: false,
+            close_orphaned: true,
+        };
+        let plan = |project: Project, target_repo: Option<&str>| {
+            // Issues are reported to the server, or to the project itself if it is the server
+            let issues = KnownIssues {
+                project: match target_repo {
+                    Some(_) => Vec::new(),
+                    None => server_issues.clone(),
+                },
+                target: target_repo.map(|_| server_issues.clone()),
+                foreign: HashMap::new(),
+            };
+            let plan = SyncPlan::new(
+                project,
+                target_repo.map(str::to_string),
+                &[],
+                &issues,
+                &config::GeneralConfig::new(),
+                options,
+            );
+            plan.actions
+                .iter()
+                .map(Action::describe)
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            plan(project("client-a"), Some("owner/server")),
+            ["Close issue owner/server#1 with title 'of a', its TODO is gone"]
+        );
+        assert_eq!(
+            plan(project("client-b"), Some("owner/server")),
+            ["Close issue owner/server#2 with title 'of b', its TODO is gone"]
+        );
+        assert!(plan(project("server"), None).is_empty());
+    }
+}
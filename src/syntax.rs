@@ -0,0 +1,105 @@
+use std::ops::Range;
+use std::path::Path;
+use tree_sitter::{Language, Parser};
+
+/// Comment is a comment node of a source file.
+pub struct Comment {
+    /// Byte range of the comment in the file
+    pub span: Range<usize>,
+    /// Line the comment starts in, counted from 0
+    pub line: usize,
+    /// Byte offset of the start of the comment in its line
+    pub column: usize,
+}
+
+// language returns the grammar for the file, judged by its extension.
+fn language(path: &str) -> Option<Language> {
+    let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+    let language = match extension.as_str() {
+        "rs" => tree_sitter_rust::LANGUAGE,
+        "c" | "h" => tree_sitter_c::LANGUAGE,
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => tree_sitter_cpp::LANGUAGE,
+        "py" | "pyi" => tree_sitter_python::LANGUAGE,
+        "js" | "jsx" | "mjs" | "cjs" => tree_sitter_javascript::LANGUAGE,
+        "ts" | "mts" | "cts" => tree_sitter_typescript::LANGUAGE_TYPESCRIPT,
+        "tsx" => tree_sitter_typescript::LANGUAGE_TSX,
+        "go" => tree_sitter_go::LANGUAGE,
+        "java" => tree_sitter_java::LANGUAGE,
+        "sh" | "bash" => tree_sitter_bash::LANGUAGE,
+        _ => return None,
+    };
+    Some(language.into())
+}
+
+/// comments returns the comments of the file in order. None is returned if there is no grammar
+/// for the language of the file or it could not be parsed, the caller falls back to scanning
+/// lines then.
+pub fn comments(path: &str, content: &[u8]) -> Option<Vec<Comment>> {
+    let mut parser = Parser::new();
+    parser.set_language(&language(path)?).ok()?;
+    let tree = parser.parse(content, None)?;
+    let mut comments = Vec::new();
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        // e.g., comment, or line_comment and block_comment in Rust and Java
+        let is_comment = node.kind().ends_with("comment");
+        if is_comment {
+            comments.push(Comment {
+                span: node.byte_range(),
+                line: node.start_position().row,
+                column: node.start_position().column,
+            });
+        } else if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return Some(comments);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment_texts(path: &str, content: &str) -> Vec<String> {
+        comments(path, content.as_bytes())
+            .unwrap()
+            .iter()
+            .map(|c| content[c.span.clone()].trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn strings_are_not_comments() {
+        let python = "s = \"\"\"\n# TODO: not a comment\n\"\"\"\nx = 1  # TODO: a comment\n";
+        assert_eq!(comment_texts("a.py", python), ["# TODO: a comment"]);
+
+        let shell = "cat <<EOF\n# TODO: not a comment\nEOF\n# TODO: a comment\n";
+        assert_eq!(comment_texts("a.sh", shell), ["# TODO: a comment"]);
+
+        let rust = "let s = \"// TODO: not a comment\";\n/* a */ f(); // TODO: a comment\n";
+        assert_eq!(
+            comment_texts("a.rs", rust),
+            ["/* a */", "// TODO: a comment"]
+        );
+    }
+
+    #[test]
+    fn comments_have_their_position() {
+        let content = "int x;\nint y; // TODO: z\n";
+        let comments = comments("a.c", content.as_bytes()).unwrap();
+        assert_eq!(comments.len(), 1);
+        assert_eq!((comments[0].line, comments[0].column), (1, 7));
+    }
+
+    #[test]
+    fn other_languages_have_no_grammar() {
+        assert!(comments("README.md", b"# TODO: x\n").is_none());
+        assert!(comments("Makefile", b"# TODO: x\n").is_none());
+        assert!(comments("src/App.TSX", b"// TODO: x\n").is_some());
+    }
+}
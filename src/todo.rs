@@ -1,11 +1,60 @@
+use crate::issueapi::IssueNumber;
+
+use regex::Regex;
 use std::fmt;
+use std::ops::Range;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Todo {
     pub file_path: String,
     pub line_number: usize,
+    pub keyword: String,
     pub title: String,
-    pub issue_number: u16,
+    pub issue_number: IssueNumber,
+    /// Path of the repository the issue belongs to (e.g., owner/repo or group/subgroup/project),
+    /// None for issues of the current repository
+    pub issue_repo: Option<String>,
+    // The line as it was scanned and the byte range of the marker in it, i.e., "TODO:" or
+    // "TODO (#12):", so the marker can be rewritten exactly
+    pub line: String,
+    pub marker: Range<usize>,
+}
+
+impl Todo {
+    /// reference returns the issue reference as written in the source, e.g., #12 or owner/repo#12.
+    pub fn reference(&self) -> String {
+        format_reference(self.issue_repo.as_deref(), self.issue_number)
+    }
+}
+
+// IGNORED is written instead of an issue reference to mark a TODO that is never reported, i.e.,
+// "TODO (II):". The parser does not recognize such TODOs.
+pub const IGNORED: &str = "II";
+
+// format_marker returns the marker of a TODO with the given issue reference, e.g., "TODO:" or
+// "TODO (#12):".
+pub fn format_marker(keyword: &str, reference: Option<&str>) -> String {
+    match reference {
+        Some(reference) => format!("{} ({}):", keyword, reference),
+        None => format!("{}:", keyword),
+    }
+}
+
+pub fn format_reference(repo: Option<&str>, issue_number: IssueNumber) -> String {
+    match repo {
+        Some(repo) => format!("{}#{}", repo, issue_number),
+        None => format!("#{}", issue_number),
+    }
+}
+
+/// split_repo_path splits a repository path into owner (which may contain slashes for GitLab
+/// subgroups) and repository name.
+pub fn split_repo_path(path: &str) -> Option<(String, String)> {
+    let (owner, repo) = path.rsplit_once('/')?;
+    if owner.is_empty() || repo.is_empty() {
+        return None;
+    }
+    Some((owner.to_string(), repo.to_string()))
 }
 
 impl fmt::Display for Todo {
@@ -13,20 +62,207 @@ impl fmt::Display for Todo {
         if self.issue_number == 0 {
             write!(
                 f,
-                "{}:{}: TODO: {}",
+                "{}:{}: {}: {}",
                 self.file_path,
                 self.line_number + 1,
+                self.keyword,
                 self.title
             )
         } else {
             write!(
                 f,
-                "{}:{}: TODO (#{}): {}",
+                "{}:{}: {} ({}): {}",
                 self.file_path,
                 self.line_number + 1,
-                self.issue_number,
+                self.keyword,
+                self.reference(),
                 self.title
             )
         }
     }
 }
+
+/// TodoParser recognizes comment lines that start with one of the configured keywords.
+pub struct TodoParser {
+    keyword_re: regex::bytes::Regex,
+    todo_re: Regex,
+    todo_seen_re: Regex,
+    repo_path_re: Regex,
+}
+
+impl TodoParser {
+    pub fn new(keywords: &[String]) -> TodoParser {
+        let keywords = keywords
+            .iter()
+            .map(|k| regex::escape(k))
+            .collect::<Vec<String>>()
+            .join("|");
+        TodoParser {
+            keyword_re: regex::bytes::Regex::new(&keywords).unwrap(),
+            todo_re: Regex::new(&format!(
+                r"^\s*(?://|#)\s*(?P<marker>(?P<keyword>{}):)\s+(?P<title>.*)$",
+                keywords
+            ))
+            .unwrap(),
+            todo_seen_re: Regex::new(&format!(
+                r"^\s*(?://|#)\s*(?P<marker>(?P<keyword>{}) \((?P<repo>[^)#]*)#(?P<number>[^)]*)\):)\s+(?P<title>.*)$",
+                keywords
+            ))
+            .unwrap(),
+            repo_path_re: Regex::new(r"^[\w.-]+(/[\w.-]+)+$").unwrap(),
+        }
+    }
+
+    /// may_contain_todo is a cheap check whether any keyword occurs in the content at all, which
+    /// lets most files skip the per-line matching.
+    pub fn may_contain_todo(&self, content: &[u8]) -> bool {
+        self.keyword_re.is_match(content)
+    }
+
+    // parse_line returns the TODO in the line, if there is one. A TODO whose issue reference
+    // is not a valid issue number is skipped and an error describing it is returned instead.
+    pub fn parse_line(
+        &self,
+        file_path: &str,
+        line_number: usize,
+        line: &str,
+    ) -> Result<Option<Todo>, String> {
+        if let Some(x) = self.todo_re.captures(line) {
+            let t = Todo {
+                file_path: file_path.to_string(),
+                line_number,
+                keyword: x["keyword"].to_string(),
+                title: x["title"].to_string(),
+                issue_number: 0,
+                issue_repo: None,
+                line: line.to_string(),
+                marker: x.name("marker").map_or(0..0, |m| m.range()),
+            };
+            return Ok(Some(t));
+        }
+
+        if let Some(x) = self.todo_seen_re.captures(line) {
+            let warning =
+                |e: String| format!("{}:{}: skipping TODO, {}", file_path, line_number + 1, e);
+            let issue_repo = match &x["repo"] {
+                "" => None,
+                repo if self.repo_path_re.is_match(repo) => Some(repo.to_string()),
+                repo => {
+                    return Err(warning(format!("'{}' is not a repository path", repo)));
+                }
+            };
+            let issue_number = parse_issue_number(&x["number"]).map_err(warning)?;
+            let t = Todo {
+                file_path: file_path.to_string(),
+                line_number,
+                keyword: x["keyword"].to_string(),
+                title: x["title"].to_string(),
+                issue_number,
+                issue_repo,
+                line: line.to_string(),
+                marker: x.name("marker").map_or(0..0, |m| m.range()),
+            };
+            return Ok(Some(t));
+        }
+        Ok(None)
+    }
+}
+
+// parse_issue_number parses the N of a "(#N)" issue reference.
+fn parse_issue_number(reference: &str) -> Result<IssueNumber, String> {
+    if reference.is_empty() || !reference.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("'#{}' is not an issue number", reference));
+    }
+    match reference.parse::<IssueNumber>() {
+        Ok(0) => Err("issue number #0 is not valid".to_string()),
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!("issue number #{} is too large", reference)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Option<Todo>, String> {
+        TodoParser::new(&["TODO".to_string()]).parse_line("a.rs", 0, line)
+    }
+
+    #[test]
+    fn issue_numbers_beyond_u16() {
+        for number in &[1, 65535, 65536, 1_000_000, IssueNumber::MAX] {
+            let todo = parse(&format!("// TODO (#{}): title", number))
+                .unwrap()
+                .unwrap();
+            assert_eq!(todo.issue_number, *number);
+            assert_eq!(todo.title, "title");
+        }
+    }
+
+    #[test]
+    fn overflowing_issue_number_is_a_warning() {
+        let e = parse("// TODO (#18446744073709551616): title").unwrap_err();
+        assert_eq!(
+            e,
+            "a.rs:1: skipping TODO, issue number #18446744073709551616 is too large"
+        );
+    }
+
+    #[test]
+    fn malformed_issue_numbers_are_warnings() {
+        for reference in &["", "0", "abc", "12a", "-1", "+1", " 1"] {
+            assert!(
+                parse(&format!("// TODO (#{}): title", reference)).is_err(),
+                "'{}' should not parse",
+                reference
+            );
+        }
+    }
+
+    #[test]
+    fn cross_repository_references() {
+        let todo = parse("// TODO (owner/repo#12): title").unwrap().unwrap();
+        assert_eq!(todo.issue_repo.as_deref(), Some("owner/repo"));
+        assert_eq!(todo.issue_number, 12);
+        assert_eq!(todo.reference(), "owner/repo#12");
+
+        let todo = parse("// TODO (group/sub.group/my-project#7): title")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            todo.issue_repo.as_deref(),
+            Some("group/sub.group/my-project")
+        );
+        assert_eq!(
+            split_repo_path("group/sub.group/my-project"),
+            Some(("group/sub.group".to_string(), "my-project".to_string()))
+        );
+
+        for repo in &["repo", "owner/", "/repo", "owner repo/x"] {
+            assert!(parse(&format!("// TODO ({}#12): title", repo)).is_err());
+        }
+    }
+
+    #[test]
+    fn marker_span_for_every_comment_style() {
+        for (line, marker) in &[
+            ("// TODO: x", "TODO:"),
+            ("//TODO:  x", "TODO:"),
+            ("\t# TODO: x", "TODO:"),
+            ("#TODO:\tx", "TODO:"),
+            ("    //  TODO (#12): x", "TODO (#12):"),
+            ("# TODO (owner/repo#12): x", "TODO (owner/repo#12):"),
+        ] {
+            let todo = parse(line).unwrap().unwrap();
+            assert_eq!(&todo.line[todo.marker.clone()], *marker);
+            assert_eq!(todo.title, "x");
+        }
+    }
+
+    #[test]
+    fn unreported_todo() {
+        let todo = parse("    # TODO: title").unwrap().unwrap();
+        assert_eq!(todo.issue_number, 0);
+        assert!(parse("let x = 1;").unwrap().is_none());
+    }
+}
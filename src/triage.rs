@@ -0,0 +1,552 @@
+use crate::editor;
+use crate::issueapi::NewIssue;
+use crate::sync;
+use crate::todo::{self, Todo};
+
+use crossterm::cursor;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{execute, queue};
+use std::io::{self, IsTerminal, Write};
+
+// Lines of code shown above and below the TODO in the preview
+const PREVIEW_CONTEXT: usize = 3;
+
+const HELP: &str = "up/down move  space select  * all  t title  b body  e editor  l labels  a assignee  i ignore  enter apply  q quit";
+
+// is_available tells whether the terminal UI can be used, i.e., whether stdin and stdout are
+// terminals. Otherwise issuefer asks line by line.
+pub fn is_available() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+// Entry is an action of the plan in the list together with what the user decided for it.
+// AnnotateTodo actions are not listed, they follow their CreateIssue action.
+pub struct Entry {
+    // Position of the action in the plan
+    index: usize,
+    action: sync::Action,
+    todo: Option<Todo>,
+    // The issue that is created, only set for CreateIssue actions
+    issue: Option<NewIssue>,
+    pub selected: bool,
+    pub ignored: bool,
+}
+
+impl Entry {
+    fn new(index: usize, action: &sync::Action) -> Entry {
+        let issue = match action {
+            sync::Action::CreateIssue {
+                title,
+                body,
+                labels,
+                assignees,
+                ..
+            } => Some(NewIssue {
+                title: title.clone(),
+                body: body.clone(),
+                labels: labels.clone(),
+                assignees: assignees.clone(),
+            }),
+            _ => None,
+        };
+        Entry {
+            index,
+            action: action.clone(),
+            todo: action.todo().map(sync::PlannedTodo::to_todo),
+            issue,
+            selected: false,
+            ignored: false,
+        }
+    }
+
+    fn summary(&self) -> String {
+        let mark = if self.ignored {
+            "[i]"
+        } else if self.selected {
+            "[x]"
+        } else {
+            "[ ]"
+        };
+        let (action, title) = match (&self.action, &self.issue) {
+            (_, Some(issue)) => ("report ", issue.title.clone()),
+            (sync::Action::UpdateTitle { title, .. }, _) => ("retitle", title.clone()),
+            (sync::Action::CloseIssue { title, .. }, _) => ("close  ", title.clone()),
+            _ => (
+                "cleanup",
+                self.todo
+                    .as_ref()
+                    .map_or(String::new(), |t| t.title.clone()),
+            ),
+        };
+        match (&self.action, &self.todo) {
+            (sync::Action::CloseIssue { repo, number, .. }, _) => format!(
+                "{} {} {}: {}",
+                mark,
+                action,
+                todo::format_reference(repo.as_deref(), *number),
+                title
+            ),
+            (_, Some(todo)) => format!(
+                "{} {} {}:{}: {}",
+                mark,
+                action,
+                todo.file_path,
+                todo.line_number + 1,
+                title
+            ),
+            (_, None) => format!("{} {} {}", mark, action, title),
+        }
+    }
+}
+
+// entries lists the actions of the plan the user decides on.
+pub fn entries(plan: &sync::SyncPlan) -> Vec<Entry> {
+    plan.actions
+        .iter()
+        .enumerate()
+        .filter(|(_, action)| !matches!(action, sync::Action::AnnotateTodo { .. }))
+        .map(|(index, action)| Entry::new(index, action))
+        .collect()
+}
+
+// decide applies the decisions to the plan: the edited issues are written back and only the
+// selected actions are kept. It returns the TODOs to ignore.
+fn decide(plan: &mut sync::SyncPlan, entries: Vec<Entry>) -> Vec<Todo> {
+    let mut keep = vec![true; plan.actions.len()];
+    let mut ignore = Vec::new();
+    for entry in entries {
+        keep[entry.index] = entry.selected && !entry.ignored;
+        if entry.ignored {
+            ignore.extend(entry.todo);
+        }
+        if let (
+            Some(issue),
+            sync::Action::CreateIssue {
+                title,
+                body,
+                labels,
+                assignees,
+                ..
+            },
+        ) = (entry.issue, &mut plan.actions[entry.index])
+        {
+            *title = issue.title;
+            *body = issue.body;
+            *labels = issue.labels;
+            *assignees = issue.assignees;
+        }
+    }
+    let mut keep = keep.into_iter();
+    plan.retain(|_| keep.next().unwrap_or(false));
+    ignore
+}
+
+// TerminalGuard restores the terminal when the UI ends, also on errors and panics.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> io::Result<TerminalGuard> {
+        terminal::enable_raw_mode()?;
+        let guard = TerminalGuard;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// suspended runs f with the terminal restored, e.g., to run an editor in it.
+fn suspended<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    let error = |e: io::Error| format!("Could not switch the terminal: {}", e);
+    execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen).map_err(error)?;
+    terminal::disable_raw_mode().map_err(error)?;
+    let result = f();
+    terminal::enable_raw_mode().map_err(error)?;
+    execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide).map_err(error)?;
+    Ok(result)
+}
+
+// run shows the terminal UI for the actions of the plan and keeps the selected ones. It returns
+// the TODOs to ignore, or None if the user quit without applying.
+pub fn run(plan: &mut sync::SyncPlan) -> Result<Option<Vec<Todo>>, String> {
+    let mut entries = entries(plan);
+    if entries.is_empty() {
+        return Ok(Some(Vec::new()));
+    }
+    let _guard = TerminalGuard::enter().map_err(|e| format!("Could not start the UI: {}", e))?;
+    let mut ui = Ui {
+        current: 0,
+        scroll: 0,
+        status: String::new(),
+    };
+    loop {
+        ui.draw(&entries)
+            .map_err(|e| format!("Could not draw the UI: {}", e))?;
+        let key = match read_key().map_err(|e| format!("Could not read key: {}", e))? {
+            Some(key) => key,
+            None => continue,
+        };
+        ui.status.clear();
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(None),
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+            KeyCode::Enter => return Ok(Some(decide(plan, entries))),
+            KeyCode::Up | KeyCode::Char('k') => ui.current = ui.current.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                ui.current = (ui.current + 1).min(entries.len().saturating_sub(1))
+            }
+            KeyCode::Char(' ') => {
+                let entry = &mut entries[ui.current];
+                entry.selected = !entry.selected;
+                entry.ignored = false;
+            }
+            KeyCode::Char('*') => {
+                let select = !entries.iter().filter(|e| !e.ignored).all(|e| e.selected);
+                for entry in entries.iter_mut().filter(|e| !e.ignored) {
+                    entry.selected = select;
+                }
+            }
+            KeyCode::Char(key @ ('t' | 'b' | 'e' | 'l' | 'a' | 'i')) => {
+                let entry = &mut entries[ui.current];
+                let (todo, issue) = match (&entry.todo, &mut entry.issue) {
+                    (Some(todo), Some(issue)) => (todo, issue),
+                    _ => {
+                        ui.status = "Only issues to create can be edited or ignored".to_string();
+                        continue;
+                    }
+                };
+                match key {
+                    'i' => {
+                        entry.ignored = !entry.ignored;
+                        entry.selected = false;
+                    }
+                    't' => {
+                        if let Some(title) = ui.prompt("Title", &issue.title)? {
+                            if title.trim().is_empty() {
+                                ui.status = "The title must not be empty".to_string();
+                            } else {
+                                issue.title = title;
+                            }
+                        }
+                    }
+                    'b' => {
+                        // The prompt is a single line, line breaks are written as \n
+                        if let Some(body) =
+                            ui.prompt("Body (\\n for line breaks)", &escape(&issue.body))?
+                        {
+                            issue.body = unescape(&body);
+                        }
+                    }
+                    'e' => match suspended(|| editor::edit_issue(todo, issue))? {
+                        Ok(Some(edited)) => *issue = edited,
+                        Ok(None) => {
+                            ui.status = "The title is empty, the issue is unchanged".to_string()
+                        }
+                        Err(e) => ui.status = e,
+                    },
+                    'l' => {
+                        if let Some(labels) =
+                            ui.prompt("Labels (comma separated)", &issue.labels.join(", "))?
+                        {
+                            issue.labels = split_list(&labels);
+                        }
+                    }
+                    _ => {
+                        if let Some(assignees) =
+                            ui.prompt("Assignees (comma separated)", &issue.assignees.join(", "))?
+                        {
+                            issue.assignees = split_list(&assignees);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// read_key waits for the next key press, other events are ignored.
+fn read_key() -> io::Result<Option<KeyEvent>> {
+    match event::read()? {
+        Event::Key(key) if key.kind != KeyEventKind::Release => Ok(Some(key)),
+        _ => Ok(None),
+    }
+}
+
+struct Ui {
+    current: usize,
+    scroll: usize,
+    status: String,
+}
+
+impl Ui {
+    fn draw(&mut self, entries: &[Entry]) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        let entry = &entries[self.current];
+        let details = details(entry);
+        // Header, separator and status line take three lines, the list gets at least three
+        let list_height = height
+            .saturating_sub(details.len() + 3)
+            .max(3)
+            .min(entries.len());
+        if self.current < self.scroll {
+            self.scroll = self.current;
+        } else if self.current >= self.scroll + list_height {
+            self.scroll = self.current + 1 - list_height;
+        }
+
+        let mut out = io::stdout();
+        queue!(out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+        let mut lines: Vec<(String, bool)> = vec![(HELP.to_string(), false)];
+        for (index, entry) in entries
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(list_height)
+        {
+            lines.push((entry.summary(), index == self.current));
+        }
+        lines.push(("-".repeat(width), false));
+        lines.extend(details.into_iter().map(|line| (line, false)));
+        for (row, (line, highlight)) in lines.iter().take(height.saturating_sub(1)).enumerate() {
+            queue!(out, cursor::MoveTo(0, row as u16))?;
+            if *highlight {
+                queue!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(out, Print(truncate(line, width)))?;
+            if *highlight {
+                queue!(out, SetAttribute(Attribute::Reset))?;
+            }
+        }
+        queue!(
+            out,
+            cursor::MoveTo(0, height.saturating_sub(1) as u16),
+            Print(truncate(&self.status, width))
+        )?;
+        out.flush()
+    }
+
+    // prompt edits value in the status line. It returns None if the user pressed Esc.
+    fn prompt(&mut self, label: &str, value: &str) -> Result<Option<String>, String> {
+        let mut value: Vec<char> = value.chars().collect();
+        let mut cursor_at = value.len();
+        let result = (|| -> io::Result<Option<String>> {
+            let mut out = io::stdout();
+            execute!(out, cursor::Show)?;
+            loop {
+                let (width, height) = terminal::size()?;
+                let prefix = format!("{}: ", label);
+                let text: String = value.iter().collect();
+                // Long values scroll so the cursor stays visible
+                let room = (width as usize)
+                    .saturating_sub(prefix.chars().count() + 1)
+                    .max(1);
+                let skip = cursor_at.saturating_sub(room);
+                let visible: String = text.chars().skip(skip).take(room).collect();
+                queue!(
+                    out,
+                    cursor::MoveTo(0, height.saturating_sub(1)),
+                    terminal::Clear(ClearType::CurrentLine),
+                    Print(&prefix),
+                    Print(visible),
+                    cursor::MoveTo(
+                        (prefix.chars().count() + cursor_at - skip) as u16,
+                        height.saturating_sub(1)
+                    )
+                )?;
+                out.flush()?;
+                let key = match read_key()? {
+                    Some(key) => key,
+                    None => continue,
+                };
+                match key.code {
+                    KeyCode::Enter => return Ok(Some(value.iter().collect())),
+                    KeyCode::Esc => return Ok(None),
+                    KeyCode::Left => cursor_at = cursor_at.saturating_sub(1),
+                    KeyCode::Right => cursor_at = (cursor_at + 1).min(value.len()),
+                    KeyCode::Home => cursor_at = 0,
+                    KeyCode::End => cursor_at = value.len(),
+                    KeyCode::Backspace if cursor_at > 0 => {
+                        cursor_at -= 1;
+                        value.remove(cursor_at);
+                    }
+                    KeyCode::Delete if cursor_at < value.len() => {
+                        value.remove(cursor_at);
+                    }
+                    KeyCode::Char(c) => {
+                        value.insert(cursor_at, c);
+                        cursor_at += 1;
+                    }
+                    _ => {}
+                }
+            }
+        })();
+        let _ = execute!(io::stdout(), cursor::Hide);
+        result.map_err(|e| format!("Could not read input: {}", e))
+    }
+}
+
+// details returns the code around the TODO and what the action does, e.g., the issue to create.
+fn details(entry: &Entry) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some(todo) = &entry.todo {
+        match std::fs::read(&todo.file_path) {
+            Ok(content) => {
+                let content = String::from_utf8_lossy(&content);
+                let first = todo.line_number.saturating_sub(PREVIEW_CONTEXT);
+                for (number, line) in content
+                    .lines()
+                    .enumerate()
+                    .skip(first)
+                    .take(todo.line_number - first + PREVIEW_CONTEXT + 1)
+                {
+                    let arrow = if number == todo.line_number { ">" } else { " " };
+                    lines.push(format!(
+                        "{}{:5} {}",
+                        arrow,
+                        number + 1,
+                        line.replace('\t', "    ")
+                    ));
+                }
+            }
+            Err(e) => lines.push(format!("{}: {}", todo.file_path, e)),
+        }
+        lines.push(String::new());
+    }
+    if let Some(issue) = &entry.issue {
+        lines.push(format!("Title:     {}", issue.title));
+        lines.push(format!("Labels:    {}", issue.labels.join(", ")));
+        lines.push(format!("Assignees: {}", issue.assignees.join(", ")));
+        lines.push("Body:".to_string());
+        lines.extend(issue.body.lines().map(|line| format!("  {}", line)));
+    } else {
+        lines.push(entry.action.describe());
+    }
+    lines
+}
+
+fn truncate(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn escape(body: &str) -> String {
+    body.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(body: &str) -> String {
+    let mut result = String::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some(c) => result.push(c),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn todo(line_number: usize) -> sync::PlannedTodo {
+        sync::PlannedTodo::new(&Todo {
+            file_path: "a.rs".to_string(),
+            line_number,
+            keyword: "TODO".to_string(),
+            title: "title".to_string(),
+            issue_number: 0,
+            issue_repo: None,
+            line: "// TODO: title".to_string(),
+            marker: 3..8,
+        })
+    }
+
+    fn create(id: &str, line_number: usize) -> Vec<sync::Action> {
+        vec![
+            sync::Action::CreateIssue {
+                id: id.to_string(),
+                todo: todo(line_number),
+                title: "title".to_string(),
+                body: String::new(),
+                labels: Vec::new(),
+                assignees: Vec::new(),
+            },
+            sync::Action::AnnotateTodo {
+                todo: todo(line_number),
+                issue: sync::IssueRef::Planned(id.to_string()),
+            },
+        ]
+    }
+
+    #[test]
+    fn decisions_are_applied_to_the_plan() {
+        let mut actions = create("new-1", 0);
+        actions.extend(create("new-2", 1));
+        actions.extend(create("new-3", 2));
+        actions.push(sync::Action::RemoveTodo { todo: todo(3) });
+        let mut plan = sync::SyncPlan {
+            version: sync::PLAN_VERSION,
+            project: sync::Project {
+                host: "github.com".to_string(),
+                owner: "owner".to_string(),
+                repo: "repo".to_string(),
+            },
+            target_repo: None,
+            actions,
+        };
+        let mut entries = entries(&plan);
+        assert_eq!(entries.len(), 4);
+        entries[0].selected = true;
+        if let Some(issue) = &mut entries[0].issue {
+            issue.assignees = vec!["someone".to_string()];
+        }
+        entries[2].ignored = true;
+        entries[3].selected = true;
+        let ignore = decide(&mut plan, entries);
+        assert_eq!(ignore.len(), 1);
+        assert_eq!(ignore[0].line_number, 2);
+        assert_eq!(plan.actions.len(), 3);
+        match &plan.actions[0] {
+            sync::Action::CreateIssue { id, assignees, .. } => {
+                assert_eq!(id, "new-1");
+                assert_eq!(assignees, &vec!["someone".to_string()]);
+            }
+            _ => panic!("expected the first issue to be created"),
+        }
+        assert!(matches!(
+            &plan.actions[1],
+            sync::Action::AnnotateTodo { issue: sync::IssueRef::Planned(id), .. } if id == "new-1"
+        ));
+        assert!(matches!(&plan.actions[2], sync::Action::RemoveTodo { .. }));
+    }
+
+    #[test]
+    fn bodies_round_trip_through_the_prompt() {
+        for body in &["", "a\nb", "C:\\n\\path\n", "trailing \\"] {
+            assert_eq!(unescape(&escape(body)), *body);
+        }
+        assert_eq!(split_list(" a, ,b ,"), vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::issueapi::{Issue, IssueAPI, IssueNumber, ReferenceKind};
+use crate::todo::{self, Todo};
+use crate::workspace::Workspace;
+
+use std::collections::{BTreeMap, HashSet};
+
+// TODOs referencing the same issue whose titles share less than this fraction of their words
+// are reported as conflicting.
+const MIN_TITLE_SIMILARITY: f64 = 0.5;
+
+pub enum Problem {
+    // The referenced number is neither an issue nor a pull request
+    Dangling(Todo),
+    // The referenced number is a pull request (GitHub) or merge request (GitLab)
+    PullRequest(Todo),
+    // The referenced number could not be looked up, e.g., in offline mode
+    Unknown(Todo, String),
+    // TODOs referencing the same issue with very different titles
+    ConflictingTitles(IssueNumber, Vec<Todo>),
+}
+
+// title_words splits a title into lower case words for comparing titles.
+fn title_words(title: &str) -> HashSet<String> {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+// title_similarity is the number of words two titles have in common divided by the number of
+// distinct words in both of them.
+fn title_similarity(a: &str, b: &str) -> f64 {
+    let a = title_words(a);
+    let b = title_words(b);
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / a.union(&b).count() as f64
+}
+
+// find_problems checks every TODO with an issue reference against the issues of the project.
+pub fn find_problems(api: &dyn IssueAPI, todos: &[Todo], issues: &[Issue]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut by_number: BTreeMap<IssueNumber, Vec<Todo>> = BTreeMap::new();
+    // References to other repositories are checked by cleanup against their own issues
+    for todo in todos
+        .iter()
+        .filter(|t| t.issue_number > 0 && t.issue_repo.is_none())
+    {
+        by_number
+            .entry(todo.issue_number)
+            .or_default()
+            .push(todo.clone());
+    }
+
+    for (number, referencing) in by_number {
+        if !issues.iter().any(|i| i.number == number) {
+            // Only numbers missing from the issue list are looked up, one request each
+            let kind = api.reference_kind(number);
+            for todo in referencing {
+                problems.push(match &kind {
+                    Ok(ReferenceKind::PullRequest) => Problem::PullRequest(todo),
+                    Ok(ReferenceKind::Issue) => continue,
+                    Ok(ReferenceKind::Missing) => Problem::Dangling(todo),
+                    Err(e) => Problem::Unknown(todo, e.clone()),
+                });
+            }
+            continue;
+        }
+        let conflicting = referencing.iter().enumerate().any(|(n, a)| {
+            referencing[n + 1..]
+                .iter()
+                .any(|b| title_similarity(&a.title, &b.title) < MIN_TITLE_SIMILARITY)
+        });
+        if conflicting {
+            problems.push(Problem::ConflictingTitles(number, referencing));
+        }
+    }
+
+    problems
+}
+
+// fix_reference points the TODO at another issue, or removes its reference with new_number
+// None, and commits the change. It returns the commit message.
+pub fn fix_reference(
+    workspace: &dyn Workspace,
+    todo: &Todo,
+    new_number: Option<IssueNumber>,
+) -> Result<String, String> {
+    let reference = new_number.map(|n| todo::format_reference(None, n));
+    crate::workspace::update_file(
+        workspace,
+        todo,
+        Some(&todo::format_marker(&todo.keyword, reference.as_deref())),
+        None,
+    )
+    .map_err(|e| format!("Could not update the TODO: {}", e))?;
+    let message = match new_number {
+        Some(n) => format!("Change TODO #{} to #{}", todo.issue_number, n),
+        None => format!("Remove reference to #{} from TODO", todo.issue_number),
+    };
+    workspace
+        .commit(&todo.file_path, &message)
+        .map_err(|e| format!("Could not commit {}: {}", todo.file_path, e))?;
+    Ok(message)
+}
+
+// differs_from_issue tells whether the title of a TODO has so few words in common with the
+// title of its issue that it likely describes something else.
+pub fn differs_from_issue(todo: &Todo, issue_title: &str) -> bool {
+    title_similarity(&todo.title, issue_title) < MIN_TITLE_SIMILARITY
+}
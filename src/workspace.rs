@@ -0,0 +1,138 @@
+use crate::rewrite;
+use crate::todo::Todo;
+
+use std::io;
+use std::path::Path;
+
+/// Workspace is where issuefer changes the sources: reading and writing files and committing
+/// them. GitWorkspace changes the working tree, the dry run only records the changes.
+pub trait Workspace {
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>, io::Error>;
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<(), io::Error>;
+    fn commit(&self, file_path: &str, message: &str) -> Result<(), String>;
+}
+
+pub struct GitWorkspace;
+
+impl Workspace for GitWorkspace {
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>, io::Error> {
+        std::fs::read(path)
+    }
+
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<(), io::Error> {
+        rewrite::write_atomically(path, content)
+    }
+
+    fn commit(&self, file_path: &str, message: &str) -> Result<(), String> {
+        crate::git::commit(file_path, message)
+    }
+}
+
+pub fn commit_add(
+    workspace: &dyn Workspace,
+    file_path: &str,
+    reference: &str,
+) -> Result<(), String> {
+    workspace.commit(file_path, &format!("Add TODO {}", reference))
+}
+
+pub fn commit_delete(
+    workspace: &dyn Workspace,
+    file_path: &str,
+    reference: &str,
+) -> Result<(), String> {
+    workspace.commit(file_path, &format!("Remove TODO {}", reference))
+}
+
+/// locate returns the TODO with its current line number, also if lines were added or removed
+/// above it since it was scanned. None is returned if the line is gone.
+pub fn locate(workspace: &dyn Workspace, mut todo: Todo) -> Option<Todo> {
+    let content = workspace
+        .read_file(std::path::Path::new(&todo.file_path))
+        .ok()?;
+    let content = String::from_utf8_lossy(&content);
+    let lines: Vec<&str> = content
+        .split('\n')
+        .map(|l| l.strip_suffix('\r').unwrap_or(l))
+        .collect();
+    if lines.get(todo.line_number) != Some(&todo.line.as_str()) {
+        todo.line_number = lines.iter().position(|l| *l == todo.line)?;
+    }
+    Some(todo)
+}
+
+// code_before returns the code in front of the comment of a TODO, given the line up to the
+// marker, e.g., "let x = 1;" for "let x = 1; // ".
+fn code_before(prefix: &str) -> &str {
+    let prefix = prefix.trim_end();
+    prefix
+        .strip_suffix("//")
+        .or_else(|| prefix.strip_suffix('#'))
+        .unwrap_or(prefix)
+        .trim_end()
+}
+
+// update_file replaces the marker of the TODO (e.g., "TODO:") with new_marker and, if given,
+// its title with new_title. If new_marker is None, the line is removed, or only the comment if
+// the TODO follows code. It fails without touching the file if the line changed since it was
+// scanned.
+pub fn update_file(
+    workspace: &dyn Workspace,
+    todo: &Todo,
+    new_marker: Option<&str>,
+    new_title: Option<&str>,
+) -> Result<(), String> {
+    let path = std::path::Path::new(&todo.file_path);
+    let content = workspace
+        .read_file(path)
+        .map_err(|e| format!("{}: {}", todo.file_path, e))?;
+    // The title is the rest of the line after the marker
+    let title_start = todo.line.len() - todo.title.len();
+    if new_title.is_some() && !todo.line[todo.marker.end..].ends_with(&todo.title) {
+        return Err(format!(
+            "{}:{}: could not find the title in '{}'",
+            todo.file_path,
+            todo.line_number + 1,
+            todo.line
+        ));
+    }
+    let mut found = false;
+    let new_content = rewrite::rewrite_content(&content, |cnt, line| {
+        if cnt != todo.line_number || line != todo.line {
+            return rewrite::LineEdit::Keep;
+        }
+        found = true;
+        match new_marker {
+            Some(new_marker) => match new_title {
+                Some(new_title) => rewrite::LineEdit::Splice(
+                    todo.marker.start..line.len(),
+                    format!(
+                        "{}{}{}",
+                        new_marker,
+                        &line[todo.marker.end..title_start],
+                        new_title
+                    ),
+                ),
+                None => rewrite::LineEdit::Splice(todo.marker.clone(), new_marker.to_string()),
+            },
+            // A TODO after code on the same line only takes its comment with it
+            None => match code_before(&line[..todo.marker.start]) {
+                "" => rewrite::LineEdit::Delete,
+                code => rewrite::LineEdit::Splice(code.len()..line.len(), String::new()),
+            },
+        }
+    });
+    let new_content =
+        new_content.map_err(|e| format!("{}: {}, edit it manually", todo.file_path, e))?;
+    if !found {
+        return Err(format!(
+            "{}:{}: the line changed since it was scanned, expected '{}'",
+            todo.file_path,
+            todo.line_number + 1,
+            todo.line
+        ));
+    }
+    workspace
+        .write_file(path, &new_content)
+        .map_err(|e| format!("{}: {}", todo.file_path, e))
+}
@@ -0,0 +1,317 @@
+// The GitHub and GitLab backends against the mock forge, see tests/common.
+mod common;
+
+use common::{MockForge, TOKEN};
+use issuefer::issueapi::{IssueUpdates, ReferenceKind};
+use issuefer::{backend, config, IssueAPI, NewIssue};
+
+fn api(forge: &MockForge) -> Box<dyn IssueAPI> {
+    match forge.kind {
+        common::Kind::GitHub => Box::new(
+            issuefer::github::GitHubAPI::new(
+                TOKEN.to_string(),
+                forge.host(),
+                forge.owner.clone(),
+                forge.repo.clone(),
+            )
+            .with_api_url(&forge.api_url()),
+        ),
+        common::Kind::GitLab => Box::new(
+            issuefer::gitlab::GitLabAPI::new(
+                TOKEN.to_string(),
+                forge.host().to_string(),
+                forge.owner.clone(),
+                forge.repo.clone(),
+            )
+            .with_api_url(&forge.api_url()),
+        ),
+    }
+}
+
+fn new_issue(title: &str, labels: &[&str], assignees: &[&str]) -> NewIssue {
+    NewIssue {
+        title: title.to_string(),
+        body: "Found in src/main.rs".to_string(),
+        labels: labels.iter().map(|l| l.to_string()).collect(),
+        assignees: assignees.iter().map(|a| a.to_string()).collect(),
+    }
+}
+
+fn updated(updates: Result<IssueUpdates, String>) -> (Vec<issuefer::Issue>, Option<String>) {
+    match updates {
+        Ok(IssueUpdates::Updated { issues, etag }) => (issues, etag),
+        Ok(IssueUpdates::NotModified) => panic!("expected updated issues"),
+        Err(e) => panic!("expected updated issues, got: {}", e),
+    }
+}
+
+#[test]
+fn issues_are_read_from_all_pages() {
+    for forge in [MockForge::github(), MockForge::gitlab()] {
+        for n in 1..=5 {
+            forge.add_issue(&format!("Issue {}", n), "closed", "");
+        }
+        forge.add_pull_request("A pull request");
+        forge.set_page_size(2);
+
+        let issues = api(&forge).get_issues().unwrap();
+        let titles: Vec<&str> = issues.iter().map(|i| i.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            ["Issue 1", "Issue 2", "Issue 3", "Issue 4", "Issue 5"]
+        );
+        let (issues, _) = updated(api(&forge).get_issues_since(None, None));
+        assert_eq!(issues.len(), 5);
+        assert!(forge.requests().len() >= 6, "{:?}", forge.requests());
+    }
+}
+
+#[test]
+fn unchanged_issues_are_not_modified() {
+    for forge in [MockForge::github(), MockForge::gitlab()] {
+        forge.add_issue("First", "open", "");
+        let api = api(&forge);
+        let (issues, etag) = updated(api.get_issues_since(None, None));
+        assert_eq!(issues.len(), 1);
+        let etag = etag.expect("an ETag");
+        match api.get_issues_since(Some(&issues[0].updated_at), Some(&etag)) {
+            Ok(IssueUpdates::NotModified) => {}
+            _ => panic!("expected NotModified"),
+        }
+
+        forge.add_issue("Second", "open", "");
+        let (issues, _) = updated(api.get_issues_since(Some(&issues[0].updated_at), Some(&etag)));
+        let titles: Vec<&str> = issues.iter().map(|i| i.title.as_str()).collect();
+        assert_eq!(titles, ["First", "Second"]);
+    }
+}
+
+#[test]
+fn time_zone_offsets_are_encoded() {
+    for forge in [MockForge::github(), MockForge::gitlab()] {
+        let since = "2020-01-01T12:00:00+02:00";
+        updated(api(&forge).get_issues_since(Some(since), None));
+        let name = match forge.kind {
+            common::Kind::GitHub => "since",
+            common::Kind::GitLab => "updated_after",
+        };
+        assert_eq!(forge.requests()[0].param(name), Some(since));
+    }
+}
+
+#[test]
+fn rate_limited_requests_are_retried() {
+    for forge in [MockForge::github(), MockForge::gitlab()] {
+        forge.add_issue("First", "open", "");
+        forge.rate_limit(1, 1);
+        let (issues, _) = updated(api(&forge).get_issues_since(None, None));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(forge.requests().len(), 2);
+    }
+}
+
+#[test]
+fn long_rate_limits_are_reported() {
+    for forge in [MockForge::github(), MockForge::gitlab()] {
+        forge.rate_limit(1, 3600);
+        let e = api(&forge).get_issues_since(None, None).err().unwrap();
+        assert!(e.contains("rate limit exceeded, try again in"), "{}", e);
+        assert_eq!(forge.requests().len(), 1);
+    }
+}
+
+#[test]
+fn errors_include_the_message_of_the_answer() {
+    let github = MockForge::github();
+    github.fail_next(404, "not_found");
+    let e = api(&github).get_issues_since(None, None).err().unwrap();
+    assert_eq!(e, "GitHub answered with status 404 Not Found: Not Found");
+
+    let wrong_token = issuefer::github::GitHubAPI::new(
+        "wrong".to_string(),
+        "github.com",
+        github.owner.clone(),
+        github.repo.clone(),
+    )
+    .with_api_url(&github.api_url());
+    let e = wrong_token.token_info().err().unwrap();
+    assert_eq!(
+        e,
+        "GitHub answered with status 401 Unauthorized: Bad credentials"
+    );
+
+    let e = api(&github).close_issue(7).err().unwrap();
+    assert_eq!(e, "GitHub answered with status 404 Not Found: Not Found");
+
+    let gitlab = MockForge::gitlab();
+    let e = api(&gitlab).close_issue(7).err().unwrap();
+    assert_eq!(
+        e,
+        "GitLab answered with status 404 Not Found: 404 Not found"
+    );
+}
+
+#[test]
+fn rejected_issues_are_not_created() {
+    let github = MockForge::github();
+    assert!(api(&github)
+        .create_issue(&new_issue("Fix it", &[], &["nobody"]))
+        .is_err());
+    let gitlab = MockForge::gitlab();
+    assert!(api(&gitlab).create_issue(&new_issue("", &[], &[])).is_err());
+    assert!(github.issues().is_empty());
+    assert!(gitlab.issues().is_empty());
+}
+
+#[test]
+fn issues_are_created_and_closed() {
+    for forge in [MockForge::github(), MockForge::gitlab()] {
+        forge.add_user("alice");
+        let api = api(&forge);
+        let issue = api
+            .create_issue(&new_issue("Fix it", &["bug", "todo"], &["alice"]))
+            .unwrap();
+        assert_eq!(issue.number, 1);
+        assert_eq!(issue.title, "Fix it");
+
+        let created = forge.issue(1);
+        assert_eq!(created.body, "Found in src/main.rs");
+        assert_eq!(created.labels, ["bug", "todo"]);
+        assert_eq!(created.assignees, ["alice"]);
+
+        api.close_issue(1).unwrap();
+        assert_eq!(forge.issue(1).state, "closed");
+    }
+}
+
+#[test]
+fn references_are_told_apart() {
+    for forge in [MockForge::github(), MockForge::gitlab()] {
+        forge.add_issue("An issue", "open", "");
+        forge.add_pull_request("A pull request");
+        let api = api(&forge);
+        assert!(matches!(api.reference_kind(1), Ok(ReferenceKind::Issue)));
+        assert!(matches!(
+            api.reference_kind(2),
+            Ok(ReferenceKind::PullRequest)
+        ));
+        assert!(matches!(api.reference_kind(3), Ok(ReferenceKind::Missing)));
+    }
+}
+
+#[test]
+fn tokens_are_introspected() {
+    let info = api(&MockForge::github()).token_info().unwrap();
+    assert_eq!(info.user, "octocat");
+    assert_eq!(info.can_write_issues, Some(true));
+    let info = api(&MockForge::gitlab()).token_info().unwrap();
+    assert_eq!(info.user, "john_smith");
+    assert_eq!(info.scopes, Some(vec!["api".to_string()]));
+}
+
+#[test]
+fn api_url_is_read_from_the_config() {
+    for forge in [MockForge::github(), MockForge::gitlab()] {
+        forge.add_issue("First", "open", "");
+        let path = std::env::temp_dir().join(format!(
+            "issuefer-test-config-{}-{}",
+            std::process::id(),
+            forge.host()
+        ));
+        std::fs::write(
+            &path,
+            format!(
+                "[{}]\ntoken = {}\napi_url = {}/\n",
+                forge.host(),
+                TOKEN,
+                forge.api_url()
+            ),
+        )
+        .unwrap();
+        let config = config::Config::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let api = backend::get_api_for_host(
+            &config,
+            forge.host(),
+            forge.owner.clone(),
+            forge.repo.clone(),
+        )
+        .unwrap();
+        assert_eq!(api.get_issues().unwrap().len(), 1);
+    }
+}
+
+#[test]
+fn close_requests_are_built_without_sending_them() {
+    for forge in [MockForge::github(), MockForge::gitlab()] {
+        forge.add_issue("First", "open", "");
+        let request = api(&forge).close_issue_request(1).unwrap();
+        assert!(
+            request.method == "PATCH" || request.method == "PUT",
+            "{}",
+            request.method
+        );
+        assert!(request.url.contains("/issues/1"), "{}", request.url);
+        assert!(forge.requests().is_empty());
+        assert_eq!(forge.issue(1).state, "open");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn tokens_are_resolved_once_per_host() {
+    let forge = MockForge::github();
+    let dir = std::env::temp_dir().join(format!("issuefer-test-token-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let calls = dir.join("calls");
+    let path = dir.join("config");
+    std::fs::write(
+        &path,
+        format!(
+            "[{}]\ntoken_command = echo call >> {} && echo {}\napi_url = {}\n",
+            forge.host(),
+            calls.display(),
+            TOKEN,
+            forge.api_url()
+        ),
+    )
+    .unwrap();
+    let config = config::Config::from_file(&path).unwrap();
+
+    for _ in 0..3 {
+        backend::get_api_for_host(
+            &config,
+            forge.host(),
+            forge.owner.clone(),
+            forge.repo.clone(),
+        )
+        .unwrap();
+    }
+    assert_eq!(std::fs::read_to_string(&calls).unwrap(), "call\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn gitlab_issues_are_sent_in_the_body() {
+    let forge = MockForge::gitlab();
+    forge.add_user("dev+ops");
+    let api = api(&forge);
+    let mut issue = new_issue("Fix a & b?", &["bug"], &["dev+ops"]);
+    issue.body = "Found in src/main.rs\n\n<!-- issuefer:x -->".to_string();
+    api.create_issue(&issue).unwrap();
+
+    let created = forge.issue(1);
+    assert_eq!(created.title, "Fix a & b?");
+    assert_eq!(created.body, issue.body);
+    assert_eq!(created.assignees, ["dev+ops"]);
+    let requests = forge.requests();
+    let create = requests.iter().find(|r| r.method == "POST").unwrap();
+    assert!(create.query.is_empty(), "{:?}", create.query);
+    assert_eq!(
+        create.header("Content-Type"),
+        Some("application/json"),
+        "{:?}",
+        create.headers
+    );
+}
@@ -0,0 +1,522 @@
+// The issuefer binary against the mock forge and temporary git repositories, see tests/common.
+mod common;
+
+use common::{MockForge, TestRepo};
+
+fn stdout(output: &std::process::Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+#[test]
+fn reported_todos_are_annotated_and_committed() {
+    let forge = MockForge::github();
+    let repo = TestRepo::new(&forge);
+    repo.write(
+        "src/main.rs",
+        "fn main() {\n    // TODO: Handle errors\n    run();\n}\n",
+    );
+    repo.commit_all("Initial commit");
+
+    let output = repo.issuefer(&["-r", "-y"]);
+    assert!(output.status.success());
+
+    let issues = forge.issues();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].title, "Handle errors");
+    assert!(
+        issues[0].body.contains("<!-- issuefer:"),
+        "{}",
+        issues[0].body
+    );
+    assert_eq!(
+        repo.read("src/main.rs"),
+        "fn main() {\n    // TODO (#1): Handle errors\n    run();\n}\n"
+    );
+    assert_eq!(repo.subjects()[0], "Add TODO #1");
+    assert_eq!(repo.git(&["status", "--porcelain"]), "");
+}
+
+#[test]
+fn todos_of_closed_issues_are_removed() {
+    let forge = MockForge::gitlab();
+    forge.add_issue("Handle errors", "closed", "");
+    forge.add_issue("Log more", "open", "");
+    let repo = TestRepo::new(&forge);
+    repo.write(
+        "src/main.rs",
+        "fn main() {\n    // TODO (#1): Handle errors\n    // TODO (#2): Log more\n    run();\n}\n",
+    );
+    repo.commit_all("Initial commit");
+
+    let output = repo.issuefer(&["-c", "-y"]);
+    assert!(output.status.success());
+
+    assert_eq!(
+        repo.read("src/main.rs"),
+        "fn main() {\n    // TODO (#2): Log more\n    run();\n}\n"
+    );
+    assert_eq!(repo.subjects()[0], "Remove TODO #1");
+    assert_eq!(forge.issues().len(), 2);
+}
+
+#[test]
+fn dry_runs_change_nothing() {
+    let forge = MockForge::github();
+    forge.add_issue("Handle errors", "closed", "");
+    let repo = TestRepo::new(&forge);
+    let content = "// TODO (#1): Handle errors\n// TODO: Log more\nfn main() {}\n";
+    repo.write("main.rs", content);
+    repo.commit_all("Initial commit");
+
+    let output = repo.issuefer(&["-r", "-c", "-y", "--dry-run"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Log more"));
+
+    assert_eq!(repo.read("main.rs"), content);
+    assert_eq!(repo.subjects(), ["Initial commit"]);
+    assert_eq!(forge.issues().len(), 1);
+    assert!(forge.requests().iter().all(|r| r.method == "GET"));
+}
+
+#[test]
+fn saved_plans_are_applied() {
+    let forge = MockForge::github();
+    forge.add_issue("Handle errors", "closed", "");
+    let repo = TestRepo::new(&forge);
+    repo.write(
+        "main.rs",
+        "// TODO (#1): Handle errors\n// TODO: Log more\nfn main() {}\n",
+    );
+    repo.commit_all("Initial commit");
+
+    let plan = repo.outside("plan.json");
+    let output = repo.issuefer(&["-r", "-c", "plan", plan.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(forge.issues().len(), 1);
+    assert_eq!(repo.subjects(), ["Initial commit"]);
+
+    // Lines added above the TODOs after planning do not matter
+    repo.write(
+        "main.rs",
+        "// Example\n// TODO (#1): Handle errors\n// TODO: Log more\nfn main() {}\n",
+    );
+    repo.commit_all("Add a comment");
+
+    let output = repo.issuefer(&["-y", "apply", plan.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    assert_eq!(forge.issue(2).title, "Log more");
+    assert_eq!(
+        repo.read("main.rs"),
+        "// Example\n// TODO (#2): Log more\nfn main() {}\n"
+    );
+    assert_eq!(
+        repo.subjects(),
+        [
+            "Remove TODO #1",
+            "Add TODO #2",
+            "Add a comment",
+            "Initial commit"
+        ]
+    );
+}
+
+#[test]
+fn runs_stop_when_issues_cannot_be_fetched() {
+    let forge = MockForge::github();
+    let repo = TestRepo::new(&forge);
+    let content = "// TODO: Handle errors\nfn main() {}\n";
+    repo.write("main.rs", content);
+    repo.commit_all("Initial commit");
+    forge.rate_limit(10, 3600);
+
+    let output = repo.issuefer(&["-r", "-y", "--no-cache"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("rate limit exceeded"));
+
+    assert_eq!(repo.read("main.rs"), content);
+    assert_eq!(repo.subjects(), ["Initial commit"]);
+    assert!(forge.issues().is_empty());
+}
+
+#[test]
+fn failed_creations_leave_the_todo_alone() {
+    let forge = MockForge::github();
+    let repo = TestRepo::new(&forge);
+    let content = "// TODO: Handle errors\nfn main() {}\n";
+    repo.write("main.rs", content);
+    repo.commit_all("Initial commit");
+
+    let plan = repo.outside("plan.json");
+    assert!(repo
+        .issuefer(&["-r", "plan", plan.to_str().unwrap()])
+        .status
+        .success());
+    let mut json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&plan).unwrap()).unwrap();
+    json["actions"][0]["assignees"] = serde_json::json!(["nobody"]);
+    std::fs::write(&plan, json.to_string()).unwrap();
+
+    let output = repo.issuefer(&["-y", "apply", plan.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Validation Failed"));
+
+    assert_eq!(repo.read("main.rs"), content);
+    assert_eq!(repo.subjects(), ["Initial commit"]);
+    assert!(forge.issues().is_empty());
+}
+
+#[cfg(feature = "tree-sitter")]
+#[test]
+fn only_real_comments_are_todos() {
+    let forge = MockForge::github();
+    let repo = TestRepo::new(&forge);
+    repo.write(
+        "main.py",
+        "HELP = \"\"\"\n# TODO: not a comment\n\"\"\"\nretries = 3  # TODO: Make it configurable\n",
+    );
+    repo.commit_all("Initial commit");
+
+    assert!(repo.issuefer(&["-r", "-y"]).status.success());
+    let issues = forge.issues();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].title, "Make it configurable");
+    assert_eq!(
+        repo.read("main.py"),
+        "HELP = \"\"\"\n# TODO: not a comment\n\"\"\"\nretries = 3  # TODO (#1): Make it configurable\n"
+    );
+
+    // Removing the TODO keeps the code in front of it
+    forge.close_issue(1);
+    assert!(repo.issuefer(&["-c", "-y"]).status.success());
+    assert_eq!(
+        repo.read("main.py"),
+        "HELP = \"\"\"\n# TODO: not a comment\n\"\"\"\nretries = 3\n"
+    );
+    assert_eq!(repo.subjects()[0], "Remove TODO #1");
+}
+
+#[test]
+fn runs_are_undone() {
+    let forge = MockForge::github();
+    let repo = TestRepo::new(&forge);
+    let content = "// TODO: Handle errors\n// TODO: Log more\nfn main() {}\n";
+    repo.write("main.rs", content);
+    repo.commit_all("Initial commit");
+    assert!(repo.issuefer(&["-r", "-y"]).status.success());
+    assert_eq!(repo.subjects().len(), 3);
+
+    // A dry run only prints what undo would do
+    let output = repo.issuefer(&["--dry-run", "undo"]);
+    assert!(output.status.success());
+    let printed = stdout(&output);
+    assert!(printed.contains("would reset 2 commits"), "{}", printed);
+    assert!(printed.contains("would close #2"), "{}", printed);
+    assert!(!printed.contains("test-token"));
+    assert_eq!(repo.subjects().len(), 3);
+    assert!(forge.issues().iter().all(|i| i.state == "open"));
+
+    let output = repo.issuefer(&["-y", "undo"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(repo.read("main.rs"), content);
+    assert_eq!(repo.subjects(), ["Initial commit"]);
+    assert!(forge.issues().iter().all(|i| i.state == "closed"));
+    assert!(!repo.issuefer(&["-y", "undo"]).status.success());
+}
+
+#[test]
+fn tracked_files_are_scanned_unless_issuefer_ignores_them() {
+    let forge = MockForge::github();
+    let repo = TestRepo::new(&forge);
+    repo.write(".gitignore", "generated/\n*.log\n");
+    repo.write("generated/api.rs", "// TODO: Tracked despite .gitignore\n");
+    repo.write("vendor/lib.rs", "// TODO: Vendored\n");
+    repo.write("vendor/.issueferignore", "*.rs\n!keep.rs\n");
+    repo.write("vendor/keep.rs", "// TODO: Kept\n");
+    repo.write(".issueferignore", "docs/\n");
+    repo.write("docs/notes.rs", "// TODO: In the docs\n");
+    repo.write(".ignore", "third_party/\n");
+    repo.write("third_party/dep.rs", "// TODO: Ignored by .ignore\n");
+    repo.commit_all("Initial commit");
+    repo.git(&["add", "--force", "generated/api.rs"]);
+    repo.git(&["commit", "--quiet", "-m", "Add generated code"]);
+    repo.write("new.rs", "// TODO: Not added yet\n");
+    repo.write("build.log", "// TODO: Ignored and untracked\n");
+
+    let output = repo.issuefer(&[]);
+    assert!(output.status.success());
+    let printed = stdout(&output);
+    assert!(
+        printed.contains("Tracked despite .gitignore"),
+        "{}",
+        printed
+    );
+    assert!(printed.contains("Kept"), "{}", printed);
+    for skipped in [
+        "Vendored",
+        "In the docs",
+        "Ignored by .ignore",
+        "Not added yet",
+        "Ignored and untracked",
+    ] {
+        assert!(!printed.contains(skipped), "{}", printed);
+    }
+
+    // Untracked files are only walked on request, and ignored ones never
+    let printed = stdout(&repo.issuefer(&["-u"]));
+    assert!(printed.contains("Not added yet"), "{}", printed);
+    assert!(!printed.contains("Ignored and untracked"), "{}", printed);
+    assert!(!printed.contains("Ignored by .ignore"), "{}", printed);
+}
+
+#[test]
+fn issues_of_interrupted_runs_are_referenced() {
+    let forge = MockForge::github();
+    let repo = TestRepo::new(&forge);
+    let content = "fn main() {\n    // TODO: Handle errors\n    run();\n}\n";
+    repo.write("src/main.rs", content);
+    repo.commit_all("Initial commit");
+
+    // The run died after creating the issue, but before annotating the TODO
+    forge.add_issue(
+        "Handle errors",
+        "open",
+        "Found in src/main.rs\n\n<!-- issuefer:github.com/owner/repo:17f-42 -->",
+    );
+    let pending = serde_json::json!([{
+        "id": "17f-42",
+        "host": forge.host(),
+        "owner": forge.owner,
+        "repo": forge.repo,
+        "target_repo": null,
+        "started_at": "2000-01-01T00:00:00Z",
+        "project": {"host": forge.host(), "owner": forge.owner, "repo": forge.repo},
+        "file_path": repo.dir.join("src/main.rs"),
+        "todo": {
+            "line_number": 1,
+            "keyword": "TODO",
+            "title": "Handle errors",
+            "issue_number": 0,
+            "issue_repo": null,
+            "line": "    // TODO: Handle errors",
+            "marker": {"start": 7, "end": 12}
+        }
+    }]);
+    repo.write(".git/issuefer/pending.json", &pending.to_string());
+
+    let output = repo.issuefer(&["-r", "-y"]);
+    assert!(output.status.success());
+    assert!(
+        stdout(&output).contains("was created by an interrupted run"),
+        "{}",
+        stdout(&output)
+    );
+
+    assert_eq!(forge.issues().len(), 1);
+    assert!(forge.requests().iter().all(|r| r.method == "GET"));
+    assert_eq!(
+        repo.read("src/main.rs"),
+        "fn main() {\n    // TODO (#1): Handle errors\n    run();\n}\n"
+    );
+    assert_eq!(repo.subjects()[0], "Add TODO #1");
+    assert!(!repo.dir.join(".git/issuefer/pending.json").exists());
+}
+
+#[test]
+fn issues_gone_from_the_forge_are_evicted_from_the_cache() {
+    let forge = MockForge::github();
+    forge.add_issue("Handle errors", "open", "");
+    let repo = TestRepo::new(&forge);
+    repo.write("main.rs", "fn main() {}\n");
+    repo.commit_all("Initial commit");
+    assert!(repo.issuefer(&[]).status.success());
+    let cache_path = ".git/issuefer/issues-github.com-owner-repo.json";
+    let mut cache: serde_json::Value = serde_json::from_str(&repo.read(cache_path)).unwrap();
+    assert_eq!(cache["issues"].as_object().unwrap().len(), 1);
+
+    // An issue that was deleted since, with the last full refresh two days ago
+    cache["issues"]["7"] = serde_json::json!({
+        "number": 7,
+        "title": "Deleted",
+        "state": "open",
+        "updated_at": "2020-01-01T00:00:01.500+00:00"
+    });
+    cache["refreshed_at"] = serde_json::json!(cache["refreshed_at"].as_u64().unwrap() - 2 * 86400);
+    repo.write(cache_path, &cache.to_string());
+
+    let output = repo.issuefer(&[]);
+    assert!(
+        stdout(&output).contains("Fetched all 1 issues"),
+        "{}",
+        stdout(&output)
+    );
+    let cache: serde_json::Value = serde_json::from_str(&repo.read(cache_path)).unwrap();
+    let numbers: Vec<&String> = cache["issues"].as_object().unwrap().keys().collect();
+    assert_eq!(numbers, ["1"]);
+    assert_eq!(cache["since"], "2020-01-01T00:00:01Z");
+}
+
+#[test]
+fn orphaned_issues_are_only_closed_after_complete_scans() {
+    let forge = MockForge::github();
+    let marker = |id| {
+        format!(
+            "Found in main.rs\n\n<!-- issuefer:github.com/owner/repo:{} -->",
+            id
+        )
+    };
+    forge.add_issue("Gone", "open", &marker("a"));
+    forge.add_issue("Kept", "open", &marker("b"));
+    let repo = TestRepo::new(&forge);
+    repo.write("main.rs", "// TODO (owner/repo#2): Kept\nfn main() {}\n");
+    repo.write("logo.png", "\u{0}PNG");
+    repo.commit_all("Initial commit");
+
+    // The skipped binary file might hold the TODO of the issue
+    let output = repo.issuefer(&["-c", "-y", "--close-orphaned"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("needs a complete scan"));
+    assert_eq!(forge.issue(1).state, "open");
+
+    repo.git(&["rm", "--quiet", "logo.png"]);
+    repo.git(&["commit", "--quiet", "-m", "Remove the logo"]);
+    // An untracked file might hold it as well
+    repo.write("notes.rs", "// TODO: Not added yet\n");
+    assert!(repo
+        .issuefer(&["-c", "-y", "--close-orphaned"])
+        .status
+        .success());
+    assert_eq!(forge.issue(1).state, "open");
+
+    std::fs::remove_file(repo.dir.join("notes.rs")).unwrap();
+    assert!(repo
+        .issuefer(&["-c", "-y", "--close-orphaned"])
+        .status
+        .success());
+    assert_eq!(forge.issue(1).state, "closed");
+    assert_eq!(forge.issue(2).state, "open");
+}
+
+#[test]
+fn remotes_are_found_from_subdirectories_and_worktrees() {
+    let forge = MockForge::github();
+    let repo = TestRepo::new(&forge);
+    repo.write("src/main.rs", "// TODO: Handle errors\nfn main() {}\n");
+    repo.commit_all("Initial commit");
+
+    let output = repo.issuefer_in(&repo.dir.join("src"), &["-r", "-y", "--dry-run"]);
+    assert!(output.status.success());
+    assert!(
+        stdout(&output).contains("Handle errors"),
+        "{}",
+        stdout(&output)
+    );
+
+    let worktree = repo.outside("worktree");
+    repo.git(&["worktree", "add", "--quiet", worktree.to_str().unwrap()]);
+    let output = repo.issuefer_in(&worktree, &["-r", "-y"]);
+    assert!(output.status.success());
+    assert_eq!(forge.issues().len(), 1);
+}
+
+#[test]
+fn patterns_match_paths_from_the_root_in_subdirectories() {
+    let forge = MockForge::github();
+    let repo = TestRepo::new(&forge);
+    repo.write("src/main.rs", "// TODO: Handle errors\nfn main() {}\n");
+    repo.write("src/generated/api.rs", "// TODO: Generated\n");
+    repo.commit_all("Initial commit");
+
+    let output = repo.issuefer_in(
+        &repo.dir.join("src"),
+        &["-r", "-y", "--exclude", "src/generated/**"],
+    );
+    assert!(output.status.success());
+
+    let titles: Vec<String> = forge.issues().into_iter().map(|i| i.title).collect();
+    assert_eq!(titles, ["Handle errors"]);
+    assert_eq!(repo.read("src/generated/api.rs"), "// TODO: Generated\n");
+}
+
+#[test]
+fn path_limited_runs_keep_the_cache_of_other_files() {
+    let forge = MockForge::github();
+    let repo = TestRepo::new(&forge);
+    repo.write("a.rs", "// TODO: First\n");
+    repo.write("b.rs", "// TODO: Second\n");
+    repo.commit_all("Initial commit");
+    let cached_files = || {
+        let cache: serde_json::Value =
+            serde_json::from_str(&repo.read(".git/issuefer/scan-cache.json")).unwrap();
+        cache["entries"].as_object().unwrap().len()
+    };
+
+    assert!(repo.issuefer(&["-r", "-y", "--dry-run"]).status.success());
+    assert_eq!(cached_files(), 2);
+    assert!(repo
+        .issuefer(&["-r", "-y", "--dry-run", "a.rs"])
+        .status
+        .success());
+    assert_eq!(cached_files(), 2);
+
+    // Old versions of the scanned files are dropped by full runs
+    repo.write("a.rs", "// TODO: Changed\n");
+    repo.commit_all("Change a.rs");
+    assert!(repo.issuefer(&["-r", "-y", "--dry-run"]).status.success());
+    assert_eq!(cached_files(), 2);
+}
+
+#[test]
+fn invalid_keywords_are_rejected() {
+    let forge = MockForge::github();
+    let repo = TestRepo::new(&forge);
+    repo.write("main.rs", "// TODO: Handle errors\n");
+    repo.commit_all("Initial commit");
+
+    for (keywords, error) in &[
+        ("[]", "keywords must not be empty"),
+        ("[\" \"]", "keywords must not be blank"),
+        ("[\"FIX.*\"]", "keyword 'FIX.*' contains '.'"),
+    ] {
+        repo.write(".issuefer.toml", &format!("keywords = {}\n", keywords));
+        let output = repo.issuefer(&["-r", "-y"]);
+        assert!(!output.status.success());
+        let output = format!(
+            "{}{}",
+            stdout(&output),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(output.contains(".issuefer.toml: "), "{}", output);
+        assert!(output.contains(error), "{}", output);
+    }
+    assert!(forge.issues().is_empty());
+
+    // Escaped characters are matched literally
+    repo.write(".issuefer.toml", "keywords = [\"C\\\\+\\\\+\"]\n");
+    repo.write("main.rs", "// C++: Handle errors\n");
+    assert!(repo.issuefer(&["-r", "-y"]).status.success());
+    assert_eq!(forge.issue(1).title, "Handle errors");
+}
+
+#[test]
+fn the_repository_config_is_used_in_subdirectories() {
+    let forge = MockForge::github();
+    let repo = TestRepo::new(&forge);
+    repo.write(
+        ".issuefer.toml",
+        "keywords = [\"FIXME\"]\n\n[[override]]\npath = \"src/docs/**\"\nkeywords = [\"NOTE\"]\n",
+    );
+    repo.write("src/main.rs", "// TODO: Ignored\n// FIXME: Handle errors\n");
+    repo.write(
+        "src/docs/guide.rs",
+        "// FIXME: Ignored too\n// NOTE: Explain more\n",
+    );
+    repo.commit_all("Initial commit");
+
+    let output = repo.issuefer_in(&repo.dir.join("src"), &["-r", "-y"]);
+    assert!(output.status.success());
+
+    let mut titles: Vec<String> = forge.issues().into_iter().map(|i| i.title).collect();
+    titles.sort();
+    assert_eq!(titles, ["Explain more", "Handle errors"]);
+}
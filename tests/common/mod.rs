@@ -0,0 +1,934 @@
+// Helpers shared by the integration tests: a mock GitHub/GitLab server that answers like the
+// real APIs (see tests/fixtures for recorded answers) and temporary git repositories.
+#![allow(dead_code)]
+
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const TOKEN: &str = "test-token";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    GitHub,
+    GitLab,
+}
+
+#[derive(Clone, Debug)]
+pub struct MockIssue {
+    pub number: u64,
+    pub title: String,
+    pub body: String,
+    // "open" or "closed" on both forges, GitLab answers "opened" for open issues
+    pub state: String,
+    pub labels: Vec<String>,
+    pub assignees: Vec<String>,
+    pub pull_request: bool,
+    pub updated_at: String,
+}
+
+// Recorded is a request as the server received it.
+#[derive(Clone, Debug)]
+pub struct Recorded {
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Recorded {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Answer {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Answer {
+    fn json(status: u16, body: Value) -> Answer {
+        Answer {
+            status,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.to_string(),
+        }
+    }
+
+    fn header(mut self, name: &str, value: impl ToString) -> Answer {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+struct State {
+    issues: Vec<MockIssue>,
+    users: Vec<String>,
+    page_size: usize,
+    rate_limited: usize,
+    rate_limit_wait: u64,
+    failures: VecDeque<(u16, Value)>,
+    requests: Vec<Recorded>,
+    // Bumped on every change, the ETag of the issue list
+    revision: u64,
+    clock: u64,
+}
+
+impl State {
+    fn tick(&mut self) -> String {
+        self.clock += 1;
+        self.revision += 1;
+        format!(
+            "2020-01-01T{:02}:{:02}:{:02}Z",
+            self.clock / 3600,
+            self.clock % 3600 / 60,
+            self.clock % 60
+        )
+    }
+}
+
+// MockForge is a GitHub or GitLab server on a local port, holding the issues of one project.
+// It is shut down with the test process.
+pub struct MockForge {
+    pub kind: Kind,
+    pub owner: String,
+    pub repo: String,
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockForge {
+    pub fn github() -> MockForge {
+        MockForge::start(Kind::GitHub, "owner", "repo")
+    }
+
+    pub fn gitlab() -> MockForge {
+        MockForge::start(Kind::GitLab, "group", "project")
+    }
+
+    fn start(kind: Kind, owner: &str, repo: &str) -> MockForge {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock server");
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State {
+            issues: Vec::new(),
+            users: vec![match kind {
+                Kind::GitHub => "octocat".to_string(),
+                Kind::GitLab => "john_smith".to_string(),
+            }],
+            page_size: 100,
+            rate_limited: 0,
+            rate_limit_wait: 0,
+            failures: VecDeque::new(),
+            requests: Vec::new(),
+            revision: 0,
+            clock: 0,
+        }));
+        let forge = MockForge {
+            kind,
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            addr,
+            state: state.clone(),
+        };
+        let server = Server {
+            kind,
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            base: format!("http://{}", addr),
+            state,
+        };
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = server.handle(stream);
+            }
+        });
+        forge
+    }
+
+    pub fn host(&self) -> &str {
+        match self.kind {
+            Kind::GitHub => "github.com",
+            Kind::GitLab => "gitlab.example.com",
+        }
+    }
+
+    pub fn api_url(&self) -> String {
+        match self.kind {
+            Kind::GitHub => format!("http://{}", self.addr),
+            Kind::GitLab => format!("http://{}/api/v4", self.addr),
+        }
+    }
+
+    pub fn remote_url(&self) -> String {
+        format!("https://{}/{}/{}.git", self.host(), self.owner, self.repo)
+    }
+
+    pub fn add_issue(&self, title: &str, state: &str, body: &str) -> u64 {
+        self.insert(title, state, body, false)
+    }
+
+    pub fn add_pull_request(&self, title: &str) -> u64 {
+        self.insert(title, "open", "", true)
+    }
+
+    fn insert(&self, title: &str, state: &str, body: &str, pull_request: bool) -> u64 {
+        let mut s = self.state.lock().unwrap();
+        let number = s.issues.len() as u64 + 1;
+        let updated_at = s.tick();
+        s.issues.push(MockIssue {
+            number,
+            title: title.to_string(),
+            body: body.to_string(),
+            state: state.to_string(),
+            labels: Vec::new(),
+            assignees: Vec::new(),
+            pull_request,
+            updated_at,
+        });
+        number
+    }
+
+    pub fn close_issue(&self, number: u64) {
+        let mut s = self.state.lock().unwrap();
+        let updated_at = s.tick();
+        let issue = s
+            .issues
+            .iter_mut()
+            .find(|issue| issue.number == number)
+            .expect("no such issue");
+        issue.state = "closed".to_string();
+        issue.updated_at = updated_at;
+    }
+
+    pub fn add_user(&self, name: &str) {
+        self.state.lock().unwrap().users.push(name.to_string());
+    }
+
+    pub fn set_page_size(&self, page_size: usize) {
+        self.state.lock().unwrap().page_size = page_size;
+    }
+
+    // rate_limit answers the next count requests as rate limited, until the limit resets in
+    // secs seconds.
+    pub fn rate_limit(&self, count: usize, secs: u64) {
+        let mut s = self.state.lock().unwrap();
+        s.rate_limited = count;
+        s.rate_limit_wait = secs;
+    }
+
+    // fail_next answers the next request with the status and a fixture of this forge.
+    pub fn fail_next(&self, status: u16, fixture_name: &str) {
+        let body = fixture(self.kind, fixture_name);
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .push_back((status, body));
+    }
+
+    pub fn issues(&self) -> Vec<MockIssue> {
+        self.state
+            .lock()
+            .unwrap()
+            .issues
+            .iter()
+            .filter(|issue| !issue.pull_request)
+            .cloned()
+            .collect()
+    }
+
+    pub fn issue(&self, number: u64) -> MockIssue {
+        self.issues()
+            .into_iter()
+            .find(|issue| issue.number == number)
+            .expect("no such issue")
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+// fixture reads a recorded answer from tests/fixtures.
+pub fn fixture(kind: Kind, name: &str) -> Value {
+    let dir = match kind {
+        Kind::GitHub => "github",
+        Kind::GitLab => "gitlab",
+    };
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(dir)
+        .join(format!("{}.json", name));
+    let content = fs::read_to_string(&path).expect("read fixture");
+    serde_json::from_str(&content).expect("parse fixture")
+}
+
+struct Server {
+    kind: Kind,
+    owner: String,
+    repo: String,
+    base: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl Server {
+    fn handle(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or("").to_string();
+        let target = parts.next().unwrap_or("").to_string();
+        let mut headers = Vec::new();
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header)?;
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        let length = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+            .and_then(|(_, value)| value.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_string(), parse_query(query)),
+            None => (target.clone(), Vec::new()),
+        };
+        let request = Recorded {
+            method,
+            path,
+            query,
+            headers,
+            body,
+        };
+        let answer = self.answer(request);
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            answer.status,
+            reason(answer.status),
+            answer.body.len()
+        );
+        for (name, value) in &answer.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(answer.body.as_bytes())?;
+        stream.flush()
+    }
+
+    fn answer(&self, request: Recorded) -> Answer {
+        let mut s = self.state.lock().unwrap();
+        s.requests.push(request.clone());
+        if s.rate_limited > 0 {
+            s.rate_limited -= 1;
+            return self.rate_limited(s.rate_limit_wait);
+        }
+        if let Some((status, body)) = s.failures.pop_front() {
+            return Answer::json(status, body);
+        }
+        match self.kind {
+            Kind::GitHub => self.github(&mut s, &request),
+            Kind::GitLab => self.gitlab(&mut s, &request),
+        }
+    }
+
+    fn rate_limited(&self, wait: u64) -> Answer {
+        let reset = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + wait;
+        match self.kind {
+            // GitHub sends 403 with the time the limit resets
+            Kind::GitHub => Answer::json(403, fixture(Kind::GitHub, "rate_limited"))
+                .header("X-RateLimit-Limit", 5000)
+                .header("X-RateLimit-Remaining", 0)
+                .header("X-RateLimit-Reset", reset),
+            Kind::GitLab => Answer {
+                status: 429,
+                headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
+                body: "Retry later\n".to_string(),
+            }
+            .header("Retry-After", wait)
+            .header("RateLimit-Remaining", 0)
+            .header("RateLimit-Reset", reset),
+        }
+    }
+
+    fn github(&self, s: &mut State, request: &Recorded) -> Answer {
+        if request.header("Authorization") != Some(&format!("token {}", TOKEN)) {
+            return Answer::json(401, fixture(Kind::GitHub, "bad_credentials"));
+        }
+        let not_found = Answer::json(404, fixture(Kind::GitHub, "not_found"));
+        let segments: Vec<&str> = request.path.trim_start_matches('/').split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["user"]) => Answer::json(200, fixture(Kind::GitHub, "user"))
+                .header("X-OAuth-Scopes", "repo, read:org"),
+            ("POST", ["graphql"]) => self.github_graphql(s, request),
+            (method, ["repos", owner, repo, rest @ ..])
+                if *owner == self.owner && *repo == self.repo =>
+            {
+                match (method, rest) {
+                    ("GET", ["issues"]) => self.github_list(s, request),
+                    ("POST", ["issues"]) => self.github_create(s, request),
+                    (method, ["issues", number]) => {
+                        let index = match find(s, number) {
+                            Some(index) => index,
+                            None => return not_found,
+                        };
+                        if method == "PATCH" {
+                            let change: Value =
+                                serde_json::from_slice(&request.body).unwrap_or(Value::Null);
+                            if let Some(state) = change["state"].as_str() {
+                                s.issues[index].state = state.to_string();
+                                s.issues[index].updated_at = s.tick();
+                            }
+                        }
+                        Answer::json(200, self.github_issue(&s.issues[index]))
+                    }
+                    _ => not_found,
+                }
+            }
+            _ => not_found,
+        }
+    }
+
+    fn github_list(&self, s: &mut State, request: &Recorded) -> Answer {
+        let etag = format!("W/\"{}\"", s.revision);
+        if request.header("If-None-Match") == Some(&etag) {
+            return Answer::json(304, Value::Null);
+        }
+        let state = request.param("state").unwrap_or("open");
+        let since = request.param("since");
+        let issues: Vec<&MockIssue> = s
+            .issues
+            .iter()
+            .filter(|issue| state == "all" || issue.state == state)
+            .filter(|issue| since.is_none_or(|since| issue.updated_at.as_str() >= since))
+            .collect();
+        let per_page = request
+            .param("per_page")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(30usize)
+            .min(s.page_size);
+        let page: usize = request
+            .param("page")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1);
+        let last = issues.len().div_ceil(per_page).max(1);
+        let body: Vec<Value> = issues
+            .iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .map(|issue| self.github_issue(issue))
+            .collect();
+        let mut answer = Answer::json(200, Value::from(body)).header("ETag", etag);
+        if page < last {
+            answer = answer.header(
+                "Link",
+                format!(
+                    "<{}>; rel=\"next\", <{}>; rel=\"last\"",
+                    self.page_url(request, page + 1),
+                    self.page_url(request, last)
+                ),
+            );
+        }
+        answer
+    }
+
+    fn page_url(&self, request: &Recorded, page: usize) -> String {
+        let mut query: Vec<String> = request
+            .query
+            .iter()
+            .filter(|(key, _)| key != "page")
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        query.push(format!("page={}", page));
+        format!("{}{}?{}", self.base, request.path, query.join("&"))
+    }
+
+    fn github_create(&self, s: &mut State, request: &Recorded) -> Answer {
+        let new: Value = serde_json::from_slice(&request.body).unwrap_or(Value::Null);
+        let title = new["title"].as_str().unwrap_or("");
+        if title.is_empty() {
+            return Answer::json(
+                422,
+                json!({
+                    "message": "Validation Failed",
+                    "errors": [{"resource": "Issue", "code": "missing_field", "field": "title"}],
+                }),
+            );
+        }
+        let strings = |value: &Value| -> Vec<String> {
+            value
+                .as_array()
+                .map(|values| {
+                    values
+                        .iter()
+                        .filter_map(|v| v.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let assignees = strings(&new["assignees"]);
+        if let Some(unknown) = assignees.iter().find(|a| !s.users.contains(a)) {
+            let mut body = fixture(Kind::GitHub, "validation_failed");
+            body["errors"][0]["value"] = json!(unknown);
+            return Answer::json(422, body);
+        }
+        let updated_at = s.tick();
+        let issue = MockIssue {
+            number: s.issues.len() as u64 + 1,
+            title: title.to_string(),
+            body: new["body"].as_str().unwrap_or("").to_string(),
+            state: "open".to_string(),
+            labels: strings(&new["labels"]),
+            assignees,
+            pull_request: false,
+            updated_at,
+        };
+        let answer = Answer::json(201, self.github_issue(&issue));
+        s.issues.push(issue);
+        answer
+    }
+
+    fn github_graphql(&self, s: &mut State, request: &Recorded) -> Answer {
+        let query: Value = serde_json::from_slice(&request.body).unwrap_or(Value::Null);
+        let variables = &query["variables"];
+        if query["query"]
+            .as_str()
+            .unwrap_or("")
+            .contains("deleteIssue")
+        {
+            let id = variables["id"].as_str().unwrap_or("");
+            s.issues
+                .retain(|issue| format!("I_{}", issue.number) != id || issue.pull_request);
+            s.revision += 1;
+            return Answer::json(
+                200,
+                json!({"data": {"deleteIssue": {"clientMutationId": null}}}),
+            );
+        }
+        let number = variables["number"].as_u64().unwrap_or(0);
+        let issue = s
+            .issues
+            .iter()
+            .find(|issue| issue.number == number && !issue.pull_request)
+            .map(|issue| json!({"id": format!("I_{}", issue.number)}));
+        Answer::json(200, json!({"data": {"repository": {"issue": issue}}}))
+    }
+
+    fn github_issue(&self, issue: &MockIssue) -> Value {
+        let mut value = fixture(Kind::GitHub, "issue");
+        let url = format!(
+            "{}/repos/{}/{}/issues/{}",
+            self.base, self.owner, self.repo, issue.number
+        );
+        value["url"] = json!(url);
+        value["html_url"] = json!(format!(
+            "https://github.com/{}/{}/issues/{}",
+            self.owner, self.repo, issue.number
+        ));
+        value["id"] = json!(issue.number);
+        value["number"] = json!(issue.number);
+        value["title"] = json!(issue.title);
+        value["body"] = json!(issue.body);
+        value["state"] = json!(issue.state);
+        value["updated_at"] = json!(issue.updated_at);
+        value["labels"] = issue.labels.iter().map(|l| json!({ "name": l })).collect();
+        value["assignees"] = issue
+            .assignees
+            .iter()
+            .map(|a| json!({ "login": a }))
+            .collect();
+        if issue.pull_request {
+            value["pull_request"] = json!({ "url": url.replace("/issues/", "/pulls/") });
+        }
+        value
+    }
+
+    fn gitlab(&self, s: &mut State, request: &Recorded) -> Answer {
+        if request.header("PRIVATE-TOKEN") != Some(TOKEN) {
+            return Answer::json(401, fixture(Kind::GitLab, "unauthorized"));
+        }
+        let not_found = Answer::json(404, fixture(Kind::GitLab, "not_found"));
+        let path = match request.path.strip_prefix("/api/v4/") {
+            Some(path) => path,
+            None => return not_found,
+        };
+        let project = format!("{}%2F{}", self.owner.replace('/', "%2F"), self.repo);
+        let segments: Vec<&str> = path.split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["user"]) => {
+                let mut user = fixture(Kind::GitLab, "user");
+                user["username"] = json!(s.users[0]);
+                Answer::json(200, user)
+            }
+            ("GET", ["personal_access_tokens", "self"]) => {
+                Answer::json(200, fixture(Kind::GitLab, "personal_access_token"))
+            }
+            ("GET", ["users"]) => {
+                let name = request.param("username").unwrap_or("");
+                let users: Vec<Value> = s
+                    .users
+                    .iter()
+                    .position(|user| user == name)
+                    .map(|index| json!({"id": index + 1, "username": name}))
+                    .into_iter()
+                    .collect();
+                Answer::json(200, Value::from(users))
+            }
+            (method, ["projects", id, rest @ ..]) if *id == project => match (method, rest) {
+                ("GET", ["issues"]) => self.gitlab_list(s, request),
+                ("POST", ["issues"]) => self.gitlab_create(s, request),
+                ("GET", ["merge_requests", number]) => {
+                    match find(s, number).filter(|index| s.issues[*index].pull_request) {
+                        Some(index) => Answer::json(200, self.gitlab_issue(&s.issues[index])),
+                        None => not_found,
+                    }
+                }
+                (method, ["issues", number]) => {
+                    // Merge requests are numbered on their own on GitLab
+                    let index = match find(s, number).filter(|i| !s.issues[*i].pull_request) {
+                        Some(index) => index,
+                        None => return not_found,
+                    };
+                    match method {
+                        "DELETE" => {
+                            s.issues.remove(index);
+                            s.revision += 1;
+                            return Answer::json(204, Value::Null);
+                        }
+                        "PUT" => {
+                            let edit: Value =
+                                serde_json::from_slice(&request.body).unwrap_or(Value::Null);
+                            let state = match edit["state_event"].as_str() {
+                                Some("close") => Some("closed"),
+                                Some("reopen") => Some("open"),
+                                _ => None,
+                            };
+                            if let Some(state) = state {
+                                s.issues[index].state = state.to_string();
+                                s.issues[index].updated_at = s.tick();
+                            }
+                        }
+                        _ => {}
+                    }
+                    Answer::json(200, self.gitlab_issue(&s.issues[index]))
+                }
+                _ => not_found,
+            },
+            _ => not_found,
+        }
+    }
+
+    fn gitlab_list(&self, s: &mut State, request: &Recorded) -> Answer {
+        let etag = format!("W/\"{}\"", s.revision);
+        if request.header("If-None-Match") == Some(&etag) {
+            return Answer::json(304, Value::Null);
+        }
+        let since = request.param("updated_after");
+        let issues: Vec<&MockIssue> = s
+            .issues
+            .iter()
+            .filter(|issue| !issue.pull_request)
+            .filter(|issue| since.is_none_or(|since| issue.updated_at.as_str() >= since))
+            .collect();
+        let per_page = request
+            .param("per_page")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(20usize)
+            .min(s.page_size);
+        let page: usize = request
+            .param("page")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1);
+        let pages = issues.len().div_ceil(per_page).max(1);
+        let body: Vec<Value> = issues
+            .iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .map(|issue| self.gitlab_issue(issue))
+            .collect();
+        let next = if page < pages {
+            (page + 1).to_string()
+        } else {
+            String::new()
+        };
+        Answer::json(200, Value::from(body))
+            .header("ETag", etag)
+            .header("X-Page", page)
+            .header("X-Per-Page", per_page)
+            .header("X-Total", issues.len())
+            .header("X-Total-Pages", pages)
+            .header("X-Next-Page", next)
+    }
+
+    fn gitlab_create(&self, s: &mut State, request: &Recorded) -> Answer {
+        let new: Value = serde_json::from_slice(&request.body).unwrap_or(Value::Null);
+        let title = new["title"].as_str().unwrap_or("");
+        if title.is_empty() {
+            return Answer::json(400, fixture(Kind::GitLab, "title_missing"));
+        }
+        let mut assignees = Vec::new();
+        for id in new["assignee_ids"].as_array().into_iter().flatten() {
+            match id.as_u64().and_then(|id| s.users.get(id as usize - 1)) {
+                Some(user) => assignees.push(user.clone()),
+                None => return Answer::json(404, fixture(Kind::GitLab, "not_found")),
+            }
+        }
+        let updated_at = s.tick();
+        let issue = MockIssue {
+            number: s.issues.len() as u64 + 1,
+            title: title.to_string(),
+            body: new["description"].as_str().unwrap_or("").to_string(),
+            state: "open".to_string(),
+            labels: new["labels"]
+                .as_str()
+                .unwrap_or("")
+                .split(',')
+                .filter(|label| !label.is_empty())
+                .map(str::to_string)
+                .collect(),
+            assignees,
+            pull_request: false,
+            updated_at,
+        };
+        let answer = Answer::json(201, self.gitlab_issue(&issue));
+        s.issues.push(issue);
+        answer
+    }
+
+    fn gitlab_issue(&self, issue: &MockIssue) -> Value {
+        let mut value = fixture(Kind::GitLab, "issue");
+        value["id"] = json!(issue.number);
+        value["iid"] = json!(issue.number);
+        value["title"] = json!(issue.title);
+        value["description"] = json!(issue.body);
+        value["state"] = json!(if issue.state == "open" {
+            "opened"
+        } else {
+            issue.state.as_str()
+        });
+        value["updated_at"] = json!(issue.updated_at);
+        value["labels"] = json!(issue.labels);
+        value["assignees"] = issue
+            .assignees
+            .iter()
+            .map(|a| json!({ "username": a }))
+            .collect();
+        value["web_url"] = json!(format!(
+            "https://gitlab.example.com/{}/{}/-/issues/{}",
+            self.owner, self.repo, issue.number
+        ));
+        value
+    }
+}
+
+fn find(s: &State, number: &str) -> Option<usize> {
+    let number: u64 = number.parse().ok()?;
+    s.issues.iter().position(|issue| issue.number == number)
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        422 => "Unprocessable Entity",
+        429 => "Too Many Requests",
+        _ => "Unknown",
+    }
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (decode(key), decode(value)),
+            None => (decode(pair), String::new()),
+        })
+        .collect()
+}
+
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+// TestRepo is a git repository in a temporary directory with the remote of a mock forge, it is
+// removed when dropped.
+pub struct TestRepo {
+    root: PathBuf,
+    pub dir: PathBuf,
+    config: PathBuf,
+}
+
+impl TestRepo {
+    pub fn new(forge: &MockForge) -> TestRepo {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "issuefer-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&root);
+        let dir = root.join("repo");
+        fs::create_dir_all(&dir).unwrap();
+        let config = root.join("issuefer.ini");
+        fs::write(
+            &config,
+            format!(
+                "[{}]\ntoken = {}\napi_url = {}\n",
+                forge.host(),
+                TOKEN,
+                forge.api_url()
+            ),
+        )
+        .unwrap();
+        let repo = TestRepo { root, dir, config };
+        repo.git(&["init", "-q"]);
+        repo.git(&["config", "user.name", "Test"]);
+        repo.git(&["config", "user.email", "test@example.com"]);
+        repo.git(&["config", "commit.gpgsign", "false"]);
+        repo.git(&["remote", "add", "origin", &forge.remote_url()]);
+        repo
+    }
+
+    pub fn git(&self, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(&self.dir)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("HOME", &self.root)
+            .output()
+            .expect("run git");
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).to_string()
+    }
+
+    pub fn write(&self, path: &str, content: &str) {
+        let path = self.dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    pub fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.dir.join(path)).unwrap()
+    }
+
+    // outside returns a path next to the repository, e.g., for a saved plan.
+    pub fn outside(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    pub fn commit_all(&self, message: &str) {
+        self.git(&["add", "-A"]);
+        self.git(&["commit", "-q", "-m", message]);
+    }
+
+    // subjects returns the commit subjects, the newest first.
+    pub fn subjects(&self) -> Vec<String> {
+        self.git(&["log", "--format=%s"])
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    // issuefer runs the binary in the repository with the config of the mock forge.
+    #[cfg(feature = "cli")]
+    pub fn issuefer(&self, args: &[&str]) -> std::process::Output {
+        self.issuefer_in(&self.dir, args)
+    }
+
+    // issuefer_in runs the binary like issuefer, but in another directory, e.g., a
+    // subdirectory of the repository.
+    #[cfg(feature = "cli")]
+    pub fn issuefer_in(&self, dir: &Path, args: &[&str]) -> std::process::Output {
+        let output = Command::new(env!("CARGO_BIN_EXE_issuefer"))
+            .arg("--config")
+            .arg(&self.config)
+            .args(args)
+            .current_dir(dir)
+            .env("HOME", &self.root)
+            .env("XDG_CONFIG_HOME", &self.root)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env_remove("GITHUB_TOKEN")
+            .env_remove("GITLAB_TOKEN")
+            .env_remove("EDITOR")
+            .env_remove("VISUAL")
+            .stdin(std::process::Stdio::null())
+            .output()
+            .expect("run issuefer");
+        println!("{}", String::from_utf8_lossy(&output.stdout));
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+        output
+    }
+}
+
+impl Drop for TestRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}